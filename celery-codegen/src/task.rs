@@ -28,7 +28,11 @@ enum TaskAttr {
     ContentType(syn::Ident),
     RetryForUnexpected(syn::LitBool),
     AcksLate(syn::LitBool),
+    IgnoreResult(syn::LitBool),
+    TrackStarted(syn::LitBool),
+    RateLimit(f64, u32),
     Bind(syn::LitBool),
+    Blocking(syn::LitBool),
     OnFailure(syn::Ident),
     OnSuccess(syn::Ident),
 }
@@ -47,6 +51,9 @@ struct Task {
     max_retry_delay: Option<syn::LitInt>,
     retry_for_unexpected: Option<syn::LitBool>,
     acks_late: Option<syn::LitBool>,
+    ignore_result: Option<syn::LitBool>,
+    track_started: Option<syn::LitBool>,
+    rate_limit: Option<(f64, u32)>,
     content_type: Option<syn::Ident>,
     original_args: Vec<syn::FnArg>,
     inputs: Option<Punctuated<FnArg, Comma>>,
@@ -54,6 +61,7 @@ struct Task {
     return_type: Option<syn::Type>,
     is_async: bool,
     bind: bool,
+    blocking: bool,
     on_failure: Option<syn::Ident>,
     on_success: Option<syn::Ident>,
 }
@@ -159,6 +167,36 @@ impl TaskAttrs {
             .next()
     }
 
+    fn ignore_result(&self) -> Option<syn::LitBool> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                TaskAttr::IgnoreResult(r) => Some(r.clone()),
+                _ => None,
+            })
+            .next()
+    }
+
+    fn track_started(&self) -> Option<syn::LitBool> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                TaskAttr::TrackStarted(r) => Some(r.clone()),
+                _ => None,
+            })
+            .next()
+    }
+
+    fn rate_limit(&self) -> Option<(f64, u32)> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                TaskAttr::RateLimit(tasks, period) => Some((*tasks, *period)),
+                _ => None,
+            })
+            .next()
+    }
+
     fn content_type(&self) -> Option<syn::Ident> {
         self.attrs
             .iter()
@@ -179,6 +217,16 @@ impl TaskAttrs {
             .next()
     }
 
+    fn blocking(&self) -> Option<syn::LitBool> {
+        self.attrs
+            .iter()
+            .filter_map(|a| match a {
+                TaskAttr::Blocking(r) => Some(r.clone()),
+                _ => None,
+            })
+            .next()
+    }
+
     fn on_failure(&self) -> Option<syn::Ident> {
         self.attrs
             .iter()
@@ -220,8 +268,12 @@ mod kw {
     syn::custom_keyword!(max_retry_delay);
     syn::custom_keyword!(retry_for_unexpected);
     syn::custom_keyword!(acks_late);
+    syn::custom_keyword!(ignore_result);
+    syn::custom_keyword!(track_started);
+    syn::custom_keyword!(rate_limit);
     syn::custom_keyword!(content_type);
     syn::custom_keyword!(bind);
+    syn::custom_keyword!(blocking);
     syn::custom_keyword!(on_failure);
     syn::custom_keyword!(on_success);
 }
@@ -269,6 +321,25 @@ impl parse::Parse for TaskAttr {
             input.parse::<kw::acks_late>()?;
             input.parse::<Token![=]>()?;
             Ok(TaskAttr::AcksLate(input.parse()?))
+        } else if lookahead.peek(kw::ignore_result) {
+            input.parse::<kw::ignore_result>()?;
+            input.parse::<Token![=]>()?;
+            Ok(TaskAttr::IgnoreResult(input.parse()?))
+        } else if lookahead.peek(kw::track_started) {
+            input.parse::<kw::track_started>()?;
+            input.parse::<Token![=]>()?;
+            Ok(TaskAttr::TrackStarted(input.parse()?))
+        } else if lookahead.peek(kw::rate_limit) {
+            input.parse::<kw::rate_limit>()?;
+            input.parse::<Token![=]>()?;
+            let rate_limit: syn::LitStr = input.parse()?;
+            let (tasks, period) = parse_rate_limit(&rate_limit.value()).ok_or_else(|| {
+                parse::Error::new(
+                    rate_limit.span(),
+                    "invalid rate limit, expected e.g. \"10/s\", \"100/m\" or \"1000/h\"",
+                )
+            })?;
+            Ok(TaskAttr::RateLimit(tasks, period))
         } else if lookahead.peek(kw::content_type) {
             input.parse::<kw::content_type>()?;
             input.parse::<Token![=]>()?;
@@ -277,6 +348,10 @@ impl parse::Parse for TaskAttr {
             input.parse::<kw::bind>()?;
             input.parse::<Token![=]>()?;
             Ok(TaskAttr::Bind(input.parse()?))
+        } else if lookahead.peek(kw::blocking) {
+            input.parse::<kw::blocking>()?;
+            input.parse::<Token![=]>()?;
+            Ok(TaskAttr::Blocking(input.parse()?))
         } else if lookahead.peek(kw::on_failure) {
             input.parse::<kw::on_failure>()?;
             input.parse::<Token![=]>()?;
@@ -291,6 +366,22 @@ impl parse::Parse for TaskAttr {
     }
 }
 
+/// Parse a rate limit written like Python's, into a number of tasks and a period in seconds.
+/// This is the same syntax as `celery::task::RateLimit` parses at runtime.
+fn parse_rate_limit(rate_limit: &str) -> Option<(f64, u32)> {
+    let (tasks, modifier) = rate_limit.split_once('/').unwrap_or((rate_limit, "s"));
+    let period = match modifier {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    match tasks.trim().parse::<f64>() {
+        Ok(tasks) if tasks.is_finite() && tasks >= 0.0 => Some((tasks, period)),
+        _ => None,
+    }
+}
+
 impl Task {
     fn new(attrs: TaskAttrs) -> Self {
         Task {
@@ -306,6 +397,9 @@ impl Task {
             max_retry_delay: attrs.max_retry_delay(),
             retry_for_unexpected: attrs.retry_for_unexpected(),
             acks_late: attrs.acks_late(),
+            ignore_result: attrs.ignore_result(),
+            track_started: attrs.track_started(),
+            rate_limit: attrs.rate_limit(),
             content_type: attrs.content_type(),
             original_args: Vec::new(),
             inputs: None,
@@ -316,6 +410,10 @@ impl Task {
                 .bind()
                 .map(|lit_bool| lit_bool.value)
                 .unwrap_or_default(),
+            blocking: attrs
+                .blocking()
+                .map(|lit_bool| lit_bool.value)
+                .unwrap_or_default(),
             on_failure: attrs.on_failure(),
             on_success: attrs.on_success(),
         }
@@ -328,6 +426,7 @@ impl VisitMut for Task {
         const ERR_VARIADIC: &str = "functions with variadic arguments are not supported";
         const ERR_MISSING_SELF: &str = "bound task should have &self as an argument";
         const ERR_ABI: &str = "functions with non-Rust ABI are not supported";
+        const ERR_BLOCKING_ASYNC: &str = "blocking tasks can't be async";
 
         if let Some(ref mut it) = node.sig.abi {
             self.errors.push(Error::spanned(ERR_ABI, it.span()));
@@ -347,6 +446,12 @@ impl VisitMut for Task {
         self.visibility = node.vis.clone();
         self.inner_block = Some((*node.block).clone());
         self.is_async = node.sig.asyncness.is_some();
+        if self.blocking {
+            if let Some(ref asyncness) = node.sig.asyncness {
+                self.errors
+                    .push(Error::spanned(ERR_BLOCKING_ASYNC, asyncness.span()));
+            }
+        }
         self.inputs = Some(node.sig.inputs.clone());
 
         if self.wrapper.is_none() {
@@ -359,7 +464,7 @@ impl VisitMut for Task {
 
         if self.params_type.is_none() {
             self.params_type = Some(syn::Ident::new(
-                &format!("{}Params", ident.to_string())[..],
+                &format!("{}Params", ident)[..],
                 Span::call_site(),
             ));
         }
@@ -486,6 +591,59 @@ fn args_to_typed_inputs<'a>(
         })
 }
 
+fn args_to_partial_params<'a>(
+    args: impl IntoIterator<Item = &'a syn::FnArg>,
+    export: &TokenStream,
+) -> TokenStream {
+    args.into_iter()
+        .fold(TokenStream::new(), |acc, arg| match arg {
+            syn::FnArg::Typed(cap) => match *cap.pat {
+                syn::Pat::Ident(ref pat) => {
+                    let ident = &pat.ident;
+                    let name = ident.to_string();
+                    quote! {
+                        #acc
+                        params.insert(
+                            #name.into(),
+                            #export::to_value(#ident).expect("invalid task parameter"),
+                        );
+                    }
+                }
+                _ => acc,
+            },
+            _ => acc,
+        })
+}
+
+/// Get the tuple type of the parameters, a pattern that destructures such a tuple, and the
+/// serialized parameters bound by that pattern.
+fn args_to_tuple<'a>(
+    args: impl IntoIterator<Item = &'a syn::FnArg>,
+    export: &TokenStream,
+) -> (TokenStream, TokenStream, TokenStream) {
+    args.into_iter().fold(
+        (TokenStream::new(), TokenStream::new(), TokenStream::new()),
+        |(types, pattern, values), arg| match arg {
+            syn::FnArg::Typed(cap) => match *cap.pat {
+                syn::Pat::Ident(ref pat) => {
+                    let ident = &pat.ident;
+                    let ty = &cap.ty;
+                    (
+                        quote! { #types #ty, },
+                        quote! { #pattern #ident, },
+                        quote! {
+                            #values
+                            #export::to_value(#ident).expect("invalid task parameter"),
+                        },
+                    )
+                }
+                _ => (types, pattern, values),
+            },
+            _ => (types, pattern, values),
+        },
+    )
+}
+
 impl ToTokens for Task {
     fn to_tokens(&self, dst: &mut TokenStream) {
         let krate = quote!(::celery);
@@ -528,11 +686,28 @@ impl ToTokens for Task {
             .as_ref()
             .map(|r| quote! { Some(#r) })
             .unwrap_or_else(|| quote! { None });
+        let ignore_result = self
+            .ignore_result
+            .as_ref()
+            .map(|r| quote! { Some(#r) })
+            .unwrap_or_else(|| quote! { None });
+        let track_started = self
+            .track_started
+            .as_ref()
+            .map(|r| quote! { Some(#r) })
+            .unwrap_or_else(|| quote! { None });
+        let rate_limit = self
+            .rate_limit
+            .map(|(tasks, period)| {
+                quote! { Some(#krate::task::RateLimit { tasks: #tasks, period: #period }) }
+            })
+            .unwrap_or_else(|| quote! { None });
         let content_type = self
             .content_type
             .as_ref()
             .map(|r| quote! { Some(#r) })
             .unwrap_or_else(|| quote! { Some(#krate::protocol::MessageContentType::Json) });
+        let blocking = self.blocking;
         let task_name = self.name.as_ref().unwrap();
         let arg_names = args_to_arg_names(&self.original_args, self.bind);
         let serialized_fields = args_to_fields(&self.original_args, self.bind);
@@ -551,6 +726,80 @@ impl ToTokens for Task {
         let params_args = args_to_calling_args(&self.original_args, self.bind);
         let calling_args = args_to_calling_args(&self.original_args, false);
 
+        // The first parameter of partial signatures is filled in by the previous task.
+        let skipped = if self.bind { 2 } else { 1 };
+        let partial_constructor = if self.original_args.len() >= skipped {
+            let partial_args = self.original_args.iter().skip(skipped);
+            let partial_typed_inputs = args_to_typed_inputs(partial_args.clone(), false);
+            let partial_params = args_to_partial_params(partial_args, &export);
+            quote! {
+                #vis fn partial(#partial_typed_inputs) -> #krate::task::Signature<Self> {
+                    #[allow(unused_mut)]
+                    let mut params = #export::Map::new();
+                    #partial_params
+                    #krate::task::Signature::<Self>::partial(params)
+                }
+            }
+        } else {
+            quote! {}
+        };
+
+        // Map constructors run the task over many parameter sets within a few messages.
+        let params_count = self.original_args.len() - if self.bind { 1 } else { 0 };
+        let map_constructors = if params_count > 0 {
+            let (tuple_types, tuple_pattern, tuple_values) = args_to_tuple(
+                self.original_args
+                    .iter()
+                    .skip(if self.bind { 1 } else { 0 }),
+                &export,
+            );
+            let map_constructor = if params_count == 1 {
+                quote! {
+                    #vis fn map(
+                        it: impl IntoIterator<Item = #tuple_types>,
+                    ) -> #krate::task::Signature<#krate::canvas::MapTask> {
+                        #krate::canvas::map(
+                            #krate::task::Signature::<Self>::partial(#export::Map::new()),
+                            it.into_iter()
+                                .map(|#tuple_pattern| #tuple_values)
+                                .collect(),
+                        )
+                    }
+                }
+            } else {
+                quote! {}
+            };
+            quote! {
+                #map_constructor
+
+                #vis fn starmap(
+                    it: impl IntoIterator<Item = (#tuple_types)>,
+                ) -> #krate::task::Signature<#krate::canvas::StarmapTask> {
+                    #krate::canvas::starmap(
+                        #krate::task::Signature::<Self>::partial(#export::Map::new()),
+                        it.into_iter()
+                            .map(|(#tuple_pattern)| vec![#tuple_values])
+                            .collect(),
+                    )
+                }
+
+                #vis fn chunks(
+                    it: impl IntoIterator<Item = (#tuple_types)>,
+                    n: usize,
+                ) -> #krate::canvas::Group {
+                    #krate::canvas::chunks(
+                        #krate::task::Signature::<Self>::partial(#export::Map::new()),
+                        it.into_iter()
+                            .map(|(#tuple_pattern)| vec![#tuple_values])
+                            .collect(),
+                        n,
+                    )
+                }
+            }
+        } else {
+            quote! {}
+        };
+
         let wrapper_struct = quote! {
             #[allow(non_camel_case_types)]
             #[derive(Clone)]
@@ -567,6 +816,10 @@ impl ToTokens for Task {
                         }
                     )
                 }
+
+                #partial_constructor
+
+                #map_constructors
             }
         };
 
@@ -610,7 +863,30 @@ impl ToTokens for Task {
             }
         };
 
-        let call_run_implementation = if self.is_async {
+        let call_run_implementation = if self.blocking {
+            // Blocking tasks run on the blocking thread pool, which needs an owned task.
+            let (clone_task, borrow_task) = match self.original_args.first() {
+                Some(syn::FnArg::Typed(cap)) if self.bind => match *cap.pat {
+                    syn::Pat::Ident(ref pat) => {
+                        let ident = &pat.ident;
+                        (
+                            quote! { let #ident = #ident.clone(); },
+                            quote! { let #ident = &#ident; },
+                        )
+                    }
+                    _ => (quote! {}, quote! {}),
+                },
+                _ => (quote! {}, quote! {}),
+            };
+            quote! {
+                #clone_task
+                Ok(#krate::task::Task::run_blocking(self, move || {
+                    #borrow_task
+                    #wrapper::_run(#calling_args)
+                })
+                .await?)
+            }
+        } else if self.is_async {
             quote! {
                 Ok(#wrapper::_run(#calling_args).await?)
             }
@@ -635,7 +911,7 @@ impl ToTokens for Task {
         };
 
         let dummy_const = syn::Ident::new(
-            &format!("__IMPL_CELERY_TASK_FOR_{}", wrapper.to_string()),
+            &format!("__IMPL_CELERY_TASK_FOR_{}", wrapper),
             Span::call_site(),
         );
 
@@ -657,6 +933,7 @@ impl ToTokens for Task {
                 impl #krate::task::Task for #wrapper {
                     const NAME: &'static str = #task_name;
                     const ARGS: &'static [&'static str] = &[#arg_names];
+                    const BLOCKING: bool = #blocking;
                     const DEFAULTS: #krate::task::TaskOptions = #krate::task::TaskOptions {
                         time_limit: #time_limit,
                         hard_time_limit: #hard_time_limit,
@@ -665,6 +942,9 @@ impl ToTokens for Task {
                         max_retry_delay: #max_retry_delay,
                         retry_for_unexpected: #retry_for_unexpected,
                         acks_late: #acks_late,
+                        ignore_result: #ignore_result,
+                        track_started: #track_started,
+                        rate_limit: #rate_limit,
                         content_type: #content_type,
                     };
 
@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+use futures::future;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::trace::{send_errbacks, TaskSender};
+use crate::backend::{ResultBackend, TaskMeta};
+use crate::error::{CeleryError, TaskError};
+use crate::task::{
+    signature_list, RawSignature, Request, ResultTuple, Task, TaskOptions, TaskResult,
+};
+
+/// Run the callback of a chord with the return values of the tasks of its header, once they
+/// are all ready.
+///
+/// If any task of the header failed, the callback isn't run. Instead, it is marked as failed
+/// and its errbacks are sent, like Python's `chord_error_from_stack`.
+pub(super) async fn on_chord_ready(
+    sender: &dyn TaskSender,
+    backend: &dyn ResultBackend,
+    mut callback: RawSignature,
+    results: Vec<TaskMeta>,
+) -> Result<(), CeleryError> {
+    if let Some(failed) = results.iter().find(|meta| meta.status.is_exception()) {
+        let reason = match failed.error() {
+            Some(e) => e.to_string(),
+            None => format!("task ended in state {}", failed.status),
+        };
+        let error =
+            TaskError::UnexpectedError(format!("Dependency {} raised {}", failed.task_id, reason));
+        let callback_id = callback.task_id()?;
+        error!(
+            "Chord callback {}[{}] failed: {}",
+            callback.task, callback_id, error
+        );
+        backend
+            .store_result(&TaskMeta::failure(&callback_id, &error))
+            .await?;
+        let errbacks = match callback.options.remove("link_error") {
+            Some(errbacks) => signature_list(errbacks)?,
+            None => vec![],
+        };
+        send_errbacks(sender, errbacks, &callback_id).await;
+        return Ok(());
+    }
+
+    if !callback.immutable {
+        let values: Vec<_> = results.into_iter().map(|meta| meta.result).collect();
+        callback.args.insert(0, json!(values));
+    }
+    sender.send_signature(callback).await?;
+    Ok(())
+}
+
+/// The built-in task that joins chords when the result backend doesn't
+/// [support native joins](ResultBackend::supports_native_join), by polling the results of
+/// the header until they are all ready. It has the same name and parameters as Python's
+/// `celery.chord_unlock` task, so it can be executed by either Rust or Python workers.
+pub(crate) struct ChordUnlockTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct ChordUnlockParams {
+    /// The ID of the group of the header.
+    pub(crate) group_id: String,
+
+    /// The callback of the chord.
+    pub(crate) callback: RawSignature,
+
+    /// The number of seconds to wait between two polls.
+    #[serde(default)]
+    pub(crate) interval: Option<f64>,
+
+    /// The maximum number of polls, or `None` to poll until the header is ready.
+    #[serde(default)]
+    pub(crate) max_retries: Option<u32>,
+
+    /// The results of the tasks of the header, as tuples.
+    pub(crate) result: Vec<ResultTuple>,
+}
+
+#[async_trait]
+impl Task for ChordUnlockTask {
+    const NAME: &'static str = "celery.chord_unlock";
+    const ARGS: &'static [&'static str] = &["group_id", "callback"];
+    const DEFAULTS: TaskOptions = TaskOptions {
+        time_limit: None,
+        hard_time_limit: None,
+        // The number of polls is limited by the `max_retries` parameter instead.
+        max_retries: Some(u32::MAX),
+        min_retry_delay: None,
+        max_retry_delay: None,
+        retry_for_unexpected: Some(false),
+        acks_late: None,
+        ignore_result: Some(true),
+        track_started: None,
+        rate_limit: None,
+        content_type: None,
+    };
+
+    type Params = ChordUnlockParams;
+    type Returns = ();
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<()> {
+        let (backend, sender) = match (&self.request.backend, &self.request.sender) {
+            (Some(backend), Some(sender)) => (backend, sender),
+            _ => {
+                return Err(TaskError::UnexpectedError(
+                    "no result backend configured".into(),
+                ))
+            }
+        };
+
+        let results = future::try_join_all(
+            params
+                .result
+                .iter()
+                .map(|ResultTuple((task_id, _), _)| backend.get_task_meta(task_id)),
+        )
+        .await
+        .map_err(|e| TaskError::ExpectedError(e.to_string()))?;
+        if !results.iter().all(|meta| meta.status.is_ready()) {
+            if let Some(max_retries) = params.max_retries {
+                if self.request.retries >= max_retries {
+                    return Err(TaskError::UnexpectedError(format!(
+                        "the header of chord {} isn't ready after {} retries",
+                        params.group_id, max_retries
+                    )));
+                }
+            }
+            let interval = params.interval.unwrap_or(1.0).ceil() as u32;
+            return self.retry_with_countdown(interval);
+        }
+
+        on_chord_ready(sender.as_ref(), backend.as_ref(), params.callback, results)
+            .await
+            .map_err(|e| TaskError::ExpectedError(e.to_string()))
+    }
+}
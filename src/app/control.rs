@@ -0,0 +1,445 @@
+//! Remote control of workers, see [`CeleryBuilder::enable_remote_control`](crate::CeleryBuilder::enable_remote_control).
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::AbortHandle;
+use log::{debug, error, info, warn};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+use super::revoked::RevokedSet;
+use super::{Celery, Consumers};
+use crate::broker::Broker;
+use crate::error::CeleryError;
+use crate::protocol::{ControlMessage, Message};
+use crate::task::RateLimit;
+
+/// Broadcasts remote control commands to workers. This is implemented by the [`Mailbox`]
+/// of the app.
+#[async_trait]
+pub(crate) trait ControlSender: Send + Sync {
+    /// Broadcast a command to all workers, without waiting for replies.
+    async fn broadcast(
+        &self,
+        method: &str,
+        arguments: Map<String, Value>,
+    ) -> Result<(), CeleryError>;
+}
+
+/// The mailbox through which an app broadcasts remote control commands.
+pub(super) struct Mailbox<B: Broker> {
+    pub(super) broker: Arc<B>,
+    pub(super) namespace: String,
+}
+
+#[async_trait]
+impl<B: Broker> ControlSender for Mailbox<B> {
+    async fn broadcast(
+        &self,
+        method: &str,
+        arguments: Map<String, Value>,
+    ) -> Result<(), CeleryError> {
+        let message = ControlMessage::new(method, arguments);
+        Ok(self.broker.send_control(&self.namespace, &message).await?)
+    }
+}
+
+/// Where a task that was received by the worker is at.
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    /// Waiting for its ETA.
+    Scheduled,
+    /// Waiting to be executed.
+    Reserved,
+    /// Executing.
+    Active,
+}
+
+struct TrackedTask {
+    /// The description of the task, in the format of Python's `Request.info`.
+    info: Map<String, Value>,
+    eta: Option<DateTime<Utc>>,
+    stage: Stage,
+
+    /// Terminates the task once it's executing.
+    abort: Option<AbortHandle>,
+}
+
+/// How long a worker waits after tasks are revoked before it saves them to its state file,
+/// so that revoking many tasks in a row only saves them once.
+pub(super) const SAVE_REVOKED_DELAY: Duration = Duration::from_secs(1);
+
+/// The tasks that a worker has received, which the `inspect` commands report, and the
+/// tasks that were revoked.
+pub(super) struct WorkerState {
+    started: Instant,
+    tasks: Mutex<HashMap<String, TrackedTask>>,
+
+    /// The number of tasks that started executing, by task name.
+    total: Mutex<BTreeMap<String, u64>>,
+
+    revoked: Mutex<RevokedSet>,
+
+    /// The file that the revoked tasks are saved to, if any.
+    state_db: Option<PathBuf>,
+
+    /// Whether tasks were revoked since the revoked tasks were last saved, and a
+    /// notification for the worker to save them.
+    unsaved: AtomicBool,
+    revoked_changed: Notify,
+
+    /// Makes the revoked tasks be saved one at a time.
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl WorkerState {
+    /// Create the state of a worker, with the revoked tasks saved in `state_db` if it's set.
+    pub(super) async fn new(state_db: Option<PathBuf>) -> io::Result<Self> {
+        let revoked = match state_db {
+            Some(ref path) => RevokedSet::load(path).await?,
+            None => RevokedSet::default(),
+        };
+        Ok(Self {
+            started: Instant::now(),
+            tasks: Mutex::new(HashMap::new()),
+            total: Mutex::new(BTreeMap::new()),
+            revoked: Mutex::new(revoked),
+            state_db,
+            unsaved: AtomicBool::new(false),
+            revoked_changed: Notify::new(),
+            saving: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(super) fn is_revoked(&self, task_id: &str) -> bool {
+        self.revoked.lock().unwrap().contains(task_id)
+    }
+
+    /// Revoke tasks, and terminate the ones that are executing if `terminate` is `true`.
+    /// Returns the IDs of the terminated tasks.
+    async fn revoke(&self, task_ids: &[String], terminate: bool) -> Vec<String> {
+        {
+            let mut revoked = self.revoked.lock().unwrap();
+            for task_id in task_ids {
+                revoked.add(task_id);
+            }
+        }
+        if self.state_db.is_some() {
+            self.unsaved.store(true, Ordering::SeqCst);
+            self.revoked_changed.notify_one();
+        }
+
+        let mut terminated = vec![];
+        if terminate {
+            let tasks = self.tasks.lock().unwrap();
+            for task_id in task_ids {
+                if let Some(abort) = tasks.get(task_id).and_then(|task| task.abort.as_ref()) {
+                    abort.abort();
+                    terminated.push(task_id.clone());
+                }
+            }
+        }
+        terminated
+    }
+
+    /// Wait until tasks are revoked, if the revoked tasks are saved to a state file.
+    pub(super) async fn revoked_changed(&self) {
+        self.revoked_changed.notified().await
+    }
+
+    /// Save the revoked tasks to the state file, if it's set and tasks were revoked since
+    /// they were last saved.
+    pub(super) async fn save_revoked(&self) {
+        let path = match self.state_db {
+            Some(ref path) => path,
+            None => return,
+        };
+        let _saving = self.saving.lock().await;
+        if !self.unsaved.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let revoked = self.revoked.lock().unwrap().clone();
+        if let Err(e) = revoked.save(path).await {
+            error!("Failed to save revoked tasks to {:?}: {}", path, e);
+            self.unsaved.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Start tracking a task that was received from `queue`, until the returned guard is
+    /// dropped.
+    pub(super) fn track(&self, message: &Message, hostname: &str, queue: &str) -> TaskGuard<'_> {
+        let (args, kwargs) = match message.raw_params() {
+            Ok((params, _)) => (params.args, params.kwargs),
+            Err(_) => (vec![], Map::new()),
+        };
+        let info = json!({
+            "id": message.headers.id,
+            "name": message.headers.task,
+            "args": args,
+            "kwargs": kwargs,
+            "type": message.headers.task,
+            "hostname": hostname,
+            "time_start": null,
+            "acknowledged": false,
+            "delivery_info": {
+                "exchange": "",
+                "routing_key": queue,
+                "priority": 0,
+                "redelivered": false,
+            },
+            "worker_pid": process::id(),
+        });
+        let task = TrackedTask {
+            info: match info {
+                Value::Object(info) => info,
+                _ => unreachable!(),
+            },
+            eta: message.headers.eta,
+            stage: Stage::Reserved,
+            abort: None,
+        };
+        let id = message.headers.id.clone();
+        self.tasks.lock().unwrap().insert(id.clone(), task);
+        TaskGuard { state: self, id }
+    }
+
+    /// The number of tasks that are executing, and of tasks that started executing so far.
+    pub(super) fn counts(&self) -> (usize, u64) {
+        let active = self
+            .tasks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|task| task.stage == Stage::Active)
+            .count();
+        let processed = self.total.lock().unwrap().values().sum();
+        (active, processed)
+    }
+
+    /// Get the descriptions of the tasks at the given stage.
+    fn tasks(&self, stage: Stage) -> Vec<Value> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|task| task.stage == stage)
+            .map(|task| match stage {
+                Stage::Scheduled => json!({
+                    "eta": task.eta.map(|eta| eta.to_rfc3339()),
+                    "priority": 0,
+                    "request": task.info,
+                }),
+                _ => Value::Object(task.info.clone()),
+            })
+            .collect()
+    }
+}
+
+/// Tracks a task in the [`WorkerState`] while it's alive.
+pub(super) struct TaskGuard<'a> {
+    state: &'a WorkerState,
+    id: String,
+}
+
+impl<'a> TaskGuard<'a> {
+    fn update<F: FnOnce(&mut TrackedTask)>(&self, f: F) {
+        if let Some(task) = self.state.tasks.lock().unwrap().get_mut(&self.id) {
+            f(task);
+        }
+    }
+
+    /// Mark the task as waiting for its ETA.
+    pub(super) fn schedule(&self) {
+        self.update(|task| task.stage = Stage::Scheduled);
+    }
+
+    /// Mark the task as waiting to be executed, once its ETA is reached.
+    pub(super) fn reserve(&self) {
+        self.update(|task| task.stage = Stage::Reserved);
+    }
+
+    /// Mark the task as executing. `abort` terminates it when it's revoked with
+    /// `terminate`.
+    pub(super) fn start(&self, acknowledged: bool, abort: AbortHandle) {
+        let time_start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs_f64())
+            .unwrap_or_default();
+        let mut name = None;
+        self.update(|task| {
+            task.stage = Stage::Active;
+            task.abort = Some(abort);
+            task.info.insert("time_start".into(), json!(time_start));
+            task.info.insert("acknowledged".into(), json!(acknowledged));
+            name = task.info["name"].as_str().map(String::from);
+        });
+        if let Some(name) = name {
+            *self.state.total.lock().unwrap().entry(name).or_default() += 1;
+        }
+    }
+}
+
+impl<'a> Drop for TaskGuard<'a> {
+    fn drop(&mut self) {
+        self.state.tasks.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl<B> Celery<B>
+where
+    B: Broker + 'static,
+{
+    /// Execute a remote control command if it's meant for this worker, and send its reply.
+    /// Returns `true` if the worker has to shut down.
+    pub(super) async fn handle_control(
+        self: &Arc<Self>,
+        message: ControlMessage,
+        consumers: &mut Consumers<B>,
+    ) -> bool {
+        if !message.is_for(&self.hostname) {
+            return false;
+        }
+        debug!("Received control command {}", message.method);
+
+        let reply = match message.method.as_str() {
+            "ping" => json!({"ok": "pong"}),
+            "registered" => {
+                let mut tasks: Vec<String> = self
+                    .task_trace_builders
+                    .read()
+                    .await
+                    .keys()
+                    .filter(|task| !task.starts_with("celery."))
+                    .cloned()
+                    .collect();
+                tasks.sort();
+                json!(tasks)
+            }
+            "active" => json!(self.worker_state.tasks(Stage::Active)),
+            "reserved" => json!(self.worker_state.tasks(Stage::Reserved)),
+            "scheduled" => json!(self.worker_state.tasks(Stage::Scheduled)),
+            "stats" => self.stats(),
+            "shutdown" => {
+                warn!("Got shutdown from remote");
+                return true;
+            }
+            "add_consumer" => match message.arguments.get("queue").and_then(Value::as_str) {
+                Some(queue) => match consumers.add(self.broker.as_ref(), queue).await {
+                    Ok(true) => json!({ "ok": format!("add consumer {}", queue) }),
+                    Ok(false) => json!({ "ok": format!("already consuming from '{}'", queue) }),
+                    Err(e) => json!({ "error": e.to_string() }),
+                },
+                None => json!({"error": "missing argument: queue"}),
+            },
+            "cancel_consumer" => match message.arguments.get("queue").and_then(Value::as_str) {
+                Some(queue) => match consumers.cancel(self.broker.as_ref(), queue).await {
+                    Ok(_) => json!({ "ok": format!("no longer consuming from '{}'", queue) }),
+                    Err(e) => json!({ "error": e.to_string() }),
+                },
+                None => json!({"error": "missing argument: queue"}),
+            },
+            "revoke" => {
+                let task_ids: Vec<String> = match message.arguments.get("task_id") {
+                    Some(Value::String(task_id)) => vec![task_id.clone()],
+                    Some(Value::Array(task_ids)) => task_ids
+                        .iter()
+                        .filter_map(|task_id| task_id.as_str().map(String::from))
+                        .collect(),
+                    _ => vec![],
+                };
+                let terminate = message
+                    .arguments
+                    .get("terminate")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false);
+                let terminated = self.worker_state.revoke(&task_ids, terminate).await;
+                if terminate {
+                    if terminated.is_empty() {
+                        json!({"ok": "terminate: tasks unknown"})
+                    } else {
+                        json!({ "ok": format!("terminate: {}", terminated.join(", ")) })
+                    }
+                } else {
+                    json!({ "ok": format!("tasks {} flagged as revoked", task_ids.join(", ")) })
+                }
+            }
+            "rate_limit" => self.set_rate_limit(&message.arguments).await,
+            method => json!({ "error": format!("No such method: {}", method) }),
+        };
+
+        if let (Some(reply_to), Some(ticket)) = (&message.reply_to, &message.ticket) {
+            let mut replies = Map::new();
+            replies.insert(self.hostname.clone(), reply);
+            if let Err(e) = self
+                .broker
+                .send_control_reply(reply_to, ticket, &Value::Object(replies))
+                .await
+            {
+                error!("Failed to reply to control command: {}", e);
+            }
+        }
+        false
+    }
+
+    /// Change the rate limit of a task, like Python's `rate_limit` command.
+    async fn set_rate_limit(&self, arguments: &Map<String, Value>) -> Value {
+        let task_name = match arguments.get("task_name").and_then(Value::as_str) {
+            Some(task_name) => task_name,
+            None => return json!({"error": "missing argument: task_name"}),
+        };
+        if !self
+            .task_trace_builders
+            .read()
+            .await
+            .contains_key(task_name)
+        {
+            return json!({"error": "unknown task"});
+        }
+        // Like in Python, a missing rate limit disables the limit.
+        let rate_limit = match arguments.get("rate_limit") {
+            Some(Value::String(rate_limit)) => rate_limit.clone(),
+            Some(Value::Null) | None => "0".into(),
+            Some(rate_limit) => rate_limit.to_string(),
+        };
+        match rate_limit.parse::<RateLimit>() {
+            Ok(rate_limit) => {
+                self.rate_limits.set(task_name, rate_limit);
+                if rate_limit.per_second().is_some() {
+                    info!(
+                        "New rate limit for tasks of type {}: {}",
+                        task_name, rate_limit
+                    );
+                    json!({"ok": "new rate limit set successfully"})
+                } else {
+                    info!("Rate limits disabled for tasks of type {}", task_name);
+                    json!({"ok": "rate limit disabled successfully"})
+                }
+            }
+            Err(_) => json!({ "error": format!("Invalid rate limit string: '{}'", rate_limit) }),
+        }
+    }
+
+    /// The statistics of the worker, like Python's `inspect stats`.
+    fn stats(&self) -> Value {
+        json!({
+            "total": *self.worker_state.total.lock().unwrap(),
+            "pid": process::id(),
+            "clock": "0",
+            "uptime": self.worker_state.started.elapsed().as_secs(),
+            "pool": {
+                "max-concurrency": self.concurrency,
+                "implementation": if self.pool.is_some() { "prefork" } else { "tokio" },
+            },
+            "broker": {
+                "url": self.broker.safe_url(),
+            },
+        })
+    }
+}
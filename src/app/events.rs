@@ -0,0 +1,93 @@
+//! Sending events to monitors like Flower, see [`CeleryBuilder::send_events`](crate::CeleryBuilder::send_events).
+
+use async_trait::async_trait;
+use log::error;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use super::Celery;
+use crate::broker::Broker;
+use crate::protocol::{Event, Message};
+
+/// How often workers send `worker-heartbeat` events, like in Python.
+pub(super) const HEARTBEAT_FREQ: Duration = Duration::from_secs(2);
+
+/// Sends events. This is implemented by the [`EventDispatcher`] of the app.
+#[async_trait]
+pub(super) trait EventSender: Send + Sync {
+    /// Send an event of the given type with the given fields. Failures are logged.
+    async fn send_event(&self, event_type: &str, fields: Value);
+}
+
+/// Sends the events of an app through its broker, like Python's `EventDispatcher`.
+pub(super) struct EventDispatcher<B: Broker> {
+    pub(super) broker: Arc<B>,
+    pub(super) hostname: String,
+
+    /// The logical clock of the events.
+    pub(super) clock: AtomicU64,
+}
+
+#[async_trait]
+impl<B: Broker> EventSender for EventDispatcher<B> {
+    async fn send_event(&self, event_type: &str, fields: Value) {
+        let fields = match fields {
+            Value::Object(fields) => fields,
+            _ => Map::new(),
+        };
+        let clock = self.clock.fetch_add(1, Ordering::SeqCst) + 1;
+        let event = Event::new(event_type, &self.hostname, clock, fields);
+        if let Err(e) = self.broker.send_event(&event).await {
+            error!("Failed to send {} event: {}", event_type, e);
+        }
+    }
+}
+
+/// The fields that describe a task in `task-sent` and `task-received` events.
+pub(super) fn task_fields(message: &Message) -> Value {
+    let (args, kwargs) = match message.raw_params() {
+        Ok((params, _)) => (params.args, params.kwargs),
+        Err(_) => (vec![], Map::new()),
+    };
+    let headers = &message.headers;
+    json!({
+        "uuid": headers.id,
+        "name": headers.task,
+        "args": headers.argsrepr.clone().unwrap_or_else(|| json!(args).to_string()),
+        "kwargs": headers.kwargsrepr.clone().unwrap_or_else(|| json!(kwargs).to_string()),
+        "root_id": headers.root_id,
+        "parent_id": headers.parent_id,
+        "retries": headers.retries.unwrap_or_default(),
+        "eta": headers.eta.map(|eta| eta.to_rfc3339()),
+        "expires": headers.expires.map(|expires| expires.to_rfc3339()),
+    })
+}
+
+impl<B> Celery<B>
+where
+    B: Broker + 'static,
+{
+    /// Send a `worker-online`, `worker-heartbeat` or `worker-offline` event, if the worker
+    /// sends events.
+    pub(super) async fn send_worker_event(&self, event_type: &str) {
+        if !self.send_events {
+            return;
+        }
+        let (active, processed) = self.worker_state.counts();
+        self.events
+            .send_event(
+                event_type,
+                json!({
+                    "freq": HEARTBEAT_FREQ.as_secs_f64(),
+                    "sw_ident": "rusty-celery",
+                    "sw_ver": env!("CARGO_PKG_VERSION"),
+                    "sw_sys": std::env::consts::OS,
+                    "active": active,
+                    "processed": processed,
+                }),
+            )
+            .await;
+    }
+}
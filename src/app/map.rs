@@ -0,0 +1,139 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::trace::TaskSender;
+use crate::error::TaskError;
+use crate::task::{RawSignature, Request, Task, TaskOptions, TaskResult};
+
+/// Apply the task of `signature` once for each set of positional arguments, in order, and
+/// collect the return values.
+///
+/// Like in Python, the applications run in place, one after the other, so together they use
+/// the concurrency slot of the task that applies them. Each one waits for the rate limit of
+/// the task, but the worker doesn't track them as separate tasks: they don't send task
+/// events and can't be revoked on their own, only along with the task that applies them.
+async fn apply_each<I>(
+    sender: &Arc<dyn TaskSender>,
+    signature: &RawSignature,
+    it: I,
+) -> TaskResult<Vec<Value>>
+where
+    I: IntoIterator<Item = Vec<Value>>,
+{
+    let mut results = vec![];
+    for args in it {
+        let mut signature = signature.clone();
+        signature.args.splice(0..0, args);
+        // Each application runs under its own ID.
+        signature.options.remove("task_id");
+        results.push(sender.clone().apply_signature(signature).await?);
+    }
+    Ok(results)
+}
+
+fn sender<T: Task>(request: &Request<T>) -> TaskResult<&Arc<dyn TaskSender>> {
+    request
+        .sender
+        .as_ref()
+        .ok_or_else(|| TaskError::UnexpectedError("no app to apply the task with".into()))
+}
+
+/// The built-in task that runs a task over a list of parameters within a single message,
+/// created with [`canvas::map`](crate::canvas::map). Each item of the list is passed to the
+/// task as its first parameter, and the return values are returned as a list.
+///
+/// It has the same name and parameters as Python's `celery.map` task, so it can be executed by
+/// either Rust or Python workers. The task it runs must be registered on the worker. The runs
+/// of the task happen one after the other in place, waiting for its rate limit, and don't send
+/// task events of their own.
+pub struct MapTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+/// The parameters of a [`MapTask`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapParams {
+    /// The signature of the task to run for each item.
+    pub task: RawSignature,
+
+    /// The first parameter of each run of the task.
+    pub it: Vec<Value>,
+}
+
+#[async_trait]
+impl Task for MapTask {
+    const NAME: &'static str = "celery.map";
+    const ARGS: &'static [&'static str] = &["task", "it"];
+
+    type Params = MapParams;
+    type Returns = Vec<Value>;
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<Vec<Value>> {
+        let sender = sender(&self.request)?;
+        apply_each(sender, &params.task, params.it.into_iter().map(|x| vec![x])).await
+    }
+}
+
+/// The built-in task that runs a task over a list of positional arguments within a single
+/// message, created with [`canvas::starmap`](crate::canvas::starmap) or
+/// [`canvas::chunks`](crate::canvas::chunks). The return values are returned as a list.
+///
+/// It has the same name and parameters as Python's `celery.starmap` task, so it can be
+/// executed by either Rust or Python workers. The task it runs must be registered on the
+/// worker. The runs of the task happen one after the other in place, waiting for its rate
+/// limit, and don't send task events of their own.
+pub struct StarmapTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+/// The parameters of a [`StarmapTask`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StarmapParams {
+    /// The signature of the task to run for each item.
+    pub task: RawSignature,
+
+    /// The positional arguments of each run of the task.
+    pub it: Vec<Vec<Value>>,
+}
+
+#[async_trait]
+impl Task for StarmapTask {
+    const NAME: &'static str = "celery.starmap";
+    const ARGS: &'static [&'static str] = &["task", "it"];
+
+    type Params = StarmapParams;
+    type Returns = Vec<Value>;
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<Vec<Value>> {
+        let sender = sender(&self.request)?;
+        apply_each(sender, &params.task, params.it).await
+    }
+}
@@ -1,9 +1,15 @@
+use async_trait::async_trait;
 use colored::Colorize;
-use futures::stream::StreamExt;
+use futures::future::{Future, FutureExt};
+use futures::stream::{self, StreamExt};
 use log::{debug, error, info, warn};
+use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::error::Error;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
 use std::sync::Arc;
 use tokio::select;
 
@@ -11,18 +17,43 @@ use tokio::select;
 use tokio::signal::unix::{signal, Signal, SignalKind};
 
 use tokio::sync::mpsc::{self, UnboundedSender};
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, Semaphore};
 use tokio::time::{self, Duration};
 use tokio_stream::StreamMap;
 
+mod chord;
+mod control;
+mod events;
+mod map;
+#[cfg(unix)]
+mod prefork;
+mod rate_limit;
+mod revoked;
 mod trace;
 
+use crate::backend::{build_backend, ResultBackend};
 use crate::broker::{build_and_connect, configure_task_routes, Broker, BrokerBuilder};
-use crate::error::{BrokerError, CeleryError, TraceError};
-use crate::protocol::{Message, MessageContentType, TryDeserializeMessage};
+use crate::canvas::{Chord, Group};
+use crate::error::{BackendError, BrokerError, CeleryError, ProtocolError, TaskError, TraceError};
+use crate::protocol::{
+    DeadLetter, DeadLetterReason, Message, MessageContentType, TryDeserializeMessage,
+};
 use crate::routing::Rule;
-use crate::task::{AsyncResult, Signature, Task, TaskEvent, TaskOptions, TaskStatus};
-use trace::{build_tracer, TraceBuilder, TracerTrait};
+use crate::task::{
+    AsyncResult, DynamicHandler, GroupResult, RateLimit, RawSignature, ResultTuple, Signature,
+    Task, TaskEvent, TaskOptions, TaskResult, TaskStatus,
+};
+use chord::ChordUnlockTask;
+pub(crate) use control::ControlSender;
+use control::{Mailbox, WorkerState, SAVE_REVOKED_DELAY};
+use events::{EventDispatcher, EventSender, HEARTBEAT_FREQ};
+pub use map::{MapParams, MapTask, StarmapParams, StarmapTask};
+use rate_limit::RateLimiter;
+pub(crate) use trace::TaskSender;
+use trace::{
+    build_dynamic_tracer, build_tracer, ChildPool, ChildTask, TraceBuilder, TraceContext,
+    TracerTrait,
+};
 
 struct Config<Bb>
 where
@@ -30,12 +61,25 @@ where
 {
     name: String,
     hostname: String,
+    broker_url: String,
     broker_builder: Bb,
     broker_connection_timeout: u32,
     broker_connection_retry: bool,
     broker_connection_max_retries: u32,
     broker_connection_retry_delay: u32,
+    result_backend: Option<String>,
+    result_expires: Option<u32>,
+    result_extended: bool,
     default_queue: String,
+    dead_letter_queue: Option<String>,
+    concurrency: Option<usize>,
+    blocking_pool_size: usize,
+    prefork: bool,
+    enable_remote_control: bool,
+    control_exchange: String,
+    worker_state_db: Option<PathBuf>,
+    send_events: bool,
+    send_sent_event: bool,
     task_options: TaskOptions,
     task_routes: Vec<(String, String)>,
 }
@@ -65,12 +109,27 @@ where
                         .and_then(|sys_hostname| sys_hostname.into_string().ok())
                         .unwrap_or_else(|| "unknown".into())
                 ),
+                broker_url: broker_url.into(),
                 broker_builder: Bb::new(broker_url),
                 broker_connection_timeout: 2,
                 broker_connection_retry: true,
                 broker_connection_max_retries: 5,
                 broker_connection_retry_delay: 5,
+                result_backend: None,
+                result_expires: Some(86400),
+                result_extended: false,
                 default_queue: "celery".into(),
+                dead_letter_queue: None,
+                concurrency: None,
+                blocking_pool_size: std::thread::available_parallelism()
+                    .map(usize::from)
+                    .unwrap_or(1),
+                prefork: false,
+                enable_remote_control: true,
+                control_exchange: "celery".into(),
+                worker_state_db: None,
+                send_events: false,
+                send_sent_event: false,
                 task_options: TaskOptions::default(),
                 task_routes: vec![],
             },
@@ -92,6 +151,20 @@ where
         self
     }
 
+    /// Set a queue to which the worker sends the messages it gives up on, instead of
+    /// dropping them: messages that can't be deserialized, messages of tasks that aren't
+    /// registered, and tasks that failed after exhausting their
+    /// [`max_retries`](TaskOptions::max_retries). By default there is none.
+    ///
+    /// Dead-lettered messages keep their original headers, plus the headers of a
+    /// [`DeadLetter`](crate::protocol::DeadLetter) telling why and from which queue they were
+    /// dead-lettered. They can be sent back to their queue with
+    /// [`Celery::replay_dead_letters`].
+    pub fn dead_letter_queue(mut self, queue_name: &str) -> Self {
+        self.config.dead_letter_queue = Some(queue_name.into());
+        self
+    }
+
     /// Set the prefetch count. The default value depends on the broker implementation,
     /// but it's recommended that you always set this to a value that works best
     /// for your application.
@@ -104,6 +177,114 @@ where
         self
     }
 
+    /// Limit the number of tasks that execute at the same time, like Python's `concurrency`
+    /// setting. By default there is no limit.
+    ///
+    /// Unlike the prefetch count, which limits how many messages the worker receives but is
+    /// increased for each task with a future ETA, this limits the tasks that are actually
+    /// running. Tasks that are due while the limit is reached wait for a running task to
+    /// finish before they are acknowledged and executed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is 0.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be at least 1");
+        self.config.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Set the number of threads of the pool that runs blocking tasks (see
+    /// [`Task::run_blocking`]), i.e. the number of blocking tasks that can run at the same
+    /// time. Defaults to the number of CPUs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `blocking_pool_size` is 0.
+    pub fn blocking_pool_size(mut self, blocking_pool_size: usize) -> Self {
+        assert!(
+            blocking_pool_size > 0,
+            "blocking_pool_size must be at least 1"
+        );
+        self.config.blocking_pool_size = blocking_pool_size;
+        self
+    }
+
+    /// Run tasks in a pool of child processes, like Python's default `prefork` pool.
+    /// Defaults to `false`.
+    ///
+    /// When the worker starts consuming, it starts as many child processes as its
+    /// [`concurrency`](CeleryBuilder::concurrency), or one per CPU by default, and sends
+    /// each task to an idle child. The children run the program of the worker again, with
+    /// the same arguments, so the program must build the same app and call
+    /// [`Celery::consume`] or [`Celery::consume_from`] like the worker does. Instead of
+    /// consuming, the app then executes the tasks that the worker sends it, with its own
+    /// connections to the broker and the result backend.
+    ///
+    /// Since a child can be killed, this is how the
+    /// [`hard_time_limit`](crate::task::TaskOptions::hard_time_limit) of tasks is enforced:
+    /// when it is exceeded, the child is killed and replaced, and the task fails with a
+    /// [`WorkerLostError`](crate::error::TaskError::WorkerLostError). A task that panics
+    /// fails with an [`UnexpectedError`](crate::error::TaskError::UnexpectedError) without
+    /// taking down its child.
+    ///
+    /// The child only runs the task itself and its
+    /// [`on_success`](crate::task::Task::on_success) callback, while the worker stores the
+    /// result and sends the tasks that follow. Built-in tasks like `celery.map` always run
+    /// in the worker.
+    #[cfg(unix)]
+    pub fn prefork(mut self, prefork: bool) -> Self {
+        self.config.prefork = prefork;
+        self
+    }
+
+    /// Set whether the worker executes remote control commands, like the ones sent by
+    /// Python's `celery inspect` and `celery control`. Defaults to `true`.
+    ///
+    /// The worker answers `ping`, `registered`, `active`, `reserved`, `scheduled` and `stats`,
+    /// and executes `shutdown`, `add_consumer`, `cancel_consumer`, `revoke` and `rate_limit`.
+    pub fn enable_remote_control(mut self, enable_remote_control: bool) -> Self {
+        self.config.enable_remote_control = enable_remote_control;
+        self
+    }
+
+    /// Set the name of the mailbox that remote control commands are sent to, like Python's
+    /// `control_exchange` setting. Defaults to `"celery"`, i.e. the `celery.pidbox`
+    /// exchange.
+    pub fn control_exchange(mut self, control_exchange: &str) -> Self {
+        self.config.control_exchange = control_exchange.into();
+        self
+    }
+
+    /// Set the file in which the worker saves the IDs of revoked tasks (see
+    /// [`AsyncResult::revoke`]), like Python's `--statedb` option, so that revocations
+    /// survive a restart. By default they are only kept in memory.
+    ///
+    /// Like in Python, revoked IDs are kept for three hours, and at most 50,000 of them are
+    /// kept.
+    pub fn worker_state_db(mut self, path: &str) -> Self {
+        self.config.worker_state_db = Some(path.into());
+        self
+    }
+
+    /// Set whether the worker sends events to the `celeryev` exchange for monitors like
+    /// Flower, like Python's `-E` option. Defaults to `false`.
+    ///
+    /// The worker then sends `task-received`, `task-started`, `task-succeeded`,
+    /// `task-failed` and `task-retried` events, and `worker-online`, `worker-heartbeat`
+    /// and `worker-offline` events, in the same format as Python workers.
+    pub fn send_events(mut self, send_events: bool) -> Self {
+        self.config.send_events = send_events;
+        self
+    }
+
+    /// Set whether a `task-sent` event is sent when a task is sent, like Python's
+    /// `task_send_sent_event` setting. Defaults to `false`.
+    pub fn send_sent_event(mut self, send_sent_event: bool) -> Self {
+        self.config.send_sent_event = send_sent_event;
+        self
+    }
+
     /// Set the broker heartbeat. The default value depends on the broker implementation.
     pub fn heartbeat(mut self, heartbeat: Option<u16>) -> Self {
         self.config.broker_builder = self.config.broker_builder.heartbeat(heartbeat);
@@ -158,6 +339,26 @@ where
         self
     }
 
+    /// Set whether by default the results of tasks are stored in the result backend (see
+    /// [`TaskOptions::ignore_result`]).
+    pub fn task_ignore_result(mut self, ignore_result: bool) -> Self {
+        self.config.task_options.ignore_result = Some(ignore_result);
+        self
+    }
+
+    /// Set whether by default a `STARTED` state is stored when a task starts executing (see
+    /// [`TaskOptions::track_started`]).
+    pub fn task_track_started(mut self, track_started: bool) -> Self {
+        self.config.task_options.track_started = Some(track_started);
+        self
+    }
+
+    /// Set the default rate limit of tasks (see [`TaskOptions::rate_limit`]).
+    pub fn task_rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.config.task_options.rate_limit = Some(rate_limit);
+        self
+    }
+
     /// Set default serialization format a task will have (see [`TaskOptions::content_type`]).
     pub fn task_content_type(mut self, content_type: MessageContentType) -> Self {
         self.config.task_options.content_type = Some(content_type);
@@ -195,13 +396,49 @@ where
         self
     }
 
+    /// Set the URL of the result backend used to store task states and return values,
+    /// e.g. `"redis://127.0.0.1:6379/"`, or `"rpc://"` to send results back to the producer
+    /// through the AMQP broker, or `"memory://"` to keep results in memory when the producer and
+    /// the worker run in the same process, or `"file:///path/to/dir"` to store results in files.
+    /// With the `sqlite_backend` feature, `"db+sqlite:///results.db"` stores results in SQLite.
+    ///
+    /// If no result backend is set, task results are not stored anywhere.
+    pub fn result_backend(mut self, backend_url: &str) -> Self {
+        self.config.result_backend = Some(backend_url.into());
+        self
+    }
+
+    /// Set the number of seconds after which stored results expire, or `None` to keep them
+    /// forever. Defaults to one day, like Python.
+    ///
+    /// Not every result backend supports expiring results. Those that do either expire
+    /// results on their own (Redis) or remove them when
+    /// [`ResultBackend::cleanup`](crate::backend::ResultBackend::cleanup) is called.
+    pub fn result_expires(mut self, result_expires: Option<u32>) -> Self {
+        self.config.result_expires = result_expires;
+        self
+    }
+
+    /// Set whether to store the name, parameters, worker, number of retries and queue of tasks
+    /// alongside their results, like Python's `result_extended` setting. Defaults to `false`.
+    pub fn result_extended(mut self, result_extended: bool) -> Self {
+        self.config.result_extended = result_extended;
+        self
+    }
+
     /// Construct a [`Celery`] app with the current configuration.
-    pub async fn build(self) -> Result<Celery<Bb::Broker>, CeleryError> {
+    pub async fn build(self) -> Result<Celery<Bb::Broker>, CeleryError>
+    where
+        Bb::Broker: 'static,
+    {
         // Declare default queue to broker.
-        let broker_builder = self
+        let mut broker_builder = self
             .config
             .broker_builder
             .declare_queue(&self.config.default_queue);
+        if let Some(ref dead_letter_queue) = self.config.dead_letter_queue {
+            broker_builder = broker_builder.declare_queue(dead_letter_queue);
+        }
 
         let (broker_builder, task_routes) =
             configure_task_routes(broker_builder, &self.config.task_routes)?;
@@ -218,14 +455,69 @@ where
         )
         .await?;
 
+        let backend = match self.config.result_backend {
+            Some(ref backend_url) => Some(
+                build_backend(
+                    backend_url,
+                    &self.config.broker_url,
+                    self.config.broker_connection_timeout,
+                    self.config
+                        .result_expires
+                        .map(|secs| Duration::from_secs(secs as u64)),
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        #[cfg(unix)]
+        let pool = if self.config.prefork {
+            let size = self.config.concurrency.unwrap_or_else(|| {
+                std::thread::available_parallelism()
+                    .map(usize::from)
+                    .unwrap_or(1)
+            });
+            Some(Arc::new(prefork::ProcessPool::new(size)?) as Arc<dyn ChildPool>)
+        } else {
+            None
+        };
+        #[cfg(not(unix))]
+        let pool = None;
+
+        let broker = Arc::new(broker);
+        let mailbox = Arc::new(Mailbox {
+            broker: broker.clone(),
+            namespace: self.config.control_exchange.clone(),
+        });
+        let events = Arc::new(EventDispatcher {
+            broker: broker.clone(),
+            hostname: self.config.hostname.clone(),
+            clock: AtomicU64::new(0),
+        });
+
         Ok(Celery {
             name: self.config.name,
             hostname: self.config.hostname,
             broker,
+            backend,
+            result_extended: self.config.result_extended,
             default_queue: self.config.default_queue,
+            dead_letter_queue: self.config.dead_letter_queue,
+            concurrency: self.config.concurrency,
+            task_permits: self.config.concurrency.map(Semaphore::new),
+            blocking_permits: Arc::new(Semaphore::new(self.config.blocking_pool_size)),
+            pool,
+            enable_remote_control: self.config.enable_remote_control,
+            control_exchange: self.config.control_exchange,
+            mailbox,
+            send_events: self.config.send_events,
+            send_sent_event: self.config.send_sent_event,
+            events,
+            worker_state: WorkerState::new(self.config.worker_state_db).await?,
+            rate_limits: RateLimiter::default(),
             task_options: self.config.task_options,
             task_routes,
-            task_trace_builders: RwLock::new(HashMap::new()),
+            task_trace_builders: RwLock::new(builtin_task_trace_builders()),
             broker_connection_timeout: self.config.broker_connection_timeout,
             broker_connection_retry: self.config.broker_connection_retry,
             broker_connection_max_retries: self.config.broker_connection_max_retries,
@@ -234,6 +526,21 @@ where
     }
 }
 
+/// The trace builders of the built-in tasks that every app registers.
+fn builtin_task_trace_builders() -> HashMap<String, TraceBuilder> {
+    let mut task_trace_builders: HashMap<String, TraceBuilder> = HashMap::new();
+    task_trace_builders.insert(
+        ChordUnlockTask::NAME.into(),
+        Box::new(build_tracer::<ChordUnlockTask>),
+    );
+    task_trace_builders.insert(MapTask::NAME.into(), Box::new(build_tracer::<MapTask>));
+    task_trace_builders.insert(
+        StarmapTask::NAME.into(),
+        Box::new(build_tracer::<StarmapTask>),
+    );
+    task_trace_builders
+}
+
 /// A [`Celery`] app is used to produce or consume tasks asynchronously. This is the struct that is
 /// created with the [`app!`] macro.
 pub struct Celery<B: Broker> {
@@ -244,11 +551,57 @@ pub struct Celery<B: Broker> {
     pub hostname: String,
 
     /// The app's broker.
-    pub broker: B,
+    pub broker: Arc<B>,
+
+    /// The app's result backend, if one was configured.
+    pub backend: Option<Arc<dyn ResultBackend>>,
+
+    /// Whether to store extended meta data of tasks in the result backend.
+    result_extended: bool,
 
     /// The default queue to send and receive from.
     pub default_queue: String,
 
+    /// The queue to which the worker sends the messages it gives up on, if any.
+    pub dead_letter_queue: Option<String>,
+
+    /// The maximum number of tasks that execute at the same time, if any.
+    concurrency: Option<usize>,
+
+    /// Limits the number of tasks that execute at the same time, if a concurrency limit was
+    /// set.
+    task_permits: Option<Semaphore>,
+
+    /// Limits the number of blocking tasks that run at the same time.
+    blocking_permits: Arc<Semaphore>,
+
+    /// Runs tasks in child processes, if the app runs tasks in child processes.
+    pool: Option<Arc<dyn ChildPool>>,
+
+    /// Whether to execute remote control commands.
+    enable_remote_control: bool,
+
+    /// The name of the mailbox of remote control commands.
+    control_exchange: String,
+
+    /// Broadcasts remote control commands.
+    mailbox: Arc<dyn ControlSender>,
+
+    /// Whether the worker sends events.
+    send_events: bool,
+
+    /// Whether to send `task-sent` events.
+    send_sent_event: bool,
+
+    /// Sends events.
+    events: Arc<EventDispatcher<B>>,
+
+    /// The tasks that the worker has received, and the revoked tasks.
+    worker_state: WorkerState,
+
+    /// Enforces the rate limits of tasks.
+    rate_limits: RateLimiter,
+
     /// Default task options.
     pub task_options: TaskOptions,
 
@@ -303,16 +656,31 @@ where
         println!(" {}", self.broker.safe_url());
         println!();
 
+        // Result backend.
+        if let Some(ref backend) = self.backend {
+            println!("{}", "[results]".bold());
+            println!(" {}", backend.safe_url());
+            println!();
+        }
+
         // Registered tasks.
         println!("{}", "[tasks]".bold());
+        // Like Python, leave out the built-in tasks.
         for task in self.task_trace_builders.read().await.keys() {
-            println!(" . {}", task);
+            if !task.starts_with("celery.") {
+                println!(" . {}", task);
+            }
         }
         println!();
     }
 
     /// Send a task to a remote worker. Returns an [`AsyncResult`] with the task ID of the task
-    /// if it was successfully sent.
+    /// if it was successfully sent, which can be used to retrieve the result of the task if
+    /// the app has a result backend.
+    ///
+    /// If the signature is a chain (see [`Signature::then`]), the returned [`AsyncResult`] is
+    /// the one of the last task of the chain, and the results of the previous tasks can be
+    /// accessed through [`AsyncResult::parent`].
     pub async fn send_task<T: Task>(
         &self,
         mut task_sig: Signature<T>,
@@ -322,15 +690,229 @@ where
         let queue = maybe_queue.as_deref().unwrap_or_else(|| {
             crate::routing::route(T::NAME, &self.task_routes).unwrap_or(&self.default_queue)
         });
+
+        // Set the IDs of the tasks of the chain up front, so that we can return the result
+        // of the last task.
+        let chain_ids: Vec<String> = task_sig
+            .chain
+            .iter_mut()
+            .map(RawSignature::task_id)
+            .collect::<Result<_, _>>()?;
+
         let message = Message::try_from(task_sig)?;
+        let result = self.send_message(message, queue).await?;
+        Ok(chain_ids.iter().fold(result, |parent, task_id| {
+            self.async_result(task_id).with_parent(parent)
+        }))
+    }
+
+    /// Send the tasks of a group to remote workers, to be executed in parallel. Returns a
+    /// [`GroupResult`] with the results of the tasks, in the order in which they were added
+    /// to the group.
+    ///
+    /// The group isn't saved to the result backend unless [`GroupResult::save`] is called.
+    pub async fn send_group(&self, group: Group) -> Result<GroupResult, CeleryError> {
+        let group_id = uuid::Uuid::new_v4().to_string();
+        let results = self.send_group_tasks(&group_id, group, None).await?;
+        Ok(self.group_result(&group_id, results))
+    }
+
+    /// Send the tasks of a group under the given group ID, as the header of a chord if
+    /// `chord` is the callback of the chord.
+    async fn send_group_tasks(
+        &self,
+        group_id: &str,
+        group: Group,
+        chord: Option<&RawSignature>,
+    ) -> Result<Vec<AsyncResult>, CeleryError> {
+        let mut results = Vec::with_capacity(group.len());
+        for (group_index, mut signature) in group.tasks.into_iter().enumerate() {
+            signature.set_last_option("group_id", json!(group_id))?;
+            signature.set_last_option("group_index", json!(group_index))?;
+            if let Some(chord) = chord {
+                signature.set_last_option("chord", json!(chord))?;
+            }
+            results.push(self.send_raw_signature(signature).await?);
+        }
+        Ok(results)
+    }
+
+    /// Send a chord: the tasks of its header are sent to remote workers to be executed in
+    /// parallel, and its body is sent once they are all done. Returns the [`AsyncResult`]
+    /// of the body.
+    ///
+    /// Chords require a result backend, otherwise a [`BackendError::NotConfigured`] error
+    /// is returned, and a [`BackendError::NotSupported`] error is returned for backends that
+    /// don't [support chords](crate::backend::ResultBackend::supports_chords), like the
+    /// `rpc://` backend. If the backend doesn't
+    /// [support native joins](crate::backend::ResultBackend::supports_native_join), a
+    /// `celery.chord_unlock` task is also sent, which polls the results of the header until
+    /// they are ready and then sends the body. It is registered on every app, so any worker
+    /// consuming from the queue of the body, or the default queue, can execute it.
+    pub async fn send_chord(&self, chord: Chord) -> Result<AsyncResult, CeleryError> {
+        let backend = self.backend.as_ref().ok_or(BackendError::NotConfigured)?;
+        if !backend.supports_chords() {
+            return Err(BackendError::NotSupported("chords".into()).into());
+        }
+        let Chord { header, mut body } = chord;
+        let body_id = body.task_id()?;
+        if header.is_empty() {
+            if !body.immutable {
+                body.args.insert(0, json!([]));
+            }
+            return self.send_raw_signature(body).await;
+        }
+
+        let group_id = uuid::Uuid::new_v4().to_string();
+        body.chord_size = Some(header.len());
+        let native_join = backend.supports_native_join();
+        if native_join {
+            backend.apply_chord(&group_id, header.len()).await?;
+        }
+        let results = self
+            .send_group_tasks(&group_id, header, Some(&body))
+            .await?;
+
+        if !native_join {
+            let queue = body.options.get("queue").cloned();
+            let mut kwargs = Map::new();
+            kwargs.insert("interval".into(), json!(1));
+            kwargs.insert("max_retries".into(), json!(null));
+            kwargs.insert(
+                "result".into(),
+                json!(results
+                    .iter()
+                    .map(AsyncResult::as_tuple)
+                    .collect::<Vec<_>>()),
+            );
+            let mut unlock = RawSignature::new(
+                ChordUnlockTask::NAME,
+                vec![json!(group_id), json!(body)],
+                kwargs,
+            );
+            unlock.options.insert("countdown".into(), json!(1));
+            if let Some(queue) = queue {
+                unlock.options.insert("queue".into(), queue);
+            }
+            self.send_raw_signature(unlock).await?;
+        }
+
+        Ok(self.async_result(&body_id))
+    }
+
+    /// Get a [`GroupResult`] for the group with the given ID and the given task results,
+    /// that saves the group to the app's result backend.
+    pub fn group_result(&self, group_id: &str, results: Vec<AsyncResult>) -> GroupResult {
+        let result = GroupResult::new(group_id, results);
+        match self.backend {
+            Some(ref backend) => result.with_backend(backend.clone()),
+            None => result,
+        }
+    }
+
+    /// Restore a group that was saved with [`GroupResult::save`], possibly by another app.
+    /// Returns `None` if the group wasn't saved or has expired.
+    pub async fn restore_group(&self, group_id: &str) -> Result<Option<GroupResult>, CeleryError> {
+        let backend = self.backend.as_ref().ok_or(BackendError::NotConfigured)?;
+        let meta = match backend.restore_group(group_id).await? {
+            Some(meta) => meta,
+            None => return Ok(None),
+        };
+        let tuple: ResultTuple = serde_json::from_value(meta.result).map_err(BackendError::from)?;
+        Ok(Some(GroupResult::from_tuple(tuple, Some(backend.clone()))))
+    }
+
+    /// Send a task by name with untyped arguments, for instance to call a task that is only
+    /// defined in Python without writing a matching Rust task. The task is routed and
+    /// serialized like tasks sent with [`Celery::send_task`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use celery::prelude::*;
+    /// # use serde_json::json;
+    /// # async fn send(app: &celery::Celery<AMQPBroker>) -> Result<(), CeleryError> {
+    /// let result = app
+    ///     .send_task_by_name("tasks.add", vec![json!(1), json!(2)], Default::default())
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_task_by_name(
+        &self,
+        name: &str,
+        args: Vec<Value>,
+        kwargs: Map<String, Value>,
+    ) -> Result<AsyncResult, CeleryError> {
+        self.send_raw_signature(RawSignature::new(name, args, kwargs))
+            .await
+    }
+
+    /// Send the task of an untyped signature. The options of the signature are interpreted
+    /// like the arguments of Python's `apply_async`, and the task is routed and serialized
+    /// like tasks sent with [`Celery::send_task`].
+    ///
+    /// Like with [`Celery::send_task`], the returned [`AsyncResult`] is the one of the last
+    /// task if the signature has a chain.
+    pub async fn send_raw_signature(
+        &self,
+        mut signature: RawSignature,
+    ) -> Result<AsyncResult, CeleryError> {
+        let chain_ids = signature.freeze_chain()?;
+        if let Some(content_type) = self.task_options.content_type {
+            signature
+                .options
+                .entry("serializer")
+                .or_insert_with(|| json!(content_type.serializer()));
+        }
+        let queue = match signature.option_str("queue") {
+            Some(queue) => queue.to_string(),
+            None => crate::routing::route(&signature.task, &self.task_routes)
+                .unwrap_or(&self.default_queue)
+                .to_string(),
+        };
+        let message = Message::try_from(signature)?;
+        let result = self.send_message(message, &queue).await?;
+        Ok(chain_ids.iter().fold(result, |parent, task_id| {
+            self.async_result(task_id).with_parent(parent)
+        }))
+    }
+
+    async fn send_message(
+        &self,
+        mut message: Message,
+        queue: &str,
+    ) -> Result<AsyncResult, CeleryError> {
+        if message.properties.reply_to.is_none() {
+            if let Some(ref backend) = self.backend {
+                message.properties.reply_to = backend.reply_to().await?;
+            }
+        }
         info!(
             "Sending task {}[{}] to {}",
-            T::NAME,
+            message.headers.task,
             message.task_id(),
             queue,
         );
         self.broker.send(&message, queue).await?;
-        Ok(AsyncResult::new(message.task_id()))
+        if self.send_sent_event {
+            let mut fields = events::task_fields(&message);
+            fields["queue"] = json!(queue);
+            fields["exchange"] = json!("");
+            fields["routing_key"] = json!(queue);
+            self.events.send_event("task-sent", fields).await;
+        }
+        Ok(self.async_result(message.task_id()))
+    }
+
+    /// Get an [`AsyncResult`] for the task with the given ID that retrieves results through
+    /// the app's result backend.
+    pub fn async_result(&self, task_id: &str) -> AsyncResult {
+        let result = AsyncResult::new(task_id).with_control(self.mailbox.clone());
+        match self.backend {
+            Some(ref backend) => result.with_backend(backend.clone()),
+            None => result,
+        }
     }
 
     /// Register a task.
@@ -345,17 +927,94 @@ where
         }
     }
 
-    async fn get_task_tracer(
+    /// Register a handler for the task with the given name. The handler is called with the
+    /// positional and keyword arguments of the task as raw JSON values, and its return value
+    /// is the result of the task.
+    ///
+    /// This lets a worker execute tasks sent by other apps, possibly written in Python,
+    /// without defining a [`Task`] type for them. Handled tasks are
+    /// [`DynamicTask`](crate::task::DynamicTask)s, so they use the default task options of the
+    /// app.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use celery::prelude::*;
+    /// # use serde_json::json;
+    /// # async fn register(app: &celery::Celery<AMQPBroker>) -> Result<(), CeleryError> {
+    /// app.register_task_handler("tasks.add", |args, _kwargs| async move {
+    ///     let sum: i64 = args.iter().filter_map(|arg| arg.as_i64()).sum();
+    ///     Ok(json!(sum))
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn register_task_handler<F, Fut>(
         &self,
+        name: &str,
+        handler: F,
+    ) -> Result<(), CeleryError>
+    where
+        F: Fn(Vec<Value>, Map<String, Value>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = TaskResult<Value>> + Send + 'static,
+    {
+        let mut task_trace_builders = self.task_trace_builders.write().await;
+        if task_trace_builders.contains_key(name) {
+            return Err(CeleryError::TaskRegistrationError(name.into()));
+        }
+        // Task names are static. This is only leaked once per registered task.
+        let name: &'static str = Box::leak(name.to_owned().into_boxed_str());
+        let handler: DynamicHandler = Arc::new(move |args, kwargs| handler(args, kwargs).boxed());
+        task_trace_builders.insert(
+            name.into(),
+            Box::new(move |message, options, event_tx, context| {
+                build_dynamic_tracer(name, handler.clone(), message, options, event_tx, context)
+            }),
+        );
+        debug!("Registered task handler {}", name);
+        Ok(())
+    }
+
+    async fn get_task_tracer(
+        self: &Arc<Self>,
         message: Message,
+        queue: &str,
         event_tx: UnboundedSender<TaskEvent>,
     ) -> Result<Box<dyn TracerTrait>, Box<dyn Error + Send + Sync + 'static>> {
         let task_trace_builders = self.task_trace_builders.read().await;
         if let Some(build_tracer) = task_trace_builders.get(&message.headers.task) {
-            Ok(
-                build_tracer(message, self.task_options, event_tx, self.hostname.clone())
-                    .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?,
+            // Built-in tasks apply other tasks in place, which would tie up a child process
+            // while other children execute them.
+            let prefork = match self.pool {
+                Some(ref pool) if !message.headers.task.starts_with("celery.") => Some(ChildTask {
+                    pool: pool.clone(),
+                    message: serde_json::from_slice(&message.json_serialized()?)
+                        .map_err(ProtocolError::from)?,
+                }),
+                _ => None,
+            };
+            Ok(build_tracer(
+                message,
+                self.task_options,
+                event_tx,
+                TraceContext {
+                    hostname: self.hostname.clone(),
+                    queue: Some(queue.into()),
+                    backend: self.backend.clone(),
+                    result_extended: self.result_extended,
+                    sender: self.clone(),
+                    blocking_permits: Some(self.blocking_permits.clone()),
+                    replaceable: prefork.is_none(),
+                    prefork,
+                    events: if self.send_events {
+                        Some(self.events.clone())
+                    } else {
+                        None
+                    },
+                },
             )
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?)
         } else {
             Err(
                 Box::new(CeleryError::UnregisteredTaskError(message.headers.task))
@@ -364,11 +1023,98 @@ where
         }
     }
 
+    /// Build the tracer of a task that is executed in place instead of being traced by the
+    /// worker: the tasks applied by `celery.map` and the tasks of child processes of the pool.
+    async fn local_tracer(
+        self: Arc<Self>,
+        message: Message,
+        queue: Option<String>,
+    ) -> TaskResult<Box<dyn TracerTrait>> {
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let task_trace_builders = self.task_trace_builders.read().await;
+        let build_tracer = task_trace_builders
+            .get(&message.headers.task)
+            .ok_or_else(|| {
+                TaskError::UnexpectedError(format!(
+                    "task {} isn't registered",
+                    message.headers.task
+                ))
+            })?;
+        build_tracer(
+            message,
+            self.task_options,
+            event_tx,
+            TraceContext {
+                hostname: self.hostname.clone(),
+                queue,
+                backend: self.backend.clone(),
+                result_extended: self.result_extended,
+                sender: self.clone(),
+                blocking_permits: Some(self.blocking_permits.clone()),
+                replaceable: false,
+                prefork: None,
+                events: None,
+            },
+        )
+        .map_err(|e| TaskError::UnexpectedError(format!("invalid signature: {}", e)))
+    }
+
+    /// Discard the delivery of a revoked task, storing its `REVOKED` state. `delayed` tells
+    /// whether the prefetch count was increased for it.
+    async fn discard_revoked(
+        &self,
+        delivery: &B::Delivery,
+        tracer: &dyn TracerTrait,
+        delayed: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+        tracer.revoke().await;
+        self.broker
+            .ack(delivery)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?;
+        if delayed {
+            self.broker
+                .decrease_prefetch_count()
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?;
+        }
+        Ok(())
+    }
+
+    /// Send a delivery that the worker gives up on to the dead-letter queue, if there is one.
+    /// Failures are logged, since the delivery is dropped either way.
+    async fn dead_letter(
+        &self,
+        delivery: &B::Delivery,
+        queue: &str,
+        reason: DeadLetterReason,
+        error: String,
+    ) {
+        if let Some(ref dead_letter_queue) = self.dead_letter_queue {
+            let dead_letter = DeadLetter {
+                reason,
+                queue: queue.into(),
+                error,
+            };
+            if let Err(e) = self
+                .broker
+                .send_dead_letter(delivery, dead_letter_queue, &dead_letter)
+                .await
+            {
+                error!(
+                    "Failed to send message to dead-letter queue {}: {}",
+                    dead_letter_queue, e
+                );
+            }
+        }
+    }
+
     /// Tries converting a delivery into a `Message`, executing the corresponding task,
     /// and communicating with the broker.
     async fn try_handle_delivery(
-        &self,
+        self: &Arc<Self>,
         delivery: B::Delivery,
+        queue: &str,
         event_tx: UnboundedSender<TaskEvent>,
     ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
         // Coerce the delivery into a protocol message.
@@ -376,7 +1122,14 @@ where
             Ok(message) => message,
             Err(e) => {
                 // This is a naughty message that we can't handle, so we'll ack it with
-                // the broker so it gets deleted.
+                // the broker so it gets deleted, or moved to the dead-letter queue.
+                self.dead_letter(
+                    &delivery,
+                    queue,
+                    DeadLetterReason::Undeserializable,
+                    e.to_string(),
+                )
+                .await;
                 self.broker
                     .ack(&delivery)
                     .await
@@ -385,15 +1138,31 @@ where
             }
         };
 
+        // Keep track of the task until it's done, for remote control commands.
+        let task_id = message.headers.id.clone();
+        let task_name = message.headers.task.clone();
+        let tracked = self.worker_state.track(&message, &self.hostname, queue);
+        let received = if self.send_events {
+            Some(events::task_fields(&message))
+        } else {
+            None
+        };
+
         // Try deserializing the message to create a task wrapped in a task tracer.
         // (The tracer handles all of the logic of directly interacting with the task
         // to execute it and run the post-execution functions).
-        let mut tracer = match self.get_task_tracer(message, event_tx).await {
+        let mut tracer = match self.get_task_tracer(message, queue, event_tx).await {
             Ok(tracer) => tracer,
             Err(e) => {
                 // Even though the message meta data was okay, we failed to deserialize
-                // the body of the message for some reason, so ack it with the broker
-                // to delete it and return an error.
+                // the body of the message or the task isn't registered, so ack it with
+                // the broker to delete it and return an error.
+                let reason = match e.downcast_ref::<CeleryError>() {
+                    Some(CeleryError::UnregisteredTaskError(_)) => DeadLetterReason::Unregistered,
+                    _ => DeadLetterReason::Undeserializable,
+                };
+                self.dead_letter(&delivery, queue, reason, e.to_string())
+                    .await;
                 self.broker
                     .ack(&delivery)
                     .await
@@ -402,6 +1171,17 @@ where
             }
         };
 
+        if let Some(received) = received {
+            self.events.send_event("task-received", received).await;
+        }
+
+        // Revoked tasks are discarded instead of being executed.
+        if self.worker_state.is_revoked(&task_id) {
+            return self
+                .discard_revoked(&delivery, tracer.as_ref(), false)
+                .await;
+        }
+
         if tracer.is_delayed() {
             // Task has an ETA, so we need to increment the prefetch count so that
             // we can receive other tasks while we wait for the ETA.
@@ -423,8 +1203,29 @@ where
             };
 
             // Then wait for the task to be ready.
+            tracked.schedule();
             tracer.wait().await;
+            tracked.reserve();
+        }
+
+        // Wait until the task is allowed to execute by its rate limit and the concurrency
+        // limit. The message isn't acknowledged before that, so it is delivered again if the
+        // worker stops in the meantime.
+        self.rate_limits
+            .acquire(&task_name, tracer.rate_limit())
+            .await;
+        let permit = match self.task_permits {
+            Some(ref task_permits) => task_permits.acquire().await.ok(),
+            None => None,
+        };
+
+        // The task may have been revoked while it was waiting.
+        if self.worker_state.is_revoked(&task_id) {
+            return self
+                .discard_revoked(&delivery, tracer.as_ref(), tracer.is_delayed())
+                .await;
         }
+        tracked.start(!tracer.acks_late(), tracer.abort_handle());
 
         // If acks_late is false, we acknowledge the message before tracing it.
         if !tracer.acks_late() {
@@ -438,13 +1239,26 @@ where
         // NOTE: we don't need to log errors from the trace here since the tracer
         // handles all errors at it's own level or the task level. In this function
         // we only log errors at the broker and delivery level.
-        if let Err(TraceError::Retry(retry_eta)) = tracer.trace().await {
-            // If retry error -> retry the task.
-            self.broker
-                .retry(&delivery, retry_eta)
-                .await
-                .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?;
+        match tracer.trace().await {
+            Err(TraceError::Retry(retry_eta)) => {
+                // If retry error -> retry the task.
+                self.broker
+                    .retry(&delivery, retry_eta)
+                    .await
+                    .map_err(|e| Box::new(e) as Box<dyn Error + Send + Sync + 'static>)?;
+            }
+            Err(TraceError::TaskError(e)) if tracer.retries_exhausted() => {
+                self.dead_letter(
+                    &delivery,
+                    queue,
+                    DeadLetterReason::RetriesExhausted,
+                    e.to_string(),
+                )
+                .await;
+            }
+            _ => {}
         }
+        drop(permit);
 
         // If we have not done it before, we have to acknowledge the message now.
         if tracer.acks_late() {
@@ -470,13 +1284,25 @@ where
     async fn handle_delivery(
         self: Arc<Self>,
         delivery: B::Delivery,
+        queue: String,
         event_tx: UnboundedSender<TaskEvent>,
     ) {
-        if let Err(e) = self.try_handle_delivery(delivery, event_tx).await {
+        if let Err(e) = self.try_handle_delivery(delivery, &queue, event_tx).await {
             error!("{}", e);
         }
     }
 
+    /// Send the messages of the dead-letter queue (see [`CeleryBuilder::dead_letter_queue`])
+    /// back to the queues they were consumed from, so that they are executed again. Returns
+    /// the number of replayed messages.
+    pub async fn replay_dead_letters(&self) -> Result<usize, CeleryError> {
+        let queue = self
+            .dead_letter_queue
+            .as_ref()
+            .ok_or(CeleryError::NoDeadLetterQueue)?;
+        Ok(self.broker.replay_dead_letters(queue).await?)
+    }
+
     /// Close channels and connections.
     pub async fn close(&self) -> Result<(), CeleryError> {
         Ok(self.broker.close().await?)
@@ -489,7 +1315,15 @@ where
     }
 
     /// Consume tasks from any number of queues.
+    ///
+    /// In a child process of the pool of a worker (see [`CeleryBuilder::prefork`]), this
+    /// executes the tasks that the worker sends instead, until the worker stops.
     pub async fn consume_from(self: &Arc<Self>, queues: &[&str]) -> Result<(), CeleryError> {
+        #[cfg(unix)]
+        if let Some(fd) = prefork::child_socket() {
+            return self.serve_pool(fd).await;
+        }
+
         loop {
             let result = self.clone()._consume_from(queues).await;
             if !self.broker_connection_retry {
@@ -546,6 +1380,10 @@ where
             return Err(CeleryError::NoQueueToConsume);
         }
 
+        if let Some(ref pool) = self.pool {
+            pool.start().await?;
+        }
+
         info!("Consuming from {:?}", queues);
 
         // Stream of errors from broker. The capacity here is arbitrary because a single
@@ -553,33 +1391,46 @@ where
         let (broker_error_tx, mut broker_error_rx) = mpsc::channel::<BrokerError>(100);
 
         // Stream of deliveries from the queue.
-        let mut stream_map = StreamMap::new();
-        let mut consumer_tags = vec![];
+        let mut consumers = Consumers::new(broker_error_tx);
         for queue in queues {
-            let broker_error_tx = broker_error_tx.clone();
+            consumers.add(self.broker.as_ref(), queue).await?;
+        }
 
-            let (consumer_tag, consumer) = self
+        // Stream of remote control commands.
+        let mut control_stream = if self.enable_remote_control {
+            match self
                 .broker
-                .consume(
-                    queue,
-                    Box::new(move |e| {
-                        broker_error_tx.clone().try_send(e).ok();
-                    }),
-                )
-                .await?;
-            stream_map.insert(queue, Box::pin(consumer));
-            consumer_tags.push(consumer_tag);
-        }
+                .consume_control(&self.control_exchange, &self.hostname)
+                .await
+            {
+                Ok(control_stream) => control_stream.fuse().boxed(),
+                Err(e) => {
+                    error!("Failed to consume remote control commands: {}", e);
+                    stream::pending().boxed()
+                }
+            }
+        } else {
+            stream::pending().boxed()
+        };
 
         // Stream of OS signals.
         let mut ender = Ender::new()?;
 
+        // Heartbeats that tell monitors the worker is alive, if it sends events.
+        self.send_worker_event("worker-online").await;
+        let mut heartbeat =
+            time::interval_at(time::Instant::now() + HEARTBEAT_FREQ, HEARTBEAT_FREQ);
+
         // A sender and receiver for task related events.
         // NOTE: we can use an unbounded channel since we already have backpressure
         // from the `prefetch_count` setting.
         let (task_event_tx, mut task_event_rx) = mpsc::unbounded_channel::<TaskEvent>();
         let mut pending_tasks = 0;
 
+        // When tasks are revoked, they're saved to the state file of the worker a moment
+        // later, in the background.
+        let mut save_revoked_at = None;
+
         // This is the main loop where we receive deliveries and pass them off
         // to be handled by spawning `self.handle_delivery`.
         // At the same time we are also listening for a SIGINT (Ctrl+C) or SIGTERM interruption.
@@ -588,13 +1439,13 @@ where
         // tasks being delayed due to a future ETA).
         loop {
             select! {
-                maybe_delivery_result = stream_map.next() => {
+                maybe_delivery_result = consumers.streams.next(), if !consumers.streams.is_empty() => {
                     if let Some((queue, delivery_result)) = maybe_delivery_result {
                         match delivery_result {
                             Ok(delivery) => {
                                 let task_event_tx = task_event_tx.clone();
                                 debug!("Received delivery from {}: {:?}", queue, delivery);
-                                tokio::spawn(self.clone().handle_delivery(delivery, task_event_tx));
+                                tokio::spawn(self.clone().handle_delivery(delivery, queue, task_event_tx));
                             }
                             Err(e) => {
                                 error!("Deliver failed: {}", e);
@@ -609,6 +1460,23 @@ where
                     info!("Warm shutdown...");
                     break;
                 },
+                _ = heartbeat.tick(), if self.send_events => {
+                    self.send_worker_event("worker-heartbeat").await;
+                },
+                _ = self.worker_state.revoked_changed() => {
+                    save_revoked_at.get_or_insert_with(|| time::Instant::now() + SAVE_REVOKED_DELAY);
+                },
+                _ = time::sleep_until(save_revoked_at.unwrap_or_else(time::Instant::now)), if save_revoked_at.is_some() => {
+                    save_revoked_at = None;
+                    let app = self.clone();
+                    tokio::spawn(async move { app.worker_state.save_revoked().await });
+                },
+                Some(message) = control_stream.next() => {
+                    if self.handle_control(message, &mut consumers).await {
+                        info!("Warm shutdown...");
+                        break;
+                    }
+                },
                 maybe_task_event = task_event_rx.recv() => {
                     if let Some(event) = maybe_task_event {
                         debug!("Received task event {:?}", event);
@@ -628,10 +1496,7 @@ where
         }
 
         // Cancel consumers.
-        for consumer_tag in consumer_tags {
-            debug!("Cancelling consumer {}", consumer_tag);
-            self.broker.cancel(&consumer_tag).await?;
-        }
+        consumers.cancel_all(self.broker.as_ref()).await?;
 
         if pending_tasks > 0 {
             // Warm shutdown loop. When there are still pending tasks we wait for them
@@ -643,6 +1508,7 @@ where
                     ending = ender.wait() => {
                         if let Ok(SigType::Interrupt) = ending {
                             warn!("Okay fine, shutting down now. See ya!");
+                            self.worker_state.save_revoked().await;
                             return Err(CeleryError::ForcedShutdown);
                         }
                     },
@@ -662,12 +1528,76 @@ where
             }
         }
 
+        self.worker_state.save_revoked().await;
+        self.send_worker_event("worker-offline").await;
         info!("No more pending tasks. See ya!");
 
         Ok(())
     }
 }
 
+/// The consumers of the queues that a worker consumes from.
+struct Consumers<B: Broker> {
+    /// The deliveries from each queue.
+    streams: StreamMap<String, Pin<Box<B::DeliveryStream>>>,
+
+    /// The consumer tag of each queue.
+    tags: HashMap<String, String>,
+
+    /// Where the consumers send broker errors.
+    broker_error_tx: mpsc::Sender<BrokerError>,
+}
+
+impl<B: Broker> Consumers<B> {
+    fn new(broker_error_tx: mpsc::Sender<BrokerError>) -> Self {
+        Self {
+            streams: StreamMap::new(),
+            tags: HashMap::new(),
+            broker_error_tx,
+        }
+    }
+
+    /// Start consuming from a queue. Returns `false` if the queue was already consumed from.
+    async fn add(&mut self, broker: &B, queue: &str) -> Result<bool, BrokerError> {
+        if self.tags.contains_key(queue) {
+            return Ok(false);
+        }
+        let broker_error_tx = self.broker_error_tx.clone();
+        let (consumer_tag, consumer) = broker
+            .consume(
+                queue,
+                Box::new(move |e| {
+                    broker_error_tx.clone().try_send(e).ok();
+                }),
+            )
+            .await?;
+        self.streams.insert(queue.into(), Box::pin(consumer));
+        self.tags.insert(queue.into(), consumer_tag);
+        Ok(true)
+    }
+
+    /// Stop consuming from a queue. Returns `false` if the queue wasn't consumed from.
+    async fn cancel(&mut self, broker: &B, queue: &str) -> Result<bool, BrokerError> {
+        match self.tags.remove(queue) {
+            Some(consumer_tag) => {
+                debug!("Cancelling consumer {}", consumer_tag);
+                self.streams.remove(queue);
+                broker.cancel(&consumer_tag).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn cancel_all(&mut self, broker: &B) -> Result<(), BrokerError> {
+        let queues: Vec<String> = self.tags.keys().cloned().collect();
+        for queue in queues {
+            self.cancel(broker, &queue).await?;
+        }
+        Ok(())
+    }
+}
+
 #[allow(unused)]
 enum SigType {
     /// Equivalent to SIGINT on unix systems.
@@ -727,3 +1657,31 @@ impl Ender {
 
 #[cfg(test)]
 mod tests;
+
+#[async_trait]
+impl<B> TaskSender for Celery<B>
+where
+    B: Broker + 'static,
+{
+    async fn send_signature(&self, signature: RawSignature) -> Result<AsyncResult, CeleryError> {
+        self.send_raw_signature(signature).await
+    }
+
+    async fn send_chord(&self, chord: Chord) -> Result<AsyncResult, CeleryError> {
+        Celery::send_chord(self, chord).await
+    }
+
+    async fn apply_signature(self: Arc<Self>, signature: RawSignature) -> TaskResult<Value> {
+        let message = Message::try_from(signature)
+            .map_err(|e| TaskError::UnexpectedError(format!("invalid signature: {}", e)))?;
+        let task_name = message.headers.task.clone();
+        let tracer = self.clone().local_tracer(message, None).await?;
+
+        // The task runs within the concurrency limit of the task that applies it, but it
+        // waits for its own rate limit.
+        self.rate_limits
+            .acquire(&task_name, tracer.rate_limit())
+            .await;
+        tracer.run().await
+    }
+}
@@ -0,0 +1,291 @@
+//! Running tasks in child processes, see [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork).
+
+use async_trait::async_trait;
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any::Any;
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::process::{Child, Command};
+use tokio::sync::Semaphore;
+use tokio::time::{self, Duration};
+
+use super::trace::ChildPool;
+use super::Celery;
+use crate::broker::Broker;
+use crate::error::{CeleryError, ProtocolError, TaskError};
+use crate::protocol::Delivery;
+use crate::task::TaskResult;
+
+/// The environment variable that tells a program that it runs as a child process of the pool,
+/// holding the file descriptor of its socket to the worker.
+const CHILD_SOCKET_ENV: &str = "RUSTY_CELERY_PREFORK_SOCKET";
+
+/// The socket to the worker, if this process is a child process of the pool.
+pub(super) fn child_socket() -> Option<RawFd> {
+    std::env::var(CHILD_SOCKET_ENV).ok()?.parse().ok()
+}
+
+/// A task sent to a child process.
+#[derive(Serialize, Deserialize)]
+struct Job {
+    message: Value,
+    queue: Option<String>,
+}
+
+/// The child processes that run tasks for a worker, like Python's prefork pool.
+///
+/// The children run the program of the worker again, which builds the same app and executes
+/// the tasks that the worker sends it instead of consuming (see [`child_socket`]). Each child
+/// executes one task at a time, so at most `size` tasks run at the same time.
+pub(super) struct ProcessPool {
+    size: usize,
+
+    /// The program that children run, and its arguments. This is the worker itself.
+    program: PathBuf,
+    args: Vec<OsString>,
+
+    idle: Mutex<Vec<ChildProcess>>,
+    permits: Semaphore,
+    started: AtomicBool,
+}
+
+impl ProcessPool {
+    pub(super) fn new(size: usize) -> io::Result<Self> {
+        Ok(Self::with_program(
+            size,
+            std::env::current_exe()?,
+            std::env::args_os().skip(1).collect(),
+        ))
+    }
+
+    pub(super) fn with_program(size: usize, program: PathBuf, args: Vec<OsString>) -> Self {
+        Self {
+            size,
+            program,
+            args,
+            idle: Mutex::new(Vec::with_capacity(size)),
+            permits: Semaphore::new(size),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    fn spawn(&self) -> io::Result<ChildProcess> {
+        let (socket, child_socket) = std::os::unix::net::UnixStream::pair()?;
+        let fd = child_socket.as_raw_fd();
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .env(CHILD_SOCKET_ENV, fd.to_string())
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        // Only the copy of the socket in the new process is kept open by `exec`. Clearing
+        // the flag is async-signal-safe, so it can run between `fork` and `exec`.
+        unsafe {
+            command.pre_exec(move || {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let process = command.spawn()?;
+        drop(child_socket);
+        debug!("Started child process {:?}", process.id());
+        socket.set_nonblocking(true)?;
+        Ok(ChildProcess {
+            process,
+            socket: UnixStream::from_std(socket)?,
+        })
+    }
+}
+
+#[async_trait]
+impl ChildPool for ProcessPool {
+    async fn start(&self) -> Result<(), CeleryError> {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let children = (0..self.size)
+            .map(|_| self.spawn())
+            .collect::<io::Result<Vec<_>>>()?;
+        self.idle.lock().unwrap().extend(children);
+        info!("Started {} child processes", self.size);
+        Ok(())
+    }
+
+    async fn execute(
+        &self,
+        message: &Value,
+        queue: Option<&str>,
+        hard_time_limit: Option<u32>,
+    ) -> TaskResult<Value> {
+        let job = serde_json::to_vec(&Job {
+            message: message.clone(),
+            queue: queue.map(String::from),
+        })
+        .map_err(|e| TaskError::UnexpectedError(format!("failed to serialize task: {}", e)))?;
+
+        let _permit = self.permits.acquire().await;
+        // Children that were lost are replaced when they are needed, so the pool can
+        // recover from failing to start them.
+        let idle = self.idle.lock().unwrap().pop();
+        let mut child = match idle {
+            Some(child) => child,
+            None => self.spawn().map_err(|e| {
+                TaskError::UnexpectedError(format!("failed to start child process: {}", e))
+            })?,
+        };
+        // If this is cancelled, for example when the task is terminated, the child is killed
+        // when it's dropped.
+        match child.execute(&job, hard_time_limit).await {
+            Ok(result) => {
+                self.idle.lock().unwrap().push(child);
+                result
+            }
+            Err(reason) => {
+                match self.spawn() {
+                    Ok(child) => self.idle.lock().unwrap().push(child),
+                    Err(e) => error!("Failed to replace lost child process: {}", e),
+                }
+                Err(TaskError::WorkerLostError(reason))
+            }
+        }
+    }
+}
+
+struct ChildProcess {
+    process: Child,
+    socket: UnixStream,
+}
+
+impl ChildProcess {
+    /// Send a job to the child and return its result, or why the child was lost.
+    async fn execute(
+        &mut self,
+        job: &[u8],
+        hard_time_limit: Option<u32>,
+    ) -> Result<TaskResult<Value>, String> {
+        let socket = &mut self.socket;
+        let exchange = async {
+            write_frame(socket, job).await?;
+            read_frame(socket).await
+        };
+        let response = match hard_time_limit {
+            Some(secs) => match time::timeout(Duration::from_secs(secs as u64), exchange).await {
+                Ok(response) => response,
+                Err(_) => {
+                    self.process.kill().await.ok();
+                    return Err(format!("hard time limit ({}s) exceeded", secs));
+                }
+            },
+            None => exchange.await,
+        };
+        match response {
+            Ok(response) => match serde_json::from_slice(&response) {
+                Ok(result) => Ok(result),
+                Err(e) => {
+                    self.process.kill().await.ok();
+                    Err(format!("invalid result from worker: {}", e))
+                }
+            },
+            // The child closes its socket when it exits.
+            Err(_) => Err(match self.process.wait().await {
+                Ok(status) => describe_status(status),
+                Err(_) => "worker exited prematurely".into(),
+            }),
+        }
+    }
+}
+
+/// Describe how a child process exited, like Python's `WorkerLostError`.
+fn describe_status(status: ExitStatus) -> String {
+    match status.signal() {
+        Some(signal) => format!("worker exited prematurely: signal {}", signal),
+        None => format!(
+            "worker exited prematurely: exitcode {}",
+            status.code().unwrap_or_default()
+        ),
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Write a frame of the protocol between the worker and its children: a length followed by
+/// that many bytes of JSON.
+async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> io::Result<()> {
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(frame).await
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = reader.read_u32().await?;
+    let mut frame = vec![0; len as usize];
+    reader.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+impl<B> Celery<B>
+where
+    B: Broker + 'static,
+{
+    /// Execute the tasks that the worker sends over the socket `fd`, one at a time, until the
+    /// worker closes it. This is what child processes of the pool do instead of consuming.
+    pub(super) async fn serve_pool(self: &Arc<Self>, fd: RawFd) -> Result<(), CeleryError> {
+        // Like in Python, children leave it to the worker to handle Ctrl-C, which the
+        // terminal sends to them too.
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) };
+        let socket = unsafe { std::os::unix::net::UnixStream::from_raw_fd(fd) };
+        socket.set_nonblocking(true)?;
+        let mut socket = UnixStream::from_std(socket)?;
+        debug!("Executing tasks for the worker");
+        loop {
+            let job = match read_frame(&mut socket).await {
+                Ok(job) => job,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let result = self.execute_job(&job).await;
+            let result = serde_json::to_vec(&result).map_err(ProtocolError::from)?;
+            write_frame(&mut socket, &result).await?;
+        }
+    }
+
+    async fn execute_job(self: &Arc<Self>, job: &[u8]) -> TaskResult<Value> {
+        let job: Job = serde_json::from_slice(job)
+            .map_err(|e| TaskError::UnexpectedError(format!("invalid task: {}", e)))?;
+        let message = serde_json::from_value::<Delivery>(job.message)
+            .map_err(ProtocolError::from)
+            .and_then(|delivery| delivery.try_deserialize_message())
+            .map_err(|e| TaskError::UnexpectedError(format!("invalid task: {}", e)))?;
+        let tracer = self.clone().local_tracer(message, job.queue).await?;
+        // A task that panics only fails, instead of taking down the child.
+        tokio::spawn(async move { tracer.run_in_child().await })
+            .await
+            .unwrap_or_else(|e| {
+                Err(TaskError::UnexpectedError(if e.is_panic() {
+                    format!("task panicked: {}", panic_message(e.into_panic().as_ref()))
+                } else {
+                    "task was cancelled".into()
+                }))
+            })
+    }
+}
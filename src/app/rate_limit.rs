@@ -0,0 +1,103 @@
+//! Enforcing the rate limits of tasks, see [`TaskOptions::rate_limit`](crate::task::TaskOptions::rate_limit).
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{self, Duration, Instant};
+
+use crate::task::RateLimit;
+
+/// A token bucket like kombu's `TokenBucket`, which Python workers use to enforce rate
+/// limits. Like in Python, it holds a single token so tasks don't run in bursts.
+struct TokenBucket {
+    rate_limit: RateLimit,
+
+    /// The number of tokens added per second.
+    fill_rate: f64,
+
+    tokens: f64,
+    timestamp: Instant,
+}
+
+impl TokenBucket {
+    const CAPACITY: f64 = 1.0;
+
+    fn new(rate_limit: RateLimit, fill_rate: f64) -> Self {
+        Self {
+            rate_limit,
+            fill_rate,
+            tokens: Self::CAPACITY,
+            timestamp: Instant::now(),
+        }
+    }
+
+    /// Take a token if there is one, or return how long to wait until there is one.
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        if self.tokens < Self::CAPACITY {
+            let delta = self.fill_rate * (now - self.timestamp).as_secs_f64();
+            self.tokens = Self::CAPACITY.min(self.tokens + delta);
+        }
+        self.timestamp = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.fill_rate,
+            ))
+        }
+    }
+}
+
+/// The rate limits that a worker enforces, with a token bucket per task.
+#[derive(Default)]
+pub(super) struct RateLimiter {
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+
+    /// The rate limits set at runtime with the `rate_limit` remote control command, which
+    /// replace the rate limits of the task options.
+    overrides: Mutex<HashMap<String, RateLimit>>,
+}
+
+impl RateLimiter {
+    /// Change the rate limit of a task at runtime. A rate of `0` disables the limit.
+    pub(super) fn set(&self, task_name: &str, rate_limit: RateLimit) {
+        self.overrides
+            .lock()
+            .unwrap()
+            .insert(task_name.into(), rate_limit);
+    }
+
+    /// Wait until the task is allowed to run by its rate limit, which is `default` unless it
+    /// was changed at runtime.
+    pub(super) async fn acquire(&self, task_name: &str, default: Option<RateLimit>) {
+        loop {
+            // The rate limit may change while waiting.
+            let rate_limit = match self.overrides.lock().unwrap().get(task_name) {
+                Some(rate_limit) => *rate_limit,
+                None => match default {
+                    Some(rate_limit) => rate_limit,
+                    None => return,
+                },
+            };
+            let fill_rate = match rate_limit.per_second() {
+                Some(fill_rate) => fill_rate,
+                None => return,
+            };
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let bucket = buckets
+                    .entry(task_name.into())
+                    .or_insert_with(|| TokenBucket::new(rate_limit, fill_rate));
+                if bucket.rate_limit != rate_limit {
+                    *bucket = TokenBucket::new(rate_limit, fill_rate);
+                }
+                match bucket.take() {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            time::sleep(wait).await;
+        }
+    }
+}
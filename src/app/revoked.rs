@@ -0,0 +1,113 @@
+//! The IDs of revoked tasks, see [`AsyncResult::revoke`](crate::task::AsyncResult::revoke).
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs;
+use uuid::Uuid;
+
+/// How many revoked IDs are kept at most, like Python's `REVOKES_MAX`.
+pub(super) const REVOKES_MAX: usize = 50_000;
+
+/// How long revoked IDs are kept, in seconds, like Python's `REVOKE_EXPIRES`.
+const REVOKE_EXPIRES: u64 = 10_800;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The IDs of the tasks that were revoked recently, with the time they were revoked at.
+///
+/// Like Python's `LimitedSet`, the IDs are kept in the order they were revoked, so that the
+/// oldest ones are forgotten first without sorting them.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub(super) struct RevokedSet {
+    /// The revoked IDs with the time they were revoked at, oldest first. An ID that was
+    /// revoked again also keeps its previous entry, which is skipped when it's forgotten.
+    revoked: VecDeque<(String, u64)>,
+
+    /// The position of the last entry of each ID, counted from the first entry ever added,
+    /// and the time it was revoked at.
+    #[serde(skip)]
+    latest: HashMap<String, (u64, u64)>,
+
+    /// How many entries were forgotten, which is the position of the first entry.
+    #[serde(skip)]
+    forgotten: u64,
+}
+
+impl RevokedSet {
+    /// Load the revoked IDs saved in the state file at `path`, if it exists.
+    pub(super) async fn load(path: &Path) -> io::Result<Self> {
+        let mut set: Self = match fs::read(path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(e),
+        };
+        set.latest = set
+            .revoked
+            .iter()
+            .enumerate()
+            .map(|(position, (task_id, revoked_at))| {
+                (task_id.clone(), (position as u64, *revoked_at))
+            })
+            .collect();
+        set.purge();
+        Ok(set)
+    }
+
+    /// Save the revoked IDs to the state file at `path`, atomically. Each save writes its
+    /// own temporary file, so saves that overlap, for example of workers that share the
+    /// state file, can't corrupt it.
+    pub(super) async fn save(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_vec(self)?;
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(format!(".{}.tmp", Uuid::new_v4()));
+        fs::write(&tmp_path, data).await?;
+        if let Err(e) = fs::rename(&tmp_path, path).await {
+            fs::remove_file(&tmp_path).await.ok();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    pub(super) fn add(&mut self, task_id: &str) {
+        let now = now();
+        let position = self.forgotten + self.revoked.len() as u64;
+        self.revoked.push_back((task_id.into(), now));
+        self.latest.insert(task_id.into(), (position, now));
+        self.purge();
+    }
+
+    pub(super) fn contains(&self, task_id: &str) -> bool {
+        self.latest
+            .get(task_id)
+            .map(|(_, revoked_at)| now().saturating_sub(*revoked_at) < REVOKE_EXPIRES)
+            .unwrap_or(false)
+    }
+
+    /// Forget the IDs that have expired, and the oldest ones beyond [`REVOKES_MAX`].
+    fn purge(&mut self) {
+        let now = now();
+        while let Some((task_id, revoked_at)) = self.revoked.front() {
+            let outdated =
+                self.latest.get(task_id).map(|(position, _)| *position) != Some(self.forgotten);
+            let expired = now.saturating_sub(*revoked_at) >= REVOKE_EXPIRES;
+            if !outdated && !expired && self.latest.len() <= REVOKES_MAX {
+                break;
+            }
+            if let Some((task_id, _)) = self.revoked.pop_front() {
+                if !outdated {
+                    self.latest.remove(&task_id);
+                }
+            }
+            self.forgotten += 1;
+        }
+    }
+}
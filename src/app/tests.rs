@@ -1,11 +1,28 @@
-use super::Celery;
-use crate::broker::mock::MockBroker;
-use crate::protocol::MessageContentType;
-use crate::task::{Request, Signature, Task, TaskOptions, TaskResult};
+use super::chord::ChordUnlockTask;
+use super::map::{MapTask, StarmapTask};
+use super::revoked::{RevokedSet, REVOKES_MAX};
+use super::trace::{build_tracer, TraceContext};
+use super::{Celery, CeleryBuilder, Consumers};
+use crate::backend::{ResultBackend, TaskMeta};
+use crate::broker::mock::{Delivery, MockBroker, MockBrokerBuilder};
+use crate::error::{BackendError, CeleryError, TaskError, TraceError};
+use crate::protocol::{
+    ControlMessage, DeadLetter, DeadLetterReason, Event, Message, MessageBuilder,
+    MessageContentType,
+};
+use crate::task::{
+    DynamicTask, RawSignature, Request, Signature, Task, TaskOptions, TaskResult, TaskState,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::SystemTime;
+use tokio::sync::{mpsc, RwLock};
 
 async fn build_basic_app() -> Celery<MockBroker> {
     let celery = Celery::<MockBroker>::builder("mock-app", "mock://localhost:8000")
@@ -30,6 +47,77 @@ async fn build_configured_app() -> Celery<MockBroker> {
     celery
 }
 
+/// Build an app on the mock broker, configured by `configure`.
+async fn build_app<F>(configure: F) -> Arc<Celery<MockBroker>>
+where
+    F: FnOnce(CeleryBuilder<MockBrokerBuilder>) -> CeleryBuilder<MockBrokerBuilder>,
+{
+    let builder = Celery::<MockBroker>::builder("mock-app", "mock://localhost:8000")
+        .hostname("mock-app@host");
+    Arc::new(configure(builder).build().await.unwrap())
+}
+
+/// Build an app like [`build_app`] that stores results in a [`RecordingBackend`].
+async fn build_recording_app<F>(configure: F) -> (Arc<Celery<MockBroker>>, Arc<RecordingBackend>)
+where
+    F: FnOnce(CeleryBuilder<MockBrokerBuilder>) -> CeleryBuilder<MockBrokerBuilder>,
+{
+    let mut app = build_app(configure).await;
+    let backend = Arc::new(RecordingBackend::default());
+    Arc::get_mut(&mut app).unwrap().backend = Some(backend.clone());
+    (app, backend)
+}
+
+/// What a worker did when it handled a delivery, see [`handle_delivery`].
+struct Handled {
+    result: Result<(), Box<dyn std::error::Error + Send + Sync>>,
+
+    /// The meta data stored for the task, if the app has a result backend.
+    meta: Option<TaskMeta>,
+
+    /// The events and dead letters sent to the broker while handling the delivery.
+    events: Vec<Event>,
+    dead_letters: Vec<(Delivery, String, DeadLetter)>,
+}
+
+/// Handle a delivery from the `celery` queue like a worker of `app` would.
+async fn handle_delivery(app: &Arc<Celery<MockBroker>>, delivery: Delivery) -> Handled {
+    let events = app.broker.events.read().await.len();
+    let dead_letters = app.broker.dead_letters.read().await.len();
+    let task_id = delivery
+        .0
+        .as_ref()
+        .map(|message| message.task_id().to_string());
+    let (event_tx, _event_rx) = mpsc::unbounded_channel();
+    let result = app.try_handle_delivery(delivery, "celery", event_tx).await;
+    let meta = match (&app.backend, task_id) {
+        (Some(backend), Some(task_id)) => Some(backend.get_task_meta(&task_id).await.unwrap()),
+        _ => None,
+    };
+    Handled {
+        result,
+        meta,
+        events: app
+            .broker
+            .events
+            .read()
+            .await
+            .iter()
+            .skip(events)
+            .cloned()
+            .collect(),
+        dead_letters: app
+            .broker
+            .dead_letters
+            .read()
+            .await
+            .iter()
+            .skip(dead_letters)
+            .cloned()
+            .collect(),
+    }
+}
+
 // We can't use the #[task] macro from inside the crate, unfortunately, so we have
 // to implement tasks the old fashion way.
 
@@ -98,6 +186,9 @@ impl Task for MultiplyTask {
         max_retry_delay: None,
         retry_for_unexpected: None,
         acks_late: None,
+        ignore_result: None,
+        track_started: None,
+        rate_limit: None,
         content_type: None,
     };
 
@@ -127,6 +218,272 @@ struct MultiplyParams {
     y: i32,
 }
 
+struct FailingTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+impl FailingTask {
+    fn new() -> Signature<Self> {
+        Signature::<Self>::new(FailingParams {})
+    }
+}
+
+#[async_trait]
+impl Task for FailingTask {
+    const NAME: &'static str = "failing";
+    const ARGS: &'static [&'static str] = &[];
+
+    type Params = FailingParams;
+    type Returns = ();
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, _params: Self::Params) -> TaskResult<Self::Returns> {
+        Err(TaskError::UnexpectedError("oops".into()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct FailingParams {}
+
+/// A task whose result can't be serialized to JSON, since its keys aren't strings.
+struct UnserializableTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+#[async_trait]
+impl Task for UnserializableTask {
+    const NAME: &'static str = "unserializable";
+    const ARGS: &'static [&'static str] = &[];
+
+    type Params = FailingParams;
+    type Returns = HashMap<(i32, i32), i32>;
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, _params: Self::Params) -> TaskResult<Self::Returns> {
+        Ok(vec![((1, 2), 3)].into_iter().collect())
+    }
+}
+
+struct ProgressTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+impl ProgressTask {
+    fn new() -> Signature<Self> {
+        Signature::<Self>::new(ProgressParams {})
+    }
+}
+
+#[async_trait]
+impl Task for ProgressTask {
+    const NAME: &'static str = "progress";
+    const ARGS: &'static [&'static str] = &[];
+
+    type Params = ProgressParams;
+    type Returns = ();
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, _params: Self::Params) -> TaskResult<Self::Returns> {
+        self.update_state(
+            TaskState::Custom("PROGRESS".into()),
+            json!({"current": 40, "total": 100}),
+        )
+        .await
+        .unwrap();
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ProgressParams {}
+
+/// Adds pairs of numbers by replacing itself with an `add` task for a single pair, or with a
+/// chord of `add` tasks for several pairs.
+struct SplitTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+impl SplitTask {
+    fn new(pairs: Vec<(i32, i32)>) -> Signature<Self> {
+        Signature::<Self>::new(SplitParams { pairs })
+    }
+}
+
+#[async_trait]
+impl Task for SplitTask {
+    const NAME: &'static str = "split";
+    const ARGS: &'static [&'static str] = &["pairs"];
+
+    type Params = SplitParams;
+    type Returns = i32;
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<Self::Returns> {
+        match params.pairs[..] {
+            // Only the returned error replaces the task.
+            [] => {
+                let _ = self.replace(AddTask::new(0, 0));
+                Ok(0)
+            }
+            [(x, y)] => self.replace(AddTask::new(x, y).then(Signature::<MultiplyTask>::partial(
+                json!({"y": 1}).as_object().unwrap().clone(),
+            ))),
+            _ => self.replace(crate::canvas::chord(
+                crate::canvas::group(params.pairs.iter().map(|&(x, y)| AddTask::new(x, y))),
+                Signature::<AddTask>::partial(json!({"y": 0}).as_object().unwrap().clone()),
+            )),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SplitParams {
+    pairs: Vec<(i32, i32)>,
+}
+
+struct BlockingTask {
+    request: Request<Self>,
+    options: TaskOptions,
+}
+
+impl BlockingTask {
+    fn new(sleep_ms: u64, panic: bool) -> Signature<Self> {
+        Signature::<Self>::new(BlockingParams { sleep_ms, panic })
+    }
+}
+
+#[async_trait]
+impl Task for BlockingTask {
+    const NAME: &'static str = "blocking";
+    const ARGS: &'static [&'static str] = &["sleep_ms", "panic"];
+    const BLOCKING: bool = true;
+
+    type Params = BlockingParams;
+    type Returns = ();
+
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self { request, options }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<Self::Returns> {
+        self.run_blocking(move || {
+            std::thread::sleep(std::time::Duration::from_millis(params.sleep_ms));
+            if params.panic {
+                panic!("oops");
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BlockingParams {
+    sleep_ms: u64,
+    panic: bool,
+}
+
+/// A result backend that just records everything stored in it.
+#[derive(Default)]
+struct RecordingBackend {
+    results: RwLock<HashMap<String, TaskMeta>>,
+    history: RwLock<Vec<TaskMeta>>,
+
+    /// Whether the backend behaves like the `rpc://` backend, which doesn't support chords.
+    without_chords: bool,
+}
+
+#[async_trait]
+impl ResultBackend for RecordingBackend {
+    fn safe_url(&self) -> String {
+        "recording://".into()
+    }
+
+    fn supports_chords(&self) -> bool {
+        !self.without_chords
+    }
+
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError> {
+        self.history.write().await.push(meta.clone());
+        self.results
+            .write()
+            .await
+            .insert(meta.task_id.clone(), meta.clone());
+        Ok(())
+    }
+
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError> {
+        Ok(self
+            .results
+            .read()
+            .await
+            .get(task_id)
+            .cloned()
+            .unwrap_or_else(|| TaskMeta::pending(task_id)))
+    }
+
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError> {
+        self.results.write().await.remove(task_id);
+        Ok(())
+    }
+}
+
 #[tokio::test]
 async fn test_app_name() {
     let app = build_basic_app().await;
@@ -233,6 +590,129 @@ async fn test_send_task_with_hard_time_limit() {
     assert!(message.headers.timelimit == (Some(5), None));
 }
 
+#[tokio::test]
+async fn test_send_task_chain() {
+    let app = build_basic_app().await;
+    let result = app
+        .send_task(AddTask::new(1, 2).then(Signature::<MultiplyTask>::partial(
+            json!({"y": 3}).as_object().unwrap().clone(),
+        )))
+        .await
+        .unwrap();
+    let parent = result.parent.as_ref().unwrap();
+    assert!(parent.parent.is_none());
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let message = &sent_tasks.get(&parent.task_id).unwrap().0;
+    assert_eq!(message.headers.task, "add");
+    let (_, embed) = message.body::<AddTask>().unwrap().parts();
+    assert_eq!(
+        serde_json::to_value(embed.chain).unwrap(),
+        json!([{
+            "task": "multiply",
+            "args": [],
+            "kwargs": {"y": 3},
+            "options": {"task_id": result.task_id, "soft_time_limit": 5, "time_limit": 10},
+            "subtask_type": null,
+            "immutable": false,
+            "chord_size": null,
+        }])
+    );
+}
+
+#[tokio::test]
+async fn test_send_group() {
+    let app = build_basic_app().await;
+    let group = crate::group![
+        AddTask::new(1, 2),
+        MultiplyTask::new(3, 4).then(Signature::<AddTask>::partial(
+            json!({"y": 5}).as_object().unwrap().clone(),
+        )),
+    ];
+    let result = app.send_group(group).await.unwrap();
+    assert_eq!(result.results.len(), 2);
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let message = &sent_tasks.get(&result.results[0].task_id).unwrap().0;
+    assert_eq!(message.headers.task, "add");
+    assert_eq!(message.headers.group.as_ref(), Some(&result.id));
+    assert_eq!(message.headers.group_index, Some(0));
+
+    // The result of a chain is the one of its last task, which is the group member.
+    let chain_result = &result.results[1];
+    let parent = chain_result.parent.as_ref().unwrap();
+    let message = &sent_tasks.get(&parent.task_id).unwrap().0;
+    assert_eq!(message.headers.task, "multiply");
+    assert_eq!(message.headers.group, None);
+    let (_, embed) = message.body::<MultiplyTask>().unwrap().parts();
+    let chain = embed.chain.unwrap();
+    assert_eq!(chain[0].task, "add");
+    assert_eq!(
+        chain[0].option_str("task_id"),
+        Some(chain_result.task_id.as_str())
+    );
+    assert_eq!(chain[0].option_str("group_id"), Some(result.id.as_str()));
+    assert_eq!(chain[0].options["group_index"], json!(1));
+}
+
+#[tokio::test]
+async fn test_group_result() {
+    let app = Celery::<MockBroker>::builder("mock-app", "mock://localhost:8000")
+        .result_backend("memory://group-result")
+        .build()
+        .await
+        .unwrap();
+    let result = app
+        .send_group(crate::canvas::group(vec![
+            AddTask::new(1, 2),
+            AddTask::new(3, 4),
+        ]))
+        .await
+        .unwrap();
+    assert_eq!(result.completed_count().await.unwrap(), 0);
+    assert!(!result.ready().await.unwrap());
+
+    let backend = app.backend.as_ref().unwrap();
+    backend
+        .store_result(&TaskMeta::success(&result.results[1].task_id, json!(7)))
+        .await
+        .unwrap();
+    assert_eq!(result.completed_count().await.unwrap(), 1);
+    backend
+        .store_result(&TaskMeta::success(&result.results[0].task_id, json!(3)))
+        .await
+        .unwrap();
+    assert!(result.successful().await.unwrap());
+    assert_eq!(
+        result
+            .join::<i32>(Some(std::time::Duration::from_secs(1)))
+            .await
+            .unwrap(),
+        vec![3, 7]
+    );
+
+    assert!(app.restore_group(&result.id).await.unwrap().is_none());
+    result.save().await.unwrap();
+    let restored = app.restore_group(&result.id).await.unwrap().unwrap();
+    assert_eq!(restored.id, result.id);
+    assert_eq!(
+        restored
+            .results
+            .iter()
+            .map(|result| &result.task_id)
+            .collect::<Vec<_>>(),
+        result
+            .results
+            .iter()
+            .map(|result| &result.task_id)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(restored.completed_count().await.unwrap(), 2);
+
+    restored.delete().await.unwrap();
+    assert!(app.restore_group(&result.id).await.unwrap().is_none());
+}
+
 #[tokio::test]
 async fn test_configured_app_send_task_app_defaults() {
     let app = build_configured_app().await;
@@ -269,3 +749,1720 @@ async fn test_configured_app_send_task_request_overrides() {
     assert!(message.headers.timelimit == (Some(10), Some(2)));
     assert!(message.properties.content_type == "application/json");
 }
+
+#[tokio::test]
+async fn test_trace_stores_success() {
+    let (app, backend) = build_recording_app(|builder| builder).await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app).await.unwrap();
+
+    let meta = backend.get_task_meta(&task_id).await.unwrap();
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!(3));
+    assert!(meta.date_done.is_some());
+}
+
+#[tokio::test]
+async fn test_trace_stores_retry() {
+    let (app, backend) = build_recording_app(|builder| builder).await;
+    let message = Message::try_from(FailingTask::new()).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<FailingTask>(message, app)
+        .await
+        .unwrap_err();
+
+    let meta = backend.get_task_meta(&task_id).await.unwrap();
+    assert_eq!(meta.status, TaskState::Retry);
+}
+
+#[tokio::test]
+async fn test_trace_stores_failure() {
+    let (app, backend) = build_recording_app(|builder| builder.task_max_retries(0)).await;
+    let message = Message::try_from(FailingTask::new()).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<FailingTask>(message, app)
+        .await
+        .unwrap_err();
+
+    let meta = backend.get_task_meta(&task_id).await.unwrap();
+    assert_eq!(meta.status, TaskState::Failure);
+    match meta.error() {
+        Some(TaskError::UnexpectedError(reason)) => assert_eq!(reason, "oops"),
+        other => panic!("unexpected error {:?}", other),
+    };
+}
+
+#[tokio::test]
+async fn test_trace_ignore_result() {
+    let (app, backend) = build_recording_app(|builder| builder.task_ignore_result(true)).await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app).await.unwrap();
+
+    assert!(!backend.results.read().await.contains_key(&task_id));
+}
+
+#[tokio::test]
+async fn test_trace_track_started() {
+    let (app, backend) = build_recording_app(|builder| builder.task_track_started(true)).await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    trace_with_app::<AddTask>(message, app).await.unwrap();
+
+    let history = backend.history.read().await;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].status, TaskState::Started);
+    assert_eq!(
+        history[0].result,
+        json!({"pid": std::process::id(), "hostname": "mock-app@host"})
+    );
+    assert_eq!(history[1].status, TaskState::Success);
+}
+
+#[tokio::test]
+async fn test_trace_update_state() {
+    let (app, backend) = build_recording_app(|builder| builder).await;
+    let message = Message::try_from(ProgressTask::new()).unwrap();
+    trace_with_app::<ProgressTask>(message, app).await.unwrap();
+
+    let history = backend.history.read().await;
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].status, TaskState::Custom("PROGRESS".into()));
+    assert_eq!(history[0].result, json!({"current": 40, "total": 100}));
+    assert_eq!(history[1].status, TaskState::Success);
+}
+
+#[tokio::test]
+async fn test_trace_sends_next_link() {
+    let app = Arc::new(build_basic_app().await);
+    let partial = |y: i32| json!({ "y": y }).as_object().unwrap().clone();
+    let message = Message::try_from(
+        AddTask::new(1, 2)
+            .then(Signature::<MultiplyTask>::partial(partial(3)))
+            .then(Signature::<AddTask>::partial(partial(4))),
+    )
+    .unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let (message, queue, _) = sent_tasks.values().next().unwrap();
+    assert_eq!(queue, "celery");
+    assert_eq!(message.headers.task, "multiply");
+    assert_eq!(message.headers.parent_id, Some(task_id.clone()));
+    assert_eq!(message.headers.root_id, Some(task_id));
+    assert_eq!(message.headers.timelimit, (Some(10), Some(5)));
+
+    // The return value of the first task is the first parameter of the next one, and the
+    // rest of the chain is passed along.
+    let (params, embed) = message.body::<MultiplyTask>().unwrap().parts();
+    assert_eq!((params.x, params.y), (3, 3));
+    let chain = embed.chain.unwrap();
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].task, "add");
+    assert_eq!(chain[0].kwargs, partial(4));
+}
+
+#[tokio::test]
+async fn test_trace_immutable_next_link() {
+    let app = Arc::new(build_basic_app().await);
+    let mut next = RawSignature::try_from(MultiplyTask::new(2, 5)).unwrap();
+    next.immutable = true;
+    let message = MessageBuilder::<AddTask>::new("aaa".into())
+        .params(AddParams { x: 1, y: 2 })
+        .chain(vec![next])
+        .build()
+        .unwrap();
+    trace_with_app::<AddTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let (message, _, _) = sent_tasks.values().next().unwrap();
+    let (params, _) = message.body::<MultiplyTask>().unwrap().parts();
+    assert_eq!((params.x, params.y), (2, 5));
+}
+
+#[tokio::test]
+async fn test_trace_sends_callbacks() {
+    let app = Arc::new(build_basic_app().await);
+    let partial = json!({ "y": 3 }).as_object().unwrap().clone();
+    let message =
+        Message::try_from(AddTask::new(1, 2).link(Signature::<MultiplyTask>::partial(partial)))
+            .unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.values().next().unwrap().0;
+    assert_eq!(message.headers.task, "multiply");
+    assert_eq!(message.headers.parent_id, Some(task_id));
+    assert_eq!(message_args(message), (json!([3]), json!({"y": 3})));
+}
+
+#[tokio::test]
+async fn test_trace_sends_errbacks() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-errbacks")
+            .task_max_retries(0)
+    })
+    .await;
+    let message = MessageBuilder::<FailingTask>::new("aaa".into())
+        .params(FailingParams {})
+        .callbacks(vec![RawSignature::new(
+            "on_success",
+            vec![],
+            Default::default(),
+        )])
+        .errbacks(vec![RawSignature::new(
+            "on_error",
+            vec![json!("extra")],
+            Default::default(),
+        )])
+        .build()
+        .unwrap();
+    trace_with_app::<FailingTask>(message, app.clone())
+        .await
+        .unwrap_err();
+
+    // Only the errback is sent, with the ID of the task, whose error is stored.
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.values().next().unwrap().0;
+    assert_eq!(message.headers.task, "on_error");
+    assert_eq!(message.headers.parent_id, Some("aaa".into()));
+    assert_eq!(message_args(message).0, json!(["aaa", "extra"]));
+    let meta = app
+        .backend
+        .as_ref()
+        .unwrap()
+        .get_task_meta("aaa")
+        .await
+        .unwrap();
+    assert!(matches!(meta.error(), Some(TaskError::UnexpectedError(_))));
+}
+
+#[tokio::test]
+async fn test_trace_unserializable_result() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-unserializable")
+            .task_max_retries(0)
+            .send_events(true)
+    })
+    .await;
+    app.register_task::<UnserializableTask>().await.unwrap();
+    let message = MessageBuilder::<UnserializableTask>::new("aaa".into())
+        .params(FailingParams {})
+        .callbacks(vec![RawSignature::new(
+            "on_success",
+            vec![],
+            Default::default(),
+        )])
+        .errbacks(vec![RawSignature::new(
+            "on_error",
+            vec![],
+            Default::default(),
+        )])
+        .build()
+        .unwrap();
+    let handled = handle_delivery(&app, Delivery(Some(message))).await;
+    handled.result.unwrap();
+
+    // The task fails instead of staying pending.
+    let meta = handled.meta.unwrap();
+    assert_eq!(meta.status, TaskState::Failure);
+    assert!(
+        matches!(meta.error(), Some(TaskError::UnexpectedError(reason)) if reason.starts_with("failed to serialize result"))
+    );
+    let types: Vec<&str> = handled
+        .events
+        .iter()
+        .map(|e| e.event_type.as_str())
+        .collect();
+    assert_eq!(types, ["task-received", "task-started", "task-failed"]);
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    assert_eq!(
+        sent_tasks.values().next().unwrap().0.headers.task,
+        "on_error"
+    );
+}
+
+#[tokio::test]
+async fn test_send_task_by_name() {
+    let app = Celery::<MockBroker>::builder("mock-app", "mock://localhost:8000")
+        .task_content_type(MessageContentType::Yaml)
+        .task_route("tasks.*", "python")
+        .build()
+        .await
+        .unwrap();
+    let kwargs = json!({"y": 2}).as_object().unwrap().clone();
+    let result = app
+        .send_task_by_name("tasks.add", vec![json!(1)], kwargs.clone())
+        .await
+        .unwrap();
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let (message, queue, _) = sent_tasks.get(&result.task_id).unwrap();
+    assert_eq!(queue, "python");
+    assert_eq!(message.headers.task, "tasks.add");
+    assert!(message.headers.origin.is_some());
+    assert_eq!(message.properties.content_type, "application/x-yaml");
+    let (params, _) = message.raw_params().unwrap();
+    assert_eq!(params.args, vec![json!(1)]);
+    assert_eq!(params.kwargs, kwargs);
+}
+
+#[tokio::test]
+async fn test_trace_task_handler() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://task-handler")
+            .task_max_retries(0)
+    })
+    .await;
+    app.register_task_handler("tasks.add", |args, kwargs| async move {
+        let sum = args
+            .iter()
+            .chain(kwargs.values())
+            .filter_map(|arg| arg.as_i64())
+            .sum::<i64>();
+        Ok(json!(sum))
+    })
+    .await
+    .unwrap();
+    assert!(matches!(
+        app.register_task_handler("tasks.add", |_, _| async { Ok(json!(null)) })
+            .await,
+        Err(CeleryError::TaskRegistrationError(_))
+    ));
+
+    let kwargs = json!({"y": 2}).as_object().unwrap().clone();
+    let result = app
+        .send_task_by_name("tasks.add", vec![json!(1)], kwargs)
+        .await
+        .unwrap();
+    let message = app.broker.sent_tasks.read().await[&result.task_id]
+        .0
+        .clone();
+    let (event_tx, _event_rx) = mpsc::unbounded_channel();
+    let mut tracer = app
+        .get_task_tracer(message, "celery", event_tx)
+        .await
+        .unwrap();
+    tracer.trace().await.unwrap();
+
+    assert_eq!(result.get::<i64>(None).await.unwrap(), 3);
+    let meta = result.meta().await.unwrap();
+    assert_eq!(meta.status, TaskState::Success);
+}
+
+#[tokio::test]
+async fn test_trace_sends_next_link_to_upstream_param() {
+    let app = Arc::new(build_basic_app().await);
+    let partial = json!({ "x": 10 }).as_object().unwrap().clone();
+    let message = Message::try_from(
+        AddTask::new(1, 2)
+            .then(Signature::<MultiplyTask>::partial(partial))
+            .then(AddTask::new(5, 6).immutable()),
+    )
+    .unwrap();
+    trace_with_app::<AddTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    // The return value goes to the parameter that the partial signature leaves out.
+    let message = {
+        let sent_tasks = app.broker.sent_tasks.read().await;
+        let message = sent_tasks.values().next().unwrap().0.clone();
+        let (params, _) = message.body::<MultiplyTask>().unwrap().parts();
+        assert_eq!((params.x, params.y), (10, 3));
+        message
+    };
+    app.broker.reset().await;
+    trace_with_app::<MultiplyTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    // Immutable signatures don't receive it.
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let message = &sent_tasks.values().next().unwrap().0;
+    assert_eq!(message_args(message), (json!([]), json!({"x": 5, "y": 6})));
+}
+
+#[test]
+fn test_body_fills_missing_params() {
+    for content_type in [
+        MessageContentType::Json,
+        MessageContentType::Yaml,
+        MessageContentType::Pickle,
+    ] {
+        let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+            .content_type(content_type)
+            .args(vec![json!(5), json!(6)])
+            .kwargs(json!({"x": 1}).as_object().unwrap().clone())
+            .build()
+            .unwrap();
+        let (params, _) = message.body::<AddTask>().unwrap().parts();
+        assert_eq!((params.x, params.y), (1, 5));
+    }
+}
+
+/// Decode the positional and keyword arguments of a JSON message.
+#[tokio::test]
+async fn test_send_chunks() {
+    let app = build_basic_app().await;
+    let add = || Signature::<AddTask>::partial(Default::default());
+    let it = vec![
+        vec![json!(1), json!(2)],
+        vec![json!(3), json!(4)],
+        vec![json!(5), json!(6)],
+    ];
+    let result = app
+        .send_group(crate::canvas::chunks(add().with_queue("math"), it, 2))
+        .await
+        .unwrap();
+    assert_eq!(result.results.len(), 2);
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    let (message, queue, _) = sent_tasks.get(&result.results[1].task_id).unwrap();
+    assert_eq!(message.headers.task, "celery.starmap");
+    assert_eq!(queue, "math");
+    let params = message.body::<StarmapTask>().unwrap().1;
+    assert_eq!(params.task.task, "add");
+    assert_eq!(params.it, vec![vec![json!(5), json!(6)]]);
+}
+
+#[tokio::test]
+async fn test_trace_map() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-map")
+            .task_max_retries(0)
+    })
+    .await;
+    app.register_task::<AddTask>().await.unwrap();
+    app.register_task::<MultiplyTask>().await.unwrap();
+
+    let add = Signature::<AddTask>::partial(Default::default());
+    let it = vec![vec![json!(1), json!(2)], vec![json!(3), json!(4)]];
+    let message = Message::try_from(crate::canvas::starmap(add, it)).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<StarmapTask>(message, app.clone())
+        .await
+        .unwrap();
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert_eq!(meta.unwrap().result, json!([3, 7]));
+
+    let double = Signature::<MultiplyTask>::partial(json!({"y": 2}).as_object().unwrap().clone());
+    let message = Message::try_from(crate::canvas::map(double, vec![json!(1), json!(2)])).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<MapTask>(message, app.clone())
+        .await
+        .unwrap();
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert_eq!(meta.unwrap().result, json!([2, 4]));
+}
+
+#[tokio::test]
+async fn test_trace_map_rate_limit() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-map-rate-limit")
+            .task_max_retries(0)
+    })
+    .await;
+    app.register_task::<AddTask>().await.unwrap();
+    app.rate_limits.set("add", "10/s".parse().unwrap());
+
+    // The first run happens right away, and each next one once a token is available.
+    let add = Signature::<AddTask>::partial(Default::default());
+    let it = vec![vec![json!(1), json!(2)]; 3];
+    let message = Message::try_from(crate::canvas::starmap(add, it)).unwrap();
+    let start = std::time::Instant::now();
+    trace_with_app::<StarmapTask>(message, app.clone())
+        .await
+        .unwrap();
+    assert!(start.elapsed() >= std::time::Duration::from_millis(180));
+}
+
+#[tokio::test]
+async fn test_trace_map_unregistered_task() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-map-unregistered")
+            .task_max_retries(0)
+    })
+    .await;
+    let add = Signature::<AddTask>::partial(Default::default());
+    let message = Message::try_from(crate::canvas::map(add, vec![json!(1)])).unwrap();
+    let task_id = message.task_id().to_string();
+    assert!(trace_with_app::<MapTask>(message, app.clone())
+        .await
+        .is_err());
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert_eq!(meta.unwrap().status, TaskState::Failure);
+}
+
+#[tokio::test]
+async fn test_trace_map_replace() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-map-replace")
+            .task_max_retries(0)
+    })
+    .await;
+    app.register_task::<SplitTask>().await.unwrap();
+    let split = Signature::<SplitTask>::partial(Default::default());
+    let message = Message::try_from(crate::canvas::map(split, vec![json!([[1, 2]])])).unwrap();
+    let task_id = message.task_id().to_string();
+    assert!(trace_with_app::<MapTask>(message, app.clone())
+        .await
+        .is_err());
+
+    // Items of `celery.map` run in place, so they can't be replaced.
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert!(
+        matches!(meta.unwrap().error(), Some(TaskError::UnexpectedError(reason)) if reason.contains("can't be replaced"))
+    );
+    assert!(app.broker.sent_tasks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_trace_replace() {
+    let app = Arc::new(build_basic_app().await);
+    let message = Message::try_from(
+        SplitTask::new(vec![(1, 2)])
+            .link(MultiplyTask::new(3, 4))
+            .link_error(FailingTask::new())
+            .then(Signature::<AddTask>::partial(
+                json!({"y": 5}).as_object().unwrap().clone(),
+            )),
+    )
+    .unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<SplitTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.values().next().unwrap().0;
+    assert_eq!(message.headers.task, "add");
+    assert_ne!(message.task_id(), task_id);
+    assert_eq!(message.headers.root_id, Some(task_id.clone()));
+
+    // The last task of the replacement takes over the ID and the callbacks of the task, and
+    // the rest of the chain of the task runs after it.
+    let (_, embed) = message.body::<AddTask>().unwrap().parts();
+    let chain = embed.chain.unwrap();
+    assert_eq!(chain.len(), 2);
+    assert_eq!(chain[0].task, "add");
+    assert_eq!(chain[1].task, "multiply");
+    assert_eq!(chain[1].option_str("task_id"), Some(task_id.as_str()));
+    assert_eq!(chain[1].options["link"][0]["task"], "multiply");
+    assert_eq!(chain[1].options["link_error"][0]["task"], "failing");
+}
+
+#[tokio::test]
+async fn test_trace_replace_ignored() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-replace-ignored")
+            .task_max_retries(0)
+    })
+    .await;
+    let message = Message::try_from(SplitTask::new(vec![])).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<SplitTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    // The task returned a value, so it isn't replaced.
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert_eq!(meta.unwrap().result, json!(0));
+    assert!(app.broker.sent_tasks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_trace_replace_with_chord() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-replace-chord")
+            .task_max_retries(0)
+    })
+    .await;
+    let mut group_result = app
+        .send_group(crate::group![SplitTask::new(vec![(1, 2), (3, 4)])])
+        .await
+        .unwrap();
+    let task_id = group_result.results.remove(0).task_id;
+    let message = app.broker.sent_tasks.read().await[&task_id].0.clone();
+    app.broker.reset().await;
+    trace_with_app::<SplitTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    // The body of the chord takes over the ID of the task and its place in its group.
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 2);
+    for (message, _, _) in sent_tasks.values() {
+        assert_eq!(message.headers.task, "add");
+        let (_, embed) = message.body::<AddTask>().unwrap().parts();
+        let body = embed.chord.unwrap();
+        assert_eq!(body.option_str("task_id"), Some(task_id.as_str()));
+        assert_eq!(body.option_str("group_id"), Some(group_result.id.as_str()));
+    }
+    let meta = app.backend.as_ref().unwrap().get_task_meta(&task_id).await;
+    assert_eq!(meta.unwrap().status, TaskState::Pending);
+}
+
+fn message_args(message: &Message) -> (serde_json::Value, serde_json::Value) {
+    let body: serde_json::Value = serde_json::from_slice(&message.raw_body).unwrap();
+    (body[0].clone(), body[1].clone())
+}
+
+/// Build a tracer for the given message and trace it like a worker of `app` would.
+async fn trace_with_app<T: Task + Send + 'static>(
+    message: Message,
+    app: Arc<Celery<MockBroker>>,
+) -> Result<(), TraceError> {
+    let (event_tx, _event_rx) = mpsc::unbounded_channel();
+    let mut tracer = build_tracer::<T>(
+        message,
+        app.task_options,
+        event_tx,
+        TraceContext {
+            hostname: app.hostname.clone(),
+            queue: Some("celery".into()),
+            backend: app.backend.clone(),
+            result_extended: app.result_extended,
+            sender: app.clone(),
+            blocking_permits: Some(app.blocking_permits.clone()),
+            replaceable: true,
+            prefork: None,
+            events: None,
+        },
+    )
+    .unwrap();
+    tracer.trace().await
+}
+
+#[tokio::test]
+async fn test_send_chord() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://send-chord")
+            .task_max_retries(0)
+    })
+    .await;
+    let body = Signature::<MultiplyTask>::partial(json!({"y": 2}).as_object().unwrap().clone());
+    let result = app
+        .send_chord(crate::canvas::chord(
+            crate::group![AddTask::new(1, 2), AddTask::new(3, 4)],
+            body,
+        ))
+        .await
+        .unwrap();
+
+    // The memory backend joins chords natively, so only the header is sent.
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 2);
+    let mut group_indexes = vec![];
+    let mut group_ids = vec![];
+    for (message, _, _) in sent_tasks.values() {
+        assert_eq!(message.headers.task, "add");
+        group_indexes.push(message.headers.group_index.unwrap());
+        group_ids.push(message.headers.group.clone().unwrap());
+        let (_, embed) = message.body::<AddTask>().unwrap().parts();
+        let chord = embed.chord.unwrap();
+        assert_eq!(chord.task, "multiply");
+        assert_eq!(chord.chord_size, Some(2));
+        assert_eq!(chord.option_str("task_id"), Some(result.task_id.as_str()));
+    }
+    group_indexes.sort_unstable();
+    assert_eq!(group_indexes, vec![0, 1]);
+    assert_eq!(group_ids[0], group_ids[1]);
+}
+
+#[tokio::test]
+async fn test_send_chord_without_backend() {
+    let app = build_basic_app().await;
+    let result = app
+        .send_chord(crate::canvas::chord(
+            crate::group![AddTask::new(1, 2)],
+            MultiplyTask::new(1, 2),
+        ))
+        .await;
+    assert!(matches!(
+        result,
+        Err(CeleryError::BackendError(BackendError::NotConfigured))
+    ));
+}
+
+#[tokio::test]
+async fn test_send_chord_not_supported() {
+    let mut app = build_app(|builder| builder).await;
+    Arc::get_mut(&mut app).unwrap().backend = Some(Arc::new(RecordingBackend {
+        without_chords: true,
+        ..Default::default()
+    }));
+    let result = app
+        .send_chord(crate::canvas::chord(
+            crate::group![AddTask::new(1, 2)],
+            MultiplyTask::new(1, 2),
+        ))
+        .await;
+    assert!(matches!(
+        result,
+        Err(CeleryError::BackendError(BackendError::NotSupported(_)))
+    ));
+
+    // Nothing is sent, not even the header.
+    assert!(app.broker.sent_tasks.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_trace_chord() {
+    let producer = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-chord")
+            .task_max_retries(0)
+    })
+    .await;
+    let worker = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-chord")
+            .task_max_retries(0)
+    })
+    .await;
+    let body = Signature::<MultiplyTask>::partial(json!({"y": 2}).as_object().unwrap().clone());
+    let result = producer
+        .send_chord(crate::canvas::chord(
+            crate::group![AddTask::new(3, 4), AddTask::new(1, 2)],
+            body,
+        ))
+        .await
+        .unwrap();
+
+    let messages: Vec<Message> = producer
+        .broker
+        .sent_tasks
+        .read()
+        .await
+        .values()
+        .map(|(message, _, _)| message.clone())
+        .collect();
+    for message in messages {
+        assert!(worker.broker.sent_tasks.read().await.is_empty());
+        trace_with_app::<AddTask>(message, worker.clone())
+            .await
+            .unwrap();
+    }
+
+    // The body is sent once, after the last task of the header, with the results in the
+    // order of the header.
+    let sent_tasks = worker.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.get(&result.task_id).unwrap().0;
+    assert_eq!(message.headers.task, "multiply");
+    assert_eq!(message_args(message), (json!([[7, 3]]), json!({"y": 2})));
+}
+
+#[tokio::test]
+async fn test_trace_chord_failure() {
+    let producer = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-chord-failure")
+            .task_max_retries(0)
+    })
+    .await;
+    let worker = build_app(|builder| {
+        builder
+            .result_backend("memory://trace-chord-failure")
+            .task_max_retries(0)
+    })
+    .await;
+    let mut chord = crate::canvas::chord(
+        crate::group![AddTask::new(1, 2), FailingTask::new()],
+        MultiplyTask::new(1, 2),
+    );
+    chord.body.options.insert(
+        "link_error".into(),
+        json!([RawSignature::new("on_error", vec![], Default::default())]),
+    );
+    let result = producer.send_chord(chord).await.unwrap();
+
+    let messages: Vec<Message> = producer
+        .broker
+        .sent_tasks
+        .read()
+        .await
+        .values()
+        .map(|(message, _, _)| message.clone())
+        .collect();
+    for message in messages {
+        if message.headers.task == "add" {
+            trace_with_app::<AddTask>(message, worker.clone())
+                .await
+                .unwrap();
+        } else {
+            trace_with_app::<FailingTask>(message, worker.clone())
+                .await
+                .unwrap_err();
+        }
+    }
+
+    // The body fails instead of running, and its errbacks are sent.
+    let meta = result.meta().await.unwrap();
+    assert_eq!(meta.status, TaskState::Failure);
+    assert!(
+        matches!(meta.error(), Some(TaskError::UnexpectedError(reason)) if reason.starts_with("Dependency"))
+    );
+    let sent_tasks = worker.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.values().next().unwrap().0;
+    assert_eq!(message.headers.task, "on_error");
+    assert_eq!(message_args(message).0, json!([result.task_id]));
+}
+
+#[tokio::test]
+async fn test_send_chord_unlock() {
+    let (app, backend) = build_recording_app(|builder| builder).await;
+    let body = Signature::<MultiplyTask>::partial(json!({"y": 2}).as_object().unwrap().clone());
+    let result = app
+        .send_chord(crate::canvas::chord(
+            crate::group![AddTask::new(1, 2), AddTask::new(3, 4)],
+            body,
+        ))
+        .await
+        .unwrap();
+
+    // The recording backend doesn't join chords, so an unlock task is sent along with the
+    // header.
+    let unlock = app
+        .broker
+        .sent_tasks
+        .read()
+        .await
+        .values()
+        .map(|(message, _, _)| message.clone())
+        .find(|message| message.headers.task == "celery.chord_unlock")
+        .unwrap();
+    assert!(unlock.headers.eta.is_some());
+    let (args, kwargs) = message_args(&unlock);
+    assert_eq!(args[1]["task"], json!("multiply"));
+    let header_ids: Vec<String> = kwargs["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|tuple| tuple[0][0].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(header_ids.len(), 2);
+
+    // The unlock task retries until the header is ready.
+    app.broker.reset().await;
+    assert!(matches!(
+        trace_with_app::<ChordUnlockTask>(unlock.clone(), app.clone()).await,
+        Err(TraceError::Retry(Some(_)))
+    ));
+    for (task_id, value) in header_ids.iter().zip([3, 7].iter()) {
+        backend
+            .store_result(&TaskMeta::success(task_id, json!(value)))
+            .await
+            .unwrap();
+    }
+    trace_with_app::<ChordUnlockTask>(unlock, app.clone())
+        .await
+        .unwrap();
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks.len(), 1);
+    let message = &sent_tasks.get(&result.task_id).unwrap().0;
+    assert_eq!(message_args(message), (json!([[3, 7]]), json!({"y": 2})));
+}
+
+#[tokio::test]
+async fn test_trace_result_extended() {
+    let (app, backend) = build_recording_app(|builder| builder.result_extended(true)).await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app).await.unwrap();
+
+    let meta = backend.get_task_meta(&task_id).await.unwrap();
+    assert_eq!(meta.name, Some("add".into()));
+    assert_eq!(meta.args, Some(json!([])));
+    assert_eq!(meta.kwargs, Some(json!({"x": 1, "y": 2})));
+    assert_eq!(meta.worker, Some("mock-app@host".into()));
+    assert_eq!(meta.retries, Some(0));
+    assert_eq!(meta.queue, Some("celery".into()));
+}
+
+#[tokio::test]
+async fn test_trace_stores_reply_to() {
+    let (app, backend) = build_recording_app(|builder| builder).await;
+    let mut message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    message.properties.reply_to = Some("bbb".into());
+    let task_id = message.task_id().to_string();
+    trace_with_app::<AddTask>(message, app).await.unwrap();
+
+    let meta = backend.get_task_meta(&task_id).await.unwrap();
+    assert_eq!(meta.reply_to, Some("bbb".into()));
+}
+
+#[tokio::test]
+async fn test_async_result_without_backend() {
+    let app = build_basic_app().await;
+    let result = app.send_task(AddTask::new(1, 2)).await.unwrap();
+    assert!(matches!(
+        result.state().await,
+        Err(BackendError::NotConfigured)
+    ));
+}
+
+#[tokio::test]
+async fn test_async_result_get() {
+    let (app, _) = build_recording_app(|builder| builder).await;
+    let result = app.send_task(AddTask::new(1, 2)).await.unwrap();
+    assert_eq!(result.state().await.unwrap(), TaskState::Pending);
+    assert!(!result.ready().await.unwrap());
+
+    let message = app
+        .broker
+        .sent_tasks
+        .read()
+        .await
+        .get(&result.task_id)
+        .unwrap()
+        .0
+        .clone();
+    trace_with_app::<AddTask>(message, app.clone())
+        .await
+        .unwrap();
+
+    assert!(result.ready().await.unwrap());
+    assert!(result.successful().await.unwrap());
+    assert!(!result.failed().await.unwrap());
+    let value: i32 = result
+        .get(Some(std::time::Duration::from_secs(1)))
+        .await
+        .unwrap();
+    assert_eq!(value, 3);
+
+    result.forget().await.unwrap();
+    assert_eq!(result.state().await.unwrap(), TaskState::Pending);
+}
+
+#[tokio::test]
+async fn test_async_result_get_failure() {
+    let (app, backend) = build_recording_app(|builder| builder.task_max_retries(0)).await;
+    let message = Message::try_from(FailingTask::new()).unwrap();
+    let task_id = message.task_id().to_string();
+    trace_with_app::<FailingTask>(message, app)
+        .await
+        .unwrap_err();
+
+    let result = crate::task::AsyncResult::new(&task_id).with_backend(backend);
+    assert!(result.failed().await.unwrap());
+    match result.get::<()>(None).await {
+        Err(BackendError::TaskFailed(TaskError::UnexpectedError(reason))) => {
+            assert_eq!(reason, "oops")
+        }
+        other => panic!("unexpected result {:?}", other),
+    };
+}
+
+#[tokio::test]
+async fn test_async_result_get_timeout() {
+    let (app, _) = build_recording_app(|builder| builder).await;
+    let result = app.async_result("aaa");
+    let timeout = std::time::Duration::from_millis(50);
+    assert!(matches!(
+        result
+            .wait::<i32>(Some(timeout), std::time::Duration::from_millis(10))
+            .await,
+        Err(BackendError::Timeout)
+    ));
+}
+
+#[tokio::test]
+async fn test_concurrency() {
+    let app = build_app(|builder| builder.concurrency(2)).await;
+    let running = Arc::new(AtomicUsize::new(0));
+    let max_running = Arc::new(AtomicUsize::new(0));
+    {
+        let (running, max_running) = (running.clone(), max_running.clone());
+        app.register_task_handler("sleep", move |_, _| {
+            let (running, max_running) = (running.clone(), max_running.clone());
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(json!(null))
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    let mut deliveries = vec![];
+    for _ in 0..5 {
+        let result = app
+            .send_task_by_name("sleep", vec![], Default::default())
+            .await
+            .unwrap();
+        let message = app.broker.sent_tasks.read().await[&result.task_id]
+            .0
+            .clone();
+        deliveries.push(Delivery(Some(message)));
+    }
+    let handled = futures::future::join_all(
+        deliveries
+            .into_iter()
+            .map(|delivery| handle_delivery(&app, delivery)),
+    )
+    .await;
+    assert!(handled.iter().all(|handled| handled.result.is_ok()));
+    assert_eq!(max_running.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_trace_blocking_task_time_limit() {
+    let app = build_app(|builder| builder.task_max_retries(0)).await;
+    let message = Message::try_from(BlockingTask::new(2000, false).with_time_limit(1)).unwrap();
+    assert!(matches!(
+        trace_with_app::<BlockingTask>(message, app).await,
+        Err(TraceError::TaskError(TaskError::TimeoutError))
+    ));
+}
+
+#[tokio::test]
+async fn test_trace_blocking_task_panic() {
+    let app = build_app(|builder| builder.task_max_retries(0)).await;
+    let message = Message::try_from(BlockingTask::new(0, true)).unwrap();
+    assert!(matches!(
+        trace_with_app::<BlockingTask>(message, app).await,
+        Err(TraceError::TaskError(TaskError::UnexpectedError(_)))
+    ));
+}
+
+/// Run the tasks of `app` in a pool of `size` child processes, and register the tasks of
+/// the prefork tests.
+#[cfg(unix)]
+async fn use_test_pool(app: &mut Arc<Celery<MockBroker>>, size: usize) {
+    // The children run the test binary again, but only the `prefork_child` test.
+    Arc::get_mut(app).unwrap().pool = Some(Arc::new(super::prefork::ProcessPool::with_program(
+        size,
+        std::env::current_exe().unwrap(),
+        vec![
+            "--exact".into(),
+            "app::tests::prefork_child".into(),
+            "--test-threads=1".into(),
+            "--quiet".into(),
+        ],
+    )));
+    app.register_task::<AddTask>().await.unwrap();
+    app.register_task_handler("panicking", |_, _| async { panic!("oops") })
+        .await
+        .unwrap();
+    app.register_task_handler("sleeping", |_, _| async {
+        // Block without yielding, so only killing the task stops it.
+        std::thread::sleep(std::time::Duration::from_secs(10));
+        Ok(json!(null))
+    })
+    .await
+    .unwrap();
+    app.register_task_handler("pid", |_, _| async { Ok(json!(std::process::id())) })
+        .await
+        .unwrap();
+}
+
+/// The child processes of the pools of the prefork tests run this test, which executes the
+/// tasks that the pool sends it. It does nothing when it's run by itself.
+#[cfg(unix)]
+#[tokio::test]
+async fn prefork_child() {
+    if super::prefork::child_socket().is_some() {
+        let mut app = build_app(|builder| {
+            builder
+                .result_backend("memory://prefork-child")
+                .task_max_retries(0)
+                .prefork(true)
+        })
+        .await;
+        use_test_pool(&mut app, 1).await;
+        app.consume().await.unwrap();
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_prefork() {
+    let mut app = build_app(|builder| {
+        builder
+            .result_backend("memory://prefork")
+            .task_max_retries(0)
+            .prefork(true)
+    })
+    .await;
+    use_test_pool(&mut app, 2).await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let meta = handle_delivery(&app, Delivery(Some(message)))
+        .await
+        .meta
+        .unwrap();
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!(3));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_prefork_panic() {
+    let mut app = build_app(|builder| {
+        builder
+            .result_backend("memory://prefork-panic")
+            .task_max_retries(0)
+            .prefork(true)
+    })
+    .await;
+    use_test_pool(&mut app, 1).await;
+    let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+        .task("panicking".into())
+        .args(vec![])
+        .build()
+        .unwrap();
+    let meta = handle_delivery(&app, Delivery(Some(message)))
+        .await
+        .meta
+        .unwrap();
+    assert!(
+        matches!(meta.error(), Some(TaskError::UnexpectedError(reason)) if reason == "task panicked: oops")
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_prefork_hard_time_limit() {
+    let mut app = build_app(|builder| {
+        builder
+            .result_backend("memory://prefork-hard-time-limit")
+            .task_max_retries(0)
+            .prefork(true)
+    })
+    .await;
+    use_test_pool(&mut app, 1).await;
+    let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+        .task("sleeping".into())
+        .args(vec![])
+        .hard_time_limit(1)
+        .build()
+        .unwrap();
+    let meta = handle_delivery(&app, Delivery(Some(message)))
+        .await
+        .meta
+        .unwrap();
+    assert!(matches!(meta.error(), Some(TaskError::WorkerLostError(_))));
+
+    // The killed child is replaced.
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let meta = handle_delivery(&app, Delivery(Some(message)))
+        .await
+        .meta
+        .unwrap();
+    assert_eq!(meta.result, json!(3));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_prefork_pool() {
+    let mut app = build_app(|builder| {
+        builder
+            .result_backend("memory://prefork-pool")
+            .task_max_retries(0)
+            .prefork(true)
+    })
+    .await;
+    use_test_pool(&mut app, 1).await;
+    app.pool.as_ref().unwrap().start().await.unwrap();
+    let mut pids = vec![];
+    for task_id in &["aaa", "bbb"] {
+        let message = MessageBuilder::<DynamicTask>::new(task_id.to_string())
+            .task("pid".into())
+            .args(vec![])
+            .build()
+            .unwrap();
+        pids.push(
+            handle_delivery(&app, Delivery(Some(message)))
+                .await
+                .meta
+                .unwrap()
+                .result,
+        );
+    }
+    // Tasks run in the same long-lived child.
+    assert_eq!(pids[0], pids[1]);
+    assert_ne!(pids[0], json!(std::process::id()));
+}
+
+/// Send a remote control command to `app` and return its reply.
+async fn control_with_app(
+    app: &Arc<Celery<MockBroker>>,
+    consumers: &mut Consumers<MockBroker>,
+    method: &str,
+    arguments: serde_json::Value,
+) -> Option<serde_json::Value> {
+    let message: ControlMessage = serde_json::from_value(json!({
+        "method": method,
+        "arguments": arguments,
+        "destination": ["mock-app@host"],
+        "reply_to": {"exchange": "reply.celery.pidbox", "routing_key": "client"},
+        "ticket": "ticket",
+    }))
+    .unwrap();
+    app.broker.reset().await;
+    app.handle_control(message, consumers).await;
+    let replies = app.broker.control_replies.read().await;
+    replies.first().map(|(reply_to, ticket, reply)| {
+        assert_eq!(reply_to.routing_key, "client");
+        assert_eq!(ticket, "ticket");
+        reply["mock-app@host"].clone()
+    })
+}
+
+fn build_consumers() -> Consumers<MockBroker> {
+    let (broker_error_tx, _) = mpsc::channel(1);
+    Consumers::new(broker_error_tx)
+}
+
+#[tokio::test]
+async fn test_control_ping() {
+    let app = build_app(|builder| builder).await;
+    app.register_task::<AddTask>().await.unwrap();
+    app.register_task::<MultiplyTask>().await.unwrap();
+    let mut consumers = build_consumers();
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "ping", json!({})).await,
+        Some(json!({"ok": "pong"}))
+    );
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "registered", json!({})).await,
+        Some(json!(["add", "multiply"]))
+    );
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "unknown", json!({})).await,
+        Some(json!({"error": "No such method: unknown"}))
+    );
+
+    // Commands for other workers are ignored.
+    let message: ControlMessage = serde_json::from_value(json!({
+        "method": "ping",
+        "destination": ["other@host"],
+        "reply_to": {"exchange": "reply.celery.pidbox", "routing_key": "client"},
+        "ticket": "ticket",
+    }))
+    .unwrap();
+    app.broker.reset().await;
+    assert!(!app.handle_control(message, &mut consumers).await);
+    assert!(app.broker.control_replies.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_control_inspect_tasks() {
+    let app = build_app(|builder| builder).await;
+    let mut consumers = build_consumers();
+    let notify = Arc::new(tokio::sync::Notify::new());
+    {
+        let notify = notify.clone();
+        app.register_task_handler("waiting", move |_, _| {
+            let notify = notify.clone();
+            async move {
+                notify.notified().await;
+                Ok(json!(null))
+            }
+        })
+        .await
+        .unwrap();
+    }
+    let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+        .task("waiting".into())
+        .args(vec![json!(1)])
+        .build()
+        .unwrap();
+    let handle = {
+        let app = app.clone();
+        tokio::spawn(async move { handle_delivery(&app, Delivery(Some(message))).await })
+    };
+
+    let active = loop {
+        let active = control_with_app(&app, &mut consumers, "active", json!({}))
+            .await
+            .unwrap();
+        if active.as_array().is_some_and(|active| !active.is_empty()) {
+            break active;
+        }
+        tokio::task::yield_now().await;
+    };
+    assert_eq!(active[0]["id"], "aaa");
+    assert_eq!(active[0]["name"], "waiting");
+    assert_eq!(active[0]["args"], json!([1]));
+    assert_eq!(active[0]["acknowledged"], true);
+    assert_eq!(active[0]["delivery_info"]["routing_key"], "celery");
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "reserved", json!({})).await,
+        Some(json!([]))
+    );
+
+    notify.notify_one();
+    handle.await.unwrap().result.unwrap();
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "active", json!({})).await,
+        Some(json!([]))
+    );
+    let stats = control_with_app(&app, &mut consumers, "stats", json!({}))
+        .await
+        .unwrap();
+    assert_eq!(stats["total"], json!({"waiting": 1}));
+    assert_eq!(stats["pool"]["implementation"], "tokio");
+}
+
+#[tokio::test]
+async fn test_control_consumers() {
+    let app = build_app(|builder| builder).await;
+    let mut consumers = build_consumers();
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "add_consumer", json!({"queue": "q"})).await,
+        Some(json!({"ok": "add consumer q"}))
+    );
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "add_consumer", json!({"queue": "q"})).await,
+        Some(json!({"ok": "already consuming from 'q'"}))
+    );
+    assert!(consumers.tags.contains_key("q"));
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "cancel_consumer",
+            json!({"queue": "q"})
+        )
+        .await,
+        Some(json!({"ok": "no longer consuming from 'q'"}))
+    );
+    assert!(consumers.streams.is_empty());
+
+    // Shutting down doesn't reply.
+    assert_eq!(
+        control_with_app(&app, &mut consumers, "shutdown", json!({})).await,
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_revoke() {
+    let app = build_app(|builder| builder.result_backend("memory://revoke")).await;
+    app.register_task::<AddTask>().await.unwrap();
+    let mut consumers = build_consumers();
+    let result = app.send_task(AddTask::new(1, 2)).await.unwrap();
+    result.revoke(false).await.unwrap();
+    let control_messages = app.broker.control_messages.read().await.clone();
+    assert_eq!(control_messages.len(), 1);
+    let (namespace, message) = &control_messages[0];
+    assert_eq!(namespace, "celery");
+    assert_eq!(message.method, "revoke");
+    assert_eq!(message.arguments["task_id"], json!(result.task_id));
+    assert_eq!(message.arguments["terminate"], json!(false));
+
+    let reply = control_with_app(
+        &app,
+        &mut consumers,
+        "revoke",
+        serde_json::Value::Object(message.arguments.clone()),
+    )
+    .await;
+    assert_eq!(
+        reply,
+        Some(json!({ "ok": format!("tasks {} flagged as revoked", result.task_id) }))
+    );
+
+    // The revoked task is discarded when it's received.
+    let mut message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    message.headers.id = result.task_id.clone();
+    let handled = handle_delivery(&app, Delivery(Some(message))).await;
+    handled.result.unwrap();
+    assert!(matches!(
+        result.get::<i32>(None).await,
+        Err(BackendError::TaskRevoked(_))
+    ));
+    assert_eq!(
+        handled.meta.unwrap().result["exc_message"],
+        json!(["revoked"])
+    );
+}
+
+#[tokio::test]
+async fn test_revoke_terminate() {
+    let app = build_app(|builder| builder.result_backend("memory://revoke-terminate")).await;
+    let mut consumers = build_consumers();
+    app.register_task_handler("waiting", |_, _| async {
+        futures::future::pending::<()>().await;
+        Ok(json!(null))
+    })
+    .await
+    .unwrap();
+    let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+        .task("waiting".into())
+        .args(vec![])
+        .build()
+        .unwrap();
+    let handle = {
+        let app = app.clone();
+        tokio::spawn(async move { handle_delivery(&app, Delivery(Some(message))).await })
+    };
+
+    loop {
+        let active = control_with_app(&app, &mut consumers, "active", json!({}))
+            .await
+            .unwrap();
+        if active.as_array().is_some_and(|active| !active.is_empty()) {
+            break;
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "revoke",
+            json!({"task_id": ["aaa", "bbb"], "terminate": true})
+        )
+        .await,
+        Some(json!({"ok": "terminate: aaa"}))
+    );
+    let handled = handle.await.unwrap();
+    handled.result.unwrap();
+    let meta = handled.meta.unwrap();
+    assert_eq!(meta.status, TaskState::Revoked);
+    assert_eq!(meta.result["exc_message"], json!(["terminated"]));
+}
+
+#[test]
+fn test_revoked_set_max() {
+    // Even if they're all revoked within the same second, only the newest IDs are kept.
+    let mut revoked = RevokedSet::default();
+    for i in 0..=REVOKES_MAX {
+        revoked.add(&i.to_string());
+    }
+    assert!(!revoked.contains("0"));
+    assert!(revoked.contains("1"));
+    assert!(revoked.contains(&REVOKES_MAX.to_string()));
+
+    // Revoking an ID again makes it the newest.
+    revoked.add("1");
+    revoked.add("new");
+    assert!(revoked.contains("1"));
+    assert!(!revoked.contains("2"));
+}
+
+#[tokio::test]
+async fn test_revoke_state_db() {
+    let path = std::env::temp_dir().join(format!("celery-revoked-{}.db", std::process::id()));
+    let path = path.to_str().unwrap();
+    let app = build_app(|builder| builder.worker_state_db(path)).await;
+    let mut consumers = build_consumers();
+    control_with_app(&app, &mut consumers, "revoke", json!({"task_id": "aaa"})).await;
+
+    // The revoked tasks are saved later, not by the control command.
+    assert!(!std::path::Path::new(path).exists());
+    app.worker_state.save_revoked().await;
+
+    // Another worker with the same state file knows about the revoked task.
+    let app = build_app(|builder| builder.worker_state_db(path)).await;
+    std::fs::remove_file(path).unwrap();
+    assert!(app.worker_state.is_revoked("aaa"));
+    assert!(!app.worker_state.is_revoked("bbb"));
+}
+
+#[tokio::test]
+async fn test_task_events() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://task-events")
+            .task_max_retries(1)
+            .send_events(true)
+            .send_sent_event(true)
+    })
+    .await;
+    app.register_task::<AddTask>().await.unwrap();
+    let result = app.send_task(AddTask::new(1, 2)).await.unwrap();
+    let events = app.broker.events.read().await.clone();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, "task-sent");
+    assert_eq!(events[0].hostname, "mock-app@host");
+    assert_eq!(events[0].fields["uuid"], json!(result.task_id));
+    assert_eq!(events[0].fields["name"], "add");
+    assert_eq!(events[0].fields["queue"], "celery");
+
+    let message = app.broker.sent_tasks.read().await[&result.task_id]
+        .0
+        .clone();
+    let events = handle_delivery(&app, Delivery(Some(message))).await.events;
+    let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(types, ["task-received", "task-started", "task-succeeded"]);
+    assert!(events
+        .iter()
+        .all(|e| e.fields["uuid"] == json!(result.task_id)));
+    assert_eq!(events[0].fields["name"], "add");
+    assert_eq!(events[2].fields["result"], "3");
+    assert!(events[2].fields["runtime"].is_f64());
+    assert!(events[0].clock < events[1].clock && events[1].clock < events[2].clock);
+}
+
+#[tokio::test]
+async fn test_task_failure_events() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://task-failure-events")
+            .task_max_retries(1)
+            .send_events(true)
+            .send_sent_event(true)
+    })
+    .await;
+    app.register_task::<FailingTask>().await.unwrap();
+    let message = Message::try_from(FailingTask::new()).unwrap();
+    let events = handle_delivery(&app, Delivery(Some(message.clone())))
+        .await
+        .events;
+    let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(types, ["task-received", "task-started", "task-retried"]);
+    assert_eq!(events[2].fields["exception"], "UnexpectedError('oops')");
+
+    let mut message = message;
+    message.headers.retries = Some(1);
+    let events = handle_delivery(&app, Delivery(Some(message))).await.events;
+    let types: Vec<&str> = events.iter().map(|e| e.event_type.as_str()).collect();
+    assert_eq!(types, ["task-received", "task-started", "task-failed"]);
+    assert_eq!(events[2].fields["exception"], "UnexpectedError('oops')");
+}
+
+#[tokio::test]
+async fn test_worker_events() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://worker-events")
+            .task_max_retries(1)
+            .send_events(true)
+            .send_sent_event(true)
+    })
+    .await;
+    app.register_task::<AddTask>().await.unwrap();
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    handle_delivery(&app, Delivery(Some(message)))
+        .await
+        .result
+        .unwrap();
+    app.broker.reset().await;
+    app.send_worker_event("worker-heartbeat").await;
+    let events = app.broker.events.read().await.clone();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, "worker-heartbeat");
+    assert_eq!(events[0].fields["active"], 0);
+    assert_eq!(events[0].fields["processed"], 1);
+    assert_eq!(events[0].fields["sw_ident"], "rusty-celery");
+
+    // Workers that don't send events stay silent.
+    let app = build_app(|builder| builder).await;
+    app.register_task::<AddTask>().await.unwrap();
+    app.send_worker_event("worker-heartbeat").await;
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    let handled = handle_delivery(&app, Delivery(Some(message))).await;
+    handled.result.unwrap();
+    assert!(app.broker.events.read().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let app = build_app(|builder| builder.task_rate_limit("10/s".parse().unwrap())).await;
+    app.register_task::<AddTask>().await.unwrap();
+
+    // The first task runs right away, and the next one once a token is available.
+    let start = std::time::Instant::now();
+    let timeout = std::time::Duration::from_secs(1);
+    for _ in 0..2 {
+        let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+        assert!(
+            tokio::time::timeout(timeout, handle_delivery(&app, Delivery(Some(message))))
+                .await
+                .is_ok()
+        );
+    }
+    assert!(start.elapsed() >= std::time::Duration::from_millis(90));
+}
+
+#[tokio::test]
+async fn test_control_rate_limit() {
+    let app = build_app(|builder| builder).await;
+    app.register_task::<AddTask>().await.unwrap();
+    let mut consumers = build_consumers();
+    let timeout = std::time::Duration::from_millis(200);
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "rate_limit",
+            json!({"task_name": "add", "rate_limit": "1/h"})
+        )
+        .await,
+        Some(json!({"ok": "new rate limit set successfully"}))
+    );
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    assert!(
+        tokio::time::timeout(timeout, handle_delivery(&app, Delivery(Some(message))))
+            .await
+            .is_ok()
+    );
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    assert!(
+        tokio::time::timeout(timeout, handle_delivery(&app, Delivery(Some(message))))
+            .await
+            .is_err()
+    );
+
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "rate_limit",
+            json!({"task_name": "add", "rate_limit": 0})
+        )
+        .await,
+        Some(json!({"ok": "rate limit disabled successfully"}))
+    );
+    let message = Message::try_from(AddTask::new(1, 2)).unwrap();
+    assert!(
+        tokio::time::timeout(timeout, handle_delivery(&app, Delivery(Some(message))))
+            .await
+            .is_ok()
+    );
+
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "rate_limit",
+            json!({"task_name": "add", "rate_limit": "fast"})
+        )
+        .await,
+        Some(json!({"error": "Invalid rate limit string: 'fast'"}))
+    );
+    assert_eq!(
+        control_with_app(
+            &app,
+            &mut consumers,
+            "rate_limit",
+            json!({"task_name": "unknown", "rate_limit": "1/s"})
+        )
+        .await,
+        Some(json!({"error": "unknown task"}))
+    );
+}
+
+#[tokio::test]
+async fn test_dead_letter_invalid_messages() {
+    let app = build_app(|builder| builder.dead_letter_queue("dead").task_max_retries(0)).await;
+    let handled = handle_delivery(&app, Delivery(None)).await;
+    assert!(handled.result.is_err());
+    assert_eq!(handled.dead_letters.len(), 1);
+    let (delivery, queue, dead_letter) = &handled.dead_letters[0];
+    assert!(delivery.0.is_none());
+    assert_eq!(queue, "dead");
+    assert_eq!(dead_letter.reason, DeadLetterReason::Undeserializable);
+    assert_eq!(dead_letter.queue, "celery");
+
+    let message = Message::try_from(MultiplyTask::new(2, 3)).unwrap();
+    let handled = handle_delivery(&app, Delivery(Some(message.clone()))).await;
+    assert!(handled.result.is_err());
+    assert_eq!(handled.dead_letters.len(), 1);
+    let (delivery, queue, dead_letter) = &handled.dead_letters[0];
+    assert_eq!(delivery.0.as_ref().unwrap().task_id(), message.task_id());
+    assert_eq!(queue, "dead");
+    assert_eq!(dead_letter.reason, DeadLetterReason::Unregistered);
+    assert_eq!(dead_letter.error, "received unregistered task multiply");
+}
+
+#[tokio::test]
+async fn test_dead_letter_retries_exhausted() {
+    let app = build_app(|builder| builder.dead_letter_queue("dead").task_max_retries(1)).await;
+    app.register_task::<FailingTask>().await.unwrap();
+    let mut message = Message::try_from(FailingTask::new()).unwrap();
+    let handled = handle_delivery(&app, Delivery(Some(message.clone()))).await;
+    handled.result.unwrap();
+    assert!(handled.dead_letters.is_empty());
+
+    message.headers.retries = Some(1);
+    let handled = handle_delivery(&app, Delivery(Some(message))).await;
+    handled.result.unwrap();
+    assert_eq!(handled.dead_letters.len(), 1);
+    let (_, queue, dead_letter) = &handled.dead_letters[0];
+    assert_eq!(queue, "dead");
+    assert_eq!(dead_letter.reason, DeadLetterReason::RetriesExhausted);
+    assert_eq!(dead_letter.error, "task raised unexpected error: oops");
+}
+
+#[tokio::test]
+async fn test_replay_dead_letters() {
+    let app = build_app(|builder| builder.dead_letter_queue("dead").task_max_retries(0)).await;
+    app.register_task::<FailingTask>().await.unwrap();
+    let message = Message::try_from(FailingTask::new()).unwrap();
+    handle_delivery(&app, Delivery(Some(message.clone())))
+        .await
+        .result
+        .unwrap();
+    assert!(handle_delivery(&app, Delivery(None)).await.result.is_err());
+
+    // Invalid messages can't be replayed, so they stay in the dead-letter queue.
+    assert_eq!(app.replay_dead_letters().await.unwrap(), 1);
+    let sent_tasks = app.broker.sent_tasks.read().await;
+    assert_eq!(sent_tasks[message.task_id()].1, "celery");
+    assert_eq!(app.broker.dead_letters.read().await.len(), 1);
+
+    // Without a dead-letter queue, messages are dropped and there is nothing to replay.
+    let app = build_app(|builder| builder).await;
+    let handled = handle_delivery(&app, Delivery(None)).await;
+    assert!(handled.result.is_err());
+    assert!(handled.dead_letters.is_empty());
+    assert!(matches!(
+        app.replay_dead_letters().await,
+        Err(CeleryError::NoDeadLetterQueue)
+    ));
+}
+#[tokio::test]
+async fn test_blocking_pool_size() {
+    let app = build_app(|builder| {
+        builder
+            .result_backend("memory://blocking-pool-size")
+            .blocking_pool_size(1)
+    })
+    .await;
+    app.register_task::<BlockingTask>().await.unwrap();
+
+    // The tasks run one after the other, and waiting for the pool doesn't count against
+    // their time limit.
+    let start = std::time::Instant::now();
+    let deliveries = (0..2).map(|_| {
+        let message = Message::try_from(BlockingTask::new(600, false).with_time_limit(1)).unwrap();
+        handle_delivery(&app, Delivery(Some(message)))
+    });
+    for handled in futures::future::join_all(deliveries).await {
+        handled.result.unwrap();
+        assert_eq!(handled.meta.unwrap().status, TaskState::Success);
+    }
+    assert!(start.elapsed() >= std::time::Duration::from_millis(1200));
+}
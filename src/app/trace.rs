@@ -1,12 +1,23 @@
 use async_trait::async_trait;
+use futures::future::{AbortHandle, AbortRegistration, Abortable};
 use log::{debug, error, info, warn};
+use serde_json::{json, Value};
 use std::convert::TryFrom;
+use std::sync::Arc;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Semaphore;
 use tokio::time::{self, Duration, Instant};
 
-use crate::error::{ProtocolError, TaskError, TraceError};
+use super::chord::on_chord_ready;
+use super::events::EventSender;
+use crate::backend::{exception_to_value, ResultBackend, TaskMeta};
+use crate::canvas::Chord;
+use crate::error::{CeleryError, ProtocolError, TaskError, TraceError};
 use crate::protocol::Message;
-use crate::task::{Request, Task, TaskEvent, TaskOptions, TaskStatus};
+use crate::task::{
+    signature_list, AsyncResult, DynamicHandler, DynamicTask, RateLimit, RawSignature, Request,
+    Task, TaskEvent, TaskOptions, TaskResult, TaskStatus,
+};
 
 /// A `Tracer` provides the API through which a `Celery` application interacts with its tasks.
 ///
@@ -14,19 +25,51 @@ use crate::task::{Request, Task, TaskEvent, TaskOptions, TaskStatus};
 /// and handling any errors, logging, and running the `on_failure` or `on_success` post-execution
 /// methods. It communicates its progress and the results back to the application through
 /// the `event_tx` channel and the return value of `Tracer::trace`, respectively.
+/// If the application has a result backend, the `Tracer` also stores the final state
+/// of the task there.
 pub(super) struct Tracer<T>
 where
     T: Task,
 {
     task: T,
     event_tx: UnboundedSender<TaskEvent>,
+    backend: Option<Arc<dyn ResultBackend>>,
+    result_extended: bool,
+    sender: Arc<dyn TaskSender>,
+
+    /// Runs the task in a child process, if the app runs tasks in child processes.
+    prefork: Option<ChildTask>,
+
+    /// Aborts the execution of the task when it's terminated.
+    abort: Option<AbortRegistration>,
+
+    /// Sends the events of the task, if the worker sends events.
+    events: Option<Arc<dyn EventSender>>,
+
+    /// Whether the task failed because it couldn't be retried anymore.
+    retries_exhausted: bool,
+}
+
+/// The return value of a task, or its serialized return value if it ran in a child process.
+enum Returned<R> {
+    Value(R),
+    Serialized(Value),
+}
+
+impl<R: std::fmt::Debug> std::fmt::Debug for Returned<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Returned::Value(returned) => returned.fmt(f),
+            Returned::Serialized(value) => write!(f, "{}", value),
+        }
+    }
 }
 
 impl<T> Tracer<T>
 where
     T: Task,
 {
-    fn new(task: T, event_tx: UnboundedSender<TaskEvent>) -> Self {
+    fn new(task: T, event_tx: UnboundedSender<TaskEvent>, context: TraceContext) -> Self {
         if let Some(eta) = task.request().eta {
             info!(
                 "Task {}[{}] received, ETA: {}",
@@ -38,7 +81,318 @@ where
             info!("Task {}[{}] received", task.name(), task.request().id);
         }
 
-        Self { task, event_tx }
+        Self {
+            task,
+            event_tx,
+            backend: context.backend,
+            result_extended: context.result_extended,
+            sender: context.sender,
+            prefork: context.prefork,
+            abort: None,
+            events: context.events,
+            retries_exhausted: false,
+        }
+    }
+
+    /// Send an event about the task, if the worker sends events.
+    async fn send_event(&self, event_type: &str, mut fields: Value) {
+        if let Some(ref events) = self.events {
+            fields["uuid"] = json!(self.task.request().id);
+            events.send_event(event_type, fields).await;
+        }
+    }
+
+    /// Send the next task of the chain, if there is one, with the return value of the task.
+    ///
+    /// Failing to send the next task is logged but otherwise doesn't affect the task.
+    async fn send_next_link(&self, value: &serde_json::Value) {
+        let request = self.task.request();
+        let mut chain = request.chain.clone();
+        let mut next = match chain.pop() {
+            Some(next) => next,
+            None => return,
+        };
+        if !next.immutable {
+            next.args.insert(0, value.clone());
+        }
+        if !chain.is_empty() {
+            next.options.insert("chain".into(), json!(chain));
+        }
+        next.options.insert("parent_id".into(), json!(request.id));
+        next.options.insert(
+            "root_id".into(),
+            json!(request.root_id.as_ref().unwrap_or(&request.id)),
+        );
+        if let Err(e) = self.sender.send_signature(next).await {
+            error!(
+                "Failed to send the next task of the chain of task {}[{}]: {}",
+                self.task.name(),
+                &request.id,
+                e
+            );
+        }
+    }
+
+    /// Send the callbacks of the task with its return value.
+    ///
+    /// Failing to send a callback is logged but otherwise doesn't affect the task.
+    async fn send_callbacks(&self, value: &serde_json::Value) {
+        let request = self.task.request();
+        for mut callback in request.callbacks.clone() {
+            if !callback.immutable {
+                callback.args.insert(0, value.clone());
+            }
+            callback
+                .options
+                .insert("parent_id".into(), json!(request.id));
+            callback.options.insert(
+                "root_id".into(),
+                json!(request.root_id.as_ref().unwrap_or(&request.id)),
+            );
+            let name = callback.task.clone();
+            if let Err(e) = self.sender.send_signature(callback).await {
+                error!(
+                    "Failed to send callback {} of task {}[{}]: {}",
+                    name,
+                    self.task.name(),
+                    &request.id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Send the replacement of the task, like Python's `Task.replace`. The replacement takes
+    /// over the ID of the task, its place in its chain, group and chord, and its callbacks
+    /// and errbacks.
+    async fn replace(&self, mut replacement: RawSignature) -> Result<(), CeleryError> {
+        if replacement.subtask_type.as_deref() == Some("chord") {
+            let mut chord = Chord::try_from(replacement)?;
+            self.hand_over(&mut chord.body)?;
+            self.sender.send_chord(chord).await?;
+        } else {
+            self.hand_over(&mut replacement)?;
+            self.sender.send_signature(replacement).await?;
+        }
+        Ok(())
+    }
+
+    /// Move the ID, the work-flow options, the callbacks and errbacks and the rest of the
+    /// chain of the task over to `signature`.
+    fn hand_over(&self, signature: &mut RawSignature) -> Result<(), ProtocolError> {
+        let request = self.task.request();
+        signature.update_last(|last| {
+            let options = &mut last.options;
+            options.insert("task_id".into(), json!(request.id));
+            if let Some(ref group_id) = request.group {
+                options.insert("group_id".into(), json!(group_id));
+                options.insert("group_index".into(), json!(request.group_index));
+            }
+            if let Some(ref chord) = request.chord {
+                options.insert("chord".into(), json!(chord));
+            }
+            for (name, signatures) in &[
+                ("link", &request.callbacks),
+                ("link_error", &request.errbacks),
+            ] {
+                if signatures.is_empty() {
+                    continue;
+                }
+                let mut links = match options.remove(*name) {
+                    Some(links) => signature_list(links)?,
+                    None => vec![],
+                };
+                links.extend(signatures.iter().cloned());
+                options.insert((*name).into(), json!(links));
+            }
+            Ok(())
+        })?;
+
+        // The rest of the chain of the task runs after the replacement. Chains are stored
+        // in reverse order.
+        if !request.chain.is_empty() {
+            let mut chain = request.chain.clone();
+            if let Some(links) = signature.options.remove("chain") {
+                chain.extend(serde_json::from_value::<Vec<RawSignature>>(links)?);
+            }
+            signature.options.insert("chain".into(), json!(chain));
+        }
+        if let Some(ref parent_id) = request.parent_id {
+            signature
+                .options
+                .insert("parent_id".into(), json!(parent_id));
+        }
+        signature.options.insert(
+            "root_id".into(),
+            json!(request.root_id.as_ref().unwrap_or(&request.id)),
+        );
+        Ok(())
+    }
+
+    /// Run the task in a child process if the app runs tasks in child processes, or within
+    /// the worker otherwise.
+    async fn execute_maybe_in_child(&self) -> TaskResult<Returned<T::Returns>> {
+        if let Some(ref child_task) = self.prefork {
+            return child_task
+                .pool
+                .execute(
+                    &child_task.message,
+                    self.task.request().queue.as_deref(),
+                    self.task.hard_time_limit(),
+                )
+                .await
+                .map(Returned::Serialized);
+        }
+        self.execute(self.task.time_limit())
+            .await
+            .map(Returned::Value)
+    }
+
+    /// Run the task within `time_limit` seconds.
+    async fn execute(&self, time_limit: Option<u32>) -> TaskResult<T::Returns> {
+        let request = self.task.request();
+        if let (true, Some(permits)) = (T::BLOCKING, &request.blocking_permits) {
+            // Waiting for the blocking pool doesn't count against the time limit.
+            let permit = permits.clone().acquire_owned().await.ok();
+            *request.blocking_permit.lock().unwrap() = permit;
+        }
+        match time_limit {
+            Some(secs) => {
+                debug!("Executing task with {} second time limit", secs);
+                let duration = Duration::from_secs(secs as u64);
+                time::timeout(duration, self.task.run(self.task.request().params.clone()))
+                    .await
+                    .unwrap_or(Err(TaskError::TimeoutError))
+            }
+            None => self.task.run(self.task.request().params.clone()).await,
+        }
+    }
+
+    /// Store the final failure of the task, report it to its chord and send its errbacks.
+    async fn fail(&self, e: &TaskError) {
+        let request = self.task.request();
+        self.store_final_result(TaskMeta::failure(&request.id, e))
+            .await;
+        self.send_event(
+            "task-failed",
+            json!({"exception": exception_repr(e), "traceback": ""}),
+        )
+        .await;
+        send_errbacks(self.sender.as_ref(), request.errbacks.clone(), &request.id).await;
+    }
+
+    /// Store the final state of the task, and report it to the chord the task is part of,
+    /// if any.
+    async fn store_final_result(&self, meta: TaskMeta) {
+        self.store_result(meta.clone()).await;
+        self.on_chord_part_return(&meta).await;
+    }
+
+    /// Report the final state of the task to the chord it is part of, if the result backend
+    /// joins chords natively, and run the callback of the chord if this was its last task.
+    /// Other backends join chords with the `celery.chord_unlock` task instead.
+    ///
+    /// Failing to join the chord is logged but otherwise doesn't affect the task.
+    async fn on_chord_part_return(&self, meta: &TaskMeta) {
+        let request = self.task.request();
+        let (callback, group_id, backend) = match (&request.chord, &request.group, &self.backend) {
+            (Some(callback), Some(group_id), Some(backend)) if backend.supports_native_join() => {
+                (callback, group_id, backend)
+            }
+            _ => return,
+        };
+        let result = match backend
+            .on_chord_part_return(group_id, request.group_index, meta)
+            .await
+        {
+            Ok(Some(results)) => {
+                on_chord_ready(
+                    self.sender.as_ref(),
+                    backend.as_ref(),
+                    callback.clone(),
+                    results,
+                )
+                .await
+            }
+            Ok(None) => Ok(()),
+            Err(e) => Err(e.into()),
+        };
+        if let Err(e) = result {
+            error!(
+                "Failed to join the chord of task {}[{}]: {}",
+                self.task.name(),
+                &request.id,
+                e
+            );
+        }
+    }
+
+    /// End a task that returned [`TaskError::Replaced`] by sending the replacement it set
+    /// with [`Task::replace`].
+    async fn end_replaced(&self) -> Result<(), TraceError> {
+        let sent = match self.task.request().take_replacement() {
+            Some(replacement) => {
+                info!(
+                    "Task {}[{}] replaced by {}",
+                    self.task.name(),
+                    &self.task.request().id,
+                    replacement.task
+                );
+                self.replace(replacement)
+                    .await
+                    .map_err(|e| format!("failed to send the replacement of the task: {}", e))
+            }
+            None => Err("the task has no replacement set with Task::replace".to_string()),
+        };
+
+        self.event_tx
+            .send(TaskEvent::StatusChange(TaskStatus::Finished))
+            .unwrap_or_else(|_| {
+                error!("Failed sending task event");
+            });
+
+        if let Err(reason) = sent {
+            let e = TaskError::UnexpectedError(reason);
+            error!(
+                "Task {}[{}] failed: {}",
+                self.task.name(),
+                &self.task.request().id,
+                e
+            );
+            self.fail(&e).await;
+            return Err(TraceError::TaskError(e));
+        }
+        Ok(())
+    }
+
+    /// Store the meta data of the task in the result backend, if there is one.
+    ///
+    /// Failing to store a result is logged but otherwise doesn't affect the task.
+    async fn store_result(&self, mut meta: TaskMeta) {
+        if self.task.ignore_result() {
+            return;
+        }
+        if let Some(ref backend) = self.backend {
+            let request = self.task.request();
+            meta.reply_to = request.reply_to.clone();
+            if self.result_extended {
+                meta.name = Some(self.task.name().into());
+                meta.args = Some(json!([]));
+                meta.kwargs = serde_json::to_value(&request.params).ok();
+                meta.worker = request.hostname.clone();
+                meta.retries = Some(request.retries);
+                meta.queue = request.queue.clone();
+            }
+            if let Err(e) = backend.store_result(&meta).await {
+                error!(
+                    "Failed to store {} state of task {}[{}]: {}",
+                    meta.status,
+                    self.task.name(),
+                    &self.task.request().id,
+                    e
+                );
+            }
+        }
     }
 }
 
@@ -57,6 +411,12 @@ where
             return Err(TraceError::ExpirationError);
         }
 
+        if self.task.track_started() {
+            let hostname = self.task.request().hostname.clone().unwrap_or_default();
+            self.store_result(TaskMeta::started(&self.task.request().id, &hostname))
+                .await;
+        }
+
         self.event_tx
             .send(TaskEvent::StatusChange(TaskStatus::Pending))
             .unwrap_or_else(|_| {
@@ -64,17 +424,34 @@ where
                 // bigger things to worry about like running out of memory.
                 error!("Failed sending task event");
             });
+        self.send_event("task-started", json!({})).await;
 
+        let abort = self.abort.take();
         let start = Instant::now();
-        let result = match self.task.time_limit() {
-            Some(secs) => {
-                debug!("Executing task with {} second time limit", secs);
-                let duration = Duration::from_secs(secs as u64);
-                time::timeout(duration, self.task.run(self.task.request().params.clone()))
-                    .await
-                    .unwrap_or(Err(TaskError::TimeoutError))
-            }
-            None => self.task.run(self.task.request().params.clone()).await,
+        let execution = self.execute_maybe_in_child();
+        let result = match abort {
+            Some(abort) => match Abortable::new(execution, abort).await {
+                Ok(result) => result,
+                Err(_) => {
+                    warn!(
+                        "Task {}[{}] terminated",
+                        self.task.name(),
+                        &self.task.request().id,
+                    );
+                    self.store_final_result(TaskMeta::revoked(
+                        &self.task.request().id,
+                        "terminated",
+                    ))
+                    .await;
+                    self.event_tx
+                        .send(TaskEvent::StatusChange(TaskStatus::Finished))
+                        .unwrap_or_else(|_| {
+                            error!("Failed sending task event");
+                        });
+                    return Err(TraceError::Revoked);
+                }
+            },
+            None => execution.await,
         };
         let duration = start.elapsed();
 
@@ -88,8 +465,50 @@ where
                     returned
                 );
 
-                // Run success callback.
-                self.task.on_success(&returned).await;
+                let value = match returned {
+                    Returned::Value(ref returned) => serde_json::to_value(returned),
+                    Returned::Serialized(ref value) => Ok(value.clone()),
+                };
+
+                // A result that can't be stored fails the task, so that whoever waits for it
+                // doesn't wait forever.
+                let value = match value {
+                    Ok(value) => value,
+                    Err(e) => {
+                        let e = TaskError::UnexpectedError(format!(
+                            "failed to serialize result: {}",
+                            e
+                        ));
+                        error!(
+                            "Task {}[{}] failed: {}",
+                            self.task.name(),
+                            &self.task.request().id,
+                            e
+                        );
+                        self.task.on_failure(&e).await;
+                        self.event_tx
+                            .send(TaskEvent::StatusChange(TaskStatus::Finished))
+                            .unwrap_or_else(|_| {
+                                error!("Failed sending task event");
+                            });
+                        self.fail(&e).await;
+                        return Err(TraceError::TaskError(e));
+                    }
+                };
+                self.store_final_result(TaskMeta::success(&self.task.request().id, value.clone()))
+                    .await;
+                self.send_next_link(&value).await;
+                self.send_callbacks(&value).await;
+                self.send_event(
+                    "task-succeeded",
+                    json!({"result": value.to_string(), "runtime": duration.as_secs_f64()}),
+                )
+                .await;
+
+                // Run success callback, unless it already ran in the child process.
+                if let Returned::Value(ref returned) = returned {
+                    self.task.on_success(returned).await;
+                }
 
                 self.event_tx
                     .send(TaskEvent::StatusChange(TaskStatus::Finished))
@@ -136,6 +555,16 @@ where
                         );
                         (true, eta)
                     }
+                    TaskError::Replaced => return self.end_replaced().await,
+                    TaskError::WorkerLostError(ref reason) => {
+                        error!(
+                            "Task {}[{}] failed: {}",
+                            self.task.name(),
+                            &self.task.request().id,
+                            reason
+                        );
+                        (false, None)
+                    }
                 };
 
                 // Run failure callback.
@@ -148,6 +577,7 @@ where
                     });
 
                 if !should_retry {
+                    self.fail(&e).await;
                     return Err(TraceError::TaskError(e));
                 }
 
@@ -159,6 +589,8 @@ where
                             self.task.name(),
                             &self.task.request().id,
                         );
+                        self.retries_exhausted = true;
+                        self.fail(&e).await;
                         return Err(TraceError::TaskError(e));
                     }
                     info!(
@@ -177,6 +609,14 @@ where
                     );
                 }
 
+                self.store_result(TaskMeta::retry(&self.task.request().id, &e))
+                    .await;
+                self.send_event(
+                    "task-retried",
+                    json!({"exception": exception_repr(&e), "traceback": ""}),
+                )
+                .await;
+
                 Err(TraceError::Retry(
                     retry_eta.or_else(|| self.task.retry_eta()),
                 ))
@@ -201,6 +641,54 @@ where
     fn acks_late(&self) -> bool {
         self.task.acks_late()
     }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        self.task.rate_limit()
+    }
+
+    fn retries_exhausted(&self) -> bool {
+        self.retries_exhausted
+    }
+
+    fn abort_handle(&mut self) -> AbortHandle {
+        let (handle, registration) = AbortHandle::new_pair();
+        self.abort = Some(registration);
+        handle
+    }
+
+    async fn revoke(&self) {
+        info!(
+            "Discarding revoked task {}[{}]",
+            self.task.name(),
+            &self.task.request().id,
+        );
+        self.store_final_result(TaskMeta::revoked(&self.task.request().id, "revoked"))
+            .await;
+    }
+
+    async fn run(&self) -> TaskResult<Value> {
+        let returned = self.execute(self.task.time_limit()).await?;
+        serde_json::to_value(&returned)
+            .map_err(|e| TaskError::UnexpectedError(format!("failed to serialize result: {}", e)))
+    }
+
+    async fn run_in_child(&self) -> TaskResult<Value> {
+        // The hard time limit is enforced by the worker, which kills the child, so the child
+        // itself only enforces a lower time limit.
+        let hard_time_limit = self.task.hard_time_limit();
+        let time_limit = self
+            .task
+            .time_limit()
+            .filter(|t| Some(*t) != hard_time_limit);
+        let returned = self.execute(time_limit).await?;
+        let value = serde_json::to_value(&returned).map_err(|e| {
+            TaskError::UnexpectedError(format!("failed to serialize result: {}", e))
+        })?;
+        // The return value only exists in the child, so this is the only place where the
+        // success callback can run.
+        self.task.on_success(&returned).await;
+        Ok(value)
+    }
 }
 
 #[async_trait]
@@ -217,12 +705,158 @@ pub(super) trait TracerTrait: Send + Sync {
     fn is_expired(&self) -> bool;
 
     fn acks_late(&self) -> bool;
+
+    /// The rate limit of the task, unless it was changed at runtime.
+    fn rate_limit(&self) -> Option<RateLimit>;
+
+    /// Whether the last trace failed because the task had been retried as many times as
+    /// allowed by its `max_retries`.
+    fn retries_exhausted(&self) -> bool;
+
+    /// Get a handle that terminates the task while it's being traced, like Python's
+    /// `revoke(terminate=True)`. The task is then stored as `REVOKED`.
+    fn abort_handle(&mut self) -> AbortHandle;
+
+    /// Store the task as `REVOKED` instead of executing it.
+    async fn revoke(&self);
+
+    /// Execute the task within its time limit without tracing it: nothing is logged or
+    /// stored, and the tasks it triggers aren't sent. Returns the serialized return value of
+    /// the task.
+    async fn run(&self) -> TaskResult<Value>;
+
+    /// Execute the task in a child process of the pool, on behalf of the worker that traces
+    /// it. Like [`run`](TracerTrait::run), but the hard time limit is left to the worker and
+    /// the [`on_success`](Task::on_success) callback of the task runs here, since the
+    /// return value only exists in the child.
+    async fn run_in_child(&self) -> TaskResult<Value>;
 }
 
 pub(super) type TraceBuilderResult = Result<Box<dyn TracerTrait>, ProtocolError>;
 
+/// Per-delivery settings of the app that are passed to a [`TraceBuilder`].
+#[derive(Clone)]
+pub(super) struct TraceContext {
+    /// Node name of the worker.
+    pub(super) hostname: String,
+
+    /// The queue the task was consumed from.
+    pub(super) queue: Option<String>,
+
+    /// The app's result backend.
+    pub(super) backend: Option<Arc<dyn ResultBackend>>,
+
+    /// Whether to store extended meta data of the task in the result backend.
+    pub(super) result_extended: bool,
+
+    /// Sends the tasks that the task triggers, such as the next task of its chain.
+    pub(super) sender: Arc<dyn TaskSender>,
+
+    /// Limits the number of blocking functions of tasks that run at the same time.
+    pub(super) blocking_permits: Option<Arc<Semaphore>>,
+
+    /// Whether the task can replace itself with [`Task::replace`], which tasks that run in
+    /// place or in a child process can't.
+    pub(super) replaceable: bool,
+
+    /// Runs the task in a child process, if the app runs tasks in child processes.
+    pub(super) prefork: Option<ChildTask>,
+
+    /// Sends the events of the task, if the worker sends events.
+    pub(super) events: Option<Arc<dyn EventSender>>,
+}
+
+/// Runs tasks in long-lived child processes. This is implemented by the process pool of the
+/// app, see [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork).
+#[async_trait]
+pub(super) trait ChildPool: Send + Sync {
+    /// Start the child processes, if they aren't started yet.
+    async fn start(&self) -> Result<(), CeleryError>;
+
+    /// Execute the task of a message, consumed from `queue`, in an idle child process and
+    /// return its serialized return value. The child is killed and replaced if it doesn't
+    /// finish within `hard_time_limit` seconds, and the task then fails with a
+    /// [`WorkerLostError`](TaskError::WorkerLostError).
+    async fn execute(
+        &self,
+        message: &Value,
+        queue: Option<&str>,
+        hard_time_limit: Option<u32>,
+    ) -> TaskResult<Value>;
+}
+
+/// A task to run in a child process of the pool.
+#[derive(Clone)]
+pub(super) struct ChildTask {
+    pub(super) pool: Arc<dyn ChildPool>,
+
+    /// The message of the task, in the JSON format of the messages of the Redis broker.
+    pub(super) message: Value,
+}
+
+/// Describe an error like Python's `repr` of the corresponding exception, e.g.
+/// `UnexpectedError('boom')`, for events.
+fn exception_repr(e: &TaskError) -> String {
+    let exception = exception_to_value(e);
+    let args: Vec<String> = exception["exc_message"]
+        .as_array()
+        .map(|args| {
+            args.iter()
+                .map(|arg| match arg {
+                    Value::String(arg) => format!("'{}'", arg),
+                    Value::Null => "None".into(),
+                    arg => arg.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    format!(
+        "{}({})",
+        exception["exc_type"].as_str().unwrap_or_default(),
+        args.join(", ")
+    )
+}
+
+/// Send the errbacks of a task that failed, with the ID of the task as their first argument
+/// like in Python. The failure of the task is stored before, so errbacks can get its error
+/// from the result backend.
+///
+/// Failing to send an errback is logged but otherwise ignored.
+pub(super) async fn send_errbacks(
+    sender: &dyn TaskSender,
+    errbacks: Vec<RawSignature>,
+    task_id: &str,
+) {
+    for mut errback in errbacks {
+        if !errback.immutable {
+            errback.args.insert(0, json!(task_id));
+        }
+        errback.options.insert("parent_id".into(), json!(task_id));
+        let name = errback.task.clone();
+        if let Err(e) = sender.send_signature(errback).await {
+            error!("Failed to send errback {} of task {}: {}", name, task_id, e);
+        }
+    }
+}
+
+/// Sends the tasks that a task triggers when it finishes, or runs them in place for
+/// built-in tasks like `celery.map`. This is implemented by the [`Celery`](crate::Celery) app.
+#[async_trait]
+pub(crate) trait TaskSender: Send + Sync {
+    /// Send the task of a signature.
+    async fn send_signature(&self, signature: RawSignature) -> Result<AsyncResult, CeleryError>;
+
+    /// Send a chord.
+    async fn send_chord(&self, chord: Chord) -> Result<AsyncResult, CeleryError>;
+
+    /// Execute the task of a signature in this process, like Python's `apply`, and return
+    /// its serialized return value. The task must be registered on the app, and the call
+    /// waits for its rate limit.
+    async fn apply_signature(self: Arc<Self>, signature: RawSignature) -> TaskResult<Value>;
+}
+
 pub(super) type TraceBuilder = Box<
-    dyn Fn(Message, TaskOptions, UnboundedSender<TaskEvent>, String) -> TraceBuilderResult
+    dyn Fn(Message, TaskOptions, UnboundedSender<TaskEvent>, TraceContext) -> TraceBuilderResult
         + Send
         + Sync
         + 'static,
@@ -230,22 +864,59 @@ pub(super) type TraceBuilder = Box<
 
 pub(super) fn build_tracer<T: Task + Send + 'static>(
     message: Message,
-    mut options: TaskOptions,
+    options: TaskOptions,
     event_tx: UnboundedSender<TaskEvent>,
-    hostname: String,
+    context: TraceContext,
 ) -> TraceBuilderResult {
     // Build request object.
-    let mut request = Request::<T>::try_from(message)?;
-    request.hostname = Some(hostname);
+    let request = Request::<T>::try_from(message)?;
+
+    // It seems redundant to construct a request just to use it to construct a task,
+    // but the task keeps the request object so the task implementation can access
+    // it.
+    new_tracer(request, options, event_tx, context, T::from_request)
+}
+
+/// Build the tracer of a task that was registered by name with
+/// [`Celery::register_task_handler`](crate::Celery::register_task_handler), which receives
+/// the raw arguments of the message.
+pub(super) fn build_dynamic_tracer(
+    name: &'static str,
+    handler: DynamicHandler,
+    message: Message,
+    options: TaskOptions,
+    event_tx: UnboundedSender<TaskEvent>,
+    context: TraceContext,
+) -> TraceBuilderResult {
+    let (params, embed) = message.raw_params()?;
+    let mut request = Request::<DynamicTask>::new(message, params);
+    request.set_embed(embed);
+    new_tracer(request, options, event_tx, context, |request, options| {
+        DynamicTask::with_handler(request, options, name, handler)
+    })
+}
+
+fn new_tracer<T: Task + Send + 'static>(
+    mut request: Request<T>,
+    mut options: TaskOptions,
+    event_tx: UnboundedSender<TaskEvent>,
+    context: TraceContext,
+    new_task: impl FnOnce(Request<T>, TaskOptions) -> T,
+) -> TraceBuilderResult {
+    request.hostname = Some(context.hostname.clone());
+    request.queue = context.queue.clone();
+    request.backend = context.backend.clone();
+    request.sender = Some(context.sender.clone());
+    request.blocking_permits = context.blocking_permits.clone();
+    if context.replaceable {
+        request.replacement = Some(Arc::default());
+    }
 
     // Override app-level options with task-level options.
     T::DEFAULTS.override_other(&mut options);
 
     // Now construct the task from the request and options.
-    // It seems redundant to construct a request just to use it to construct a task,
-    // but the task keeps the request object so the task implementation can access
-    // it.
-    let task = T::from_request(request, options);
+    let task = new_task(request, options);
 
-    Ok(Box::new(Tracer::<T>::new(task, event_tx)))
+    Ok(Box::new(Tracer::<T>::new(task, event_tx, context)))
 }
@@ -0,0 +1,225 @@
+//! Filesystem result backend.
+
+use async_trait::async_trait;
+use log::debug;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::fs;
+use tokio::time::Duration;
+
+use super::{
+    group_key, task_key, GroupMeta, ResultBackend, ResultBackendBuilder, TaskMeta,
+    GROUP_KEY_PREFIX, TASK_KEY_PREFIX,
+};
+use crate::error::BackendError;
+
+struct Config {
+    backend_url: String,
+    result_expires: Option<Duration>,
+}
+
+/// Builds a [`FilesystemBackend`] with a custom configuration.
+pub struct FilesystemBackendBuilder {
+    config: Config,
+}
+
+impl FilesystemBackendBuilder {
+    /// Set how long results are kept before they are swept. `None` keeps them forever.
+    /// Defaults to one day, like Python.
+    pub fn result_expires(mut self, result_expires: Option<Duration>) -> Self {
+        self.config.result_expires = result_expires;
+        self
+    }
+
+    /// Get the directory from the URL, which must be either `file:///path/to/dir` or
+    /// `file://localhost/path/to/dir`, like in Python.
+    fn path(&self) -> Result<PathBuf, BackendError> {
+        let url = &self.config.backend_url;
+        if let Some(path) = url.strip_prefix("file://localhost/") {
+            Ok(PathBuf::from(format!("/{}", path)))
+        } else if let Some(path) = url.strip_prefix("file:///") {
+            Ok(PathBuf::from(format!("/{}", path)))
+        } else {
+            Err(BackendError::InvalidBackendUrl(url.clone()))
+        }
+    }
+}
+
+#[async_trait]
+impl ResultBackendBuilder for FilesystemBackendBuilder {
+    type Backend = FilesystemBackend;
+
+    /// Create a new `FilesystemBackendBuilder`.
+    fn new(backend_url: &str) -> Self {
+        Self {
+            config: Config {
+                backend_url: backend_url.into(),
+                result_expires: Some(Duration::from_secs(24 * 60 * 60)),
+            },
+        }
+    }
+
+    /// Build a `FilesystemBackend`. The directory must already exist.
+    async fn build(&self, _connection_timeout: u32) -> Result<FilesystemBackend, BackendError> {
+        let path = self.path()?;
+        if !fs::metadata(&path).await?.is_dir() {
+            return Err(BackendError::InvalidBackendUrl(
+                self.config.backend_url.clone(),
+            ));
+        }
+        let backend = FilesystemBackend {
+            uri: self.config.backend_url.clone(),
+            path,
+            result_expires: self.config.result_expires,
+        };
+        backend.cleanup().await?;
+        Ok(backend)
+    }
+}
+
+/// A result backend that stores the result of each task in its own file in a directory,
+/// like Python's `FilesystemBackend`.
+///
+/// Files are named after the task (`celery-task-meta-<task_id>`) or the group
+/// (`celery-taskset-meta-<group_id>`) and contain the JSON encoded meta data, so the
+/// directory can be shared with Python apps, for example over a mounted volume. Files are
+/// written to a temporary file first and then renamed, so readers never see a partially
+/// written result.
+///
+/// Expired results are swept when the backend is built and whenever
+/// [`cleanup`](ResultBackend::cleanup) is called, along with expired temporary files left
+/// behind by apps that stopped while writing them. Expired results that haven't been swept
+/// yet are treated as missing.
+pub struct FilesystemBackend {
+    uri: String,
+    path: PathBuf,
+    result_expires: Option<Duration>,
+}
+
+impl FilesystemBackend {
+    /// Write a file atomically by writing to a temporary file first and renaming it.
+    async fn write(&self, key: &str, value: Vec<u8>) -> Result<(), BackendError> {
+        let tmp_path = self
+            .path
+            .join(format!(".{}.{}.tmp", key, uuid::Uuid::new_v4()));
+        fs::write(&tmp_path, value).await?;
+        if let Err(e) = fs::rename(&tmp_path, self.path.join(key)).await {
+            fs::remove_file(&tmp_path).await.ok();
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Read a file, treating expired files as missing.
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        let path = self.path.join(key);
+        let value = match fs::read(&path).await {
+            Ok(value) => value,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if self.is_expired(&path).await? {
+            return Ok(None);
+        }
+        Ok(Some(value))
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), BackendError> {
+        match fs::remove_file(self.path.join(key)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check if the file at `path` was last written longer ago than `result_expires`.
+    async fn is_expired(&self, path: &Path) -> Result<bool, BackendError> {
+        let result_expires = match self.result_expires {
+            Some(result_expires) => result_expires,
+            None => return Ok(false),
+        };
+        let modified = match fs::metadata(path).await {
+            Ok(metadata) => metadata.modified()?,
+            // Removed in the meantime.
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(SystemTime::now()
+            .duration_since(modified)
+            .map(|age| age > result_expires)
+            .unwrap_or(false))
+    }
+}
+
+#[async_trait]
+impl ResultBackend for FilesystemBackend {
+    fn safe_url(&self) -> String {
+        self.uri.clone()
+    }
+
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError> {
+        self.write(&task_key(&meta.task_id), serde_json::to_vec(meta)?)
+            .await
+    }
+
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError> {
+        match self.read(&task_key(task_id)).await? {
+            Some(value) => Ok(serde_json::from_slice(&value)?),
+            None => Ok(TaskMeta::pending(task_id)),
+        }
+    }
+
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError> {
+        self.remove(&task_key(task_id)).await
+    }
+
+    async fn save_group(&self, meta: &GroupMeta) -> Result<(), BackendError> {
+        self.write(&group_key(&meta.group_id), serde_json::to_vec(meta)?)
+            .await
+    }
+
+    async fn restore_group(&self, group_id: &str) -> Result<Option<GroupMeta>, BackendError> {
+        match self.read(&group_key(group_id)).await? {
+            Some(value) => Ok(Some(GroupMeta {
+                group_id: group_id.into(),
+                ..serde_json::from_slice(&value)?
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), BackendError> {
+        self.remove(&group_key(group_id)).await
+    }
+
+    async fn cleanup(&self) -> Result<(), BackendError> {
+        if self.result_expires.is_none() {
+            return Ok(());
+        }
+        let mut entries = fs::read_dir(&self.path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            // Temporary files are named after the result, see `write`.
+            let is_result = entry
+                .file_name()
+                .to_str()
+                .map(|name| match name.strip_prefix('.') {
+                    Some(tmp_name) => tmp_name.ends_with(".tmp") && is_result_key(tmp_name),
+                    None => is_result_key(name),
+                })
+                .unwrap_or(false);
+            if is_result && self.is_expired(&entry.path()).await? {
+                debug!("Removing expired result {:?}", entry.path());
+                match fs::remove_file(entry.path()).await {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+                    _ => {}
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if `name` starts like the key of a task or group result.
+fn is_result_key(name: &str) -> bool {
+    name.starts_with(TASK_KEY_PREFIX) || name.starts_with(GROUP_KEY_PREFIX)
+}
@@ -0,0 +1,199 @@
+//! In-memory result backend.
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::time::{self, Duration};
+
+use super::{GroupMeta, ResultBackend, ResultBackendBuilder, TaskMeta};
+use crate::error::BackendError;
+
+/// Stores of all memory backends in the process, keyed by URL, so that apps built with the
+/// same URL share their results.
+static STORES: Lazy<Mutex<HashMap<String, Arc<Store>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct Store {
+    results: Mutex<HashMap<String, TaskMeta>>,
+    groups: Mutex<HashMap<String, GroupMeta>>,
+    chords: Mutex<HashMap<String, ChordState>>,
+
+    /// Notified whenever a result is stored.
+    notify: Notify,
+}
+
+/// The results of the header of a chord that is being joined.
+#[derive(Default)]
+struct ChordState {
+    size: usize,
+    results: Vec<(Option<u32>, TaskMeta)>,
+}
+
+struct Config {
+    backend_url: String,
+}
+
+/// Builds a [`MemoryBackend`] with a custom configuration.
+pub struct MemoryBackendBuilder {
+    config: Config,
+}
+
+#[async_trait]
+impl ResultBackendBuilder for MemoryBackendBuilder {
+    type Backend = MemoryBackend;
+
+    /// Create a new `MemoryBackendBuilder`.
+    fn new(backend_url: &str) -> Self {
+        Self {
+            config: Config {
+                backend_url: backend_url.into(),
+            },
+        }
+    }
+
+    /// Build a `MemoryBackend`.
+    async fn build(&self, _connection_timeout: u32) -> Result<MemoryBackend, BackendError> {
+        if !self.config.backend_url.starts_with("memory://") {
+            return Err(BackendError::InvalidBackendUrl(
+                self.config.backend_url.clone(),
+            ));
+        }
+        let store = STORES
+            .lock()
+            .unwrap()
+            .entry(self.config.backend_url.clone())
+            .or_default()
+            .clone();
+        Ok(MemoryBackend {
+            uri: self.config.backend_url.clone(),
+            store,
+        })
+    }
+}
+
+/// A result backend that keeps results in memory.
+///
+/// All memory backends in a process that are built with the same URL, e.g. `memory://`,
+/// share the same results, so a producer and a worker running in the same process can
+/// exchange results without any external service. Waiting for a result doesn't poll: it
+/// wakes up as soon as the result is stored, and chords are joined natively.
+pub struct MemoryBackend {
+    uri: String,
+    store: Arc<Store>,
+}
+
+#[async_trait]
+impl ResultBackend for MemoryBackend {
+    fn safe_url(&self) -> String {
+        self.uri.clone()
+    }
+
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError> {
+        self.store
+            .results
+            .lock()
+            .unwrap()
+            .insert(meta.task_id.clone(), meta.clone());
+        self.store.notify.notify_waiters();
+        Ok(())
+    }
+
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError> {
+        Ok(self
+            .store
+            .results
+            .lock()
+            .unwrap()
+            .get(task_id)
+            .cloned()
+            .unwrap_or_else(|| TaskMeta::pending(task_id)))
+    }
+
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError> {
+        self.store.results.lock().unwrap().remove(task_id);
+        Ok(())
+    }
+
+    async fn save_group(&self, meta: &GroupMeta) -> Result<(), BackendError> {
+        self.store
+            .groups
+            .lock()
+            .unwrap()
+            .insert(meta.group_id.clone(), meta.clone());
+        Ok(())
+    }
+
+    async fn restore_group(&self, group_id: &str) -> Result<Option<GroupMeta>, BackendError> {
+        Ok(self.store.groups.lock().unwrap().get(group_id).cloned())
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), BackendError> {
+        self.store.groups.lock().unwrap().remove(group_id);
+        Ok(())
+    }
+
+    fn supports_native_join(&self) -> bool {
+        true
+    }
+
+    async fn apply_chord(&self, group_id: &str, chord_size: usize) -> Result<(), BackendError> {
+        self.store
+            .chords
+            .lock()
+            .unwrap()
+            .entry(group_id.into())
+            .or_default()
+            .size = chord_size;
+        Ok(())
+    }
+
+    async fn on_chord_part_return(
+        &self,
+        group_id: &str,
+        group_index: Option<u32>,
+        meta: &TaskMeta,
+    ) -> Result<Option<Vec<TaskMeta>>, BackendError> {
+        let mut chords = self.store.chords.lock().unwrap();
+        let chord = chords.entry(group_id.into()).or_default();
+        // A task that is delivered again replaces its previous result.
+        chord
+            .results
+            .retain(|(_, result)| result.task_id != meta.task_id);
+        chord.results.push((group_index, meta.clone()));
+        if chord.results.len() != chord.size {
+            return Ok(None);
+        }
+        let mut results = chords.remove(group_id).unwrap_or_default().results;
+        // Tasks without an index go last, like in Redis.
+        results.sort_by_key(|(group_index, _)| group_index.unwrap_or(u32::MAX));
+        Ok(Some(results.into_iter().map(|(_, meta)| meta).collect()))
+    }
+
+    /// Wait for the result to be stored. `interval` is ignored since waiters are notified
+    /// as soon as a result is stored.
+    async fn wait_for(
+        &self,
+        task_id: &str,
+        timeout: Option<Duration>,
+        _interval: Duration,
+    ) -> Result<TaskMeta, BackendError> {
+        let wait = async {
+            loop {
+                let notified = self.store.notify.notified();
+                let meta = self.get_task_meta(task_id).await?;
+                if meta.status.is_ready() {
+                    return Ok(meta);
+                }
+                notified.await;
+            }
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, wait)
+                .await
+                .map_err(|_| BackendError::Timeout)?,
+            None => wait.await,
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! The result backend is an optional part of a [`Celery`](crate::Celery) app. It stores the
+//! states and return values of tasks so that producers can retrieve them later through an
+//! [`AsyncResult`](crate::task::AsyncResult).
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use crate::error::{BackendError, TaskError};
+use crate::task::TaskState;
+
+mod filesystem;
+mod memory;
+mod redis;
+mod rpc;
+#[cfg(any(test, feature = "sqlite_backend"))]
+mod sqlite;
+pub use self::filesystem::{FilesystemBackend, FilesystemBackendBuilder};
+pub use self::memory::{MemoryBackend, MemoryBackendBuilder};
+pub use self::redis::{RedisBackend, RedisBackendBuilder};
+pub use self::rpc::{RpcBackend, RpcBackendBuilder};
+#[cfg(any(test, feature = "sqlite_backend"))]
+pub use self::sqlite::{SqliteBackend, SqliteBackendBuilder};
+
+/// Key prefix for task results. This is the same prefix used by Python's key-value store
+/// backends.
+pub(crate) const TASK_KEY_PREFIX: &str = "celery-task-meta-";
+
+/// Get the key that the result of the task with the given ID is stored under.
+pub(crate) fn task_key(task_id: &str) -> String {
+    format!("{}{}", TASK_KEY_PREFIX, task_id)
+}
+
+/// Key prefix for group results, the same as in Python.
+pub(crate) const GROUP_KEY_PREFIX: &str = "celery-taskset-meta-";
+
+/// Get the key that the results of the group with the given ID are stored under.
+pub(crate) fn group_key(group_id: &str) -> String {
+    format!("{}{}", GROUP_KEY_PREFIX, group_id)
+}
+
+/// A [`ResultBackend`] is used to store and retrieve the states and results of tasks.
+#[async_trait]
+pub trait ResultBackend: Send + Sync {
+    /// Return a string representation of the backend URL with any sensitive information
+    /// redacted.
+    fn safe_url(&self) -> String;
+
+    /// Get the name of the queue that workers should send results to, for backends that
+    /// receive results as messages. This is called before a task is sent and sets the
+    /// `reply_to` property of the task message.
+    async fn reply_to(&self) -> Result<Option<String>, BackendError> {
+        Ok(None)
+    }
+
+    /// Store the meta data of a task, replacing any meta data previously stored for it.
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError>;
+
+    /// Get the meta data of a task. If nothing is stored for the task yet, this should
+    /// return [`TaskMeta::pending`].
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError>;
+
+    /// Remove the meta data of a task.
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError>;
+
+    /// Store the meta data of a group of tasks. Backends that don't support groups return
+    /// [`BackendError::NotSupported`].
+    async fn save_group(&self, _meta: &GroupMeta) -> Result<(), BackendError> {
+        Err(BackendError::NotSupported("groups".into()))
+    }
+
+    /// Get the meta data of a group of tasks, if it was saved.
+    async fn restore_group(&self, _group_id: &str) -> Result<Option<GroupMeta>, BackendError> {
+        Err(BackendError::NotSupported("groups".into()))
+    }
+
+    /// Remove the meta data of a group of tasks.
+    async fn delete_group(&self, _group_id: &str) -> Result<(), BackendError> {
+        Err(BackendError::NotSupported("groups".into()))
+    }
+
+    /// Whether the backend joins chords itself, by counting the tasks of the header of a
+    /// chord as they finish in [`ResultBackend::on_chord_part_return`]. Other backends join
+    /// chords by polling the results of the header with the `celery.chord_unlock` task, like
+    /// in Python.
+    fn supports_native_join(&self) -> bool {
+        false
+    }
+
+    /// Whether the backend can be used for chords. Backends that don't join chords natively
+    /// need any worker to be able to get the results of the header with
+    /// [`ResultBackend::get_task_meta`], which backends that only deliver results to the app
+    /// that sent the task, like the `rpc://` backend, can't do.
+    fn supports_chords(&self) -> bool {
+        true
+    }
+
+    /// Prepare to join a chord whose header is the group with the given ID and number of
+    /// tasks. This is only called if the backend
+    /// [supports native joins](ResultBackend::supports_native_join).
+    async fn apply_chord(&self, _group_id: &str, _chord_size: usize) -> Result<(), BackendError> {
+        Err(BackendError::NotSupported("chords".into()))
+    }
+
+    /// Record that a task of the header of a chord finished with the given meta data.
+    ///
+    /// Returns the meta data of all the tasks of the header, ordered by their index in the
+    /// group, to the call for the last task to finish, and `None` to the others, so that the
+    /// callback of the chord runs exactly once. This is only called if the backend
+    /// [supports native joins](ResultBackend::supports_native_join).
+    async fn on_chord_part_return(
+        &self,
+        _group_id: &str,
+        _group_index: Option<u32>,
+        _meta: &TaskMeta,
+    ) -> Result<Option<Vec<TaskMeta>>, BackendError> {
+        Err(BackendError::NotSupported("chords".into()))
+    }
+
+    /// Remove expired results. This does nothing by default, for backends that expire
+    /// results on their own or never expire them.
+    async fn cleanup(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    /// Wait until the task is [ready](TaskState::is_ready) and return its meta data.
+    ///
+    /// By default this polls [`ResultBackend::get_task_meta`] every `interval` until the task
+    /// is ready or the `timeout` expires, in which case a [`BackendError::Timeout`] is returned.
+    async fn wait_for(
+        &self,
+        task_id: &str,
+        timeout: Option<Duration>,
+        interval: Duration,
+    ) -> Result<TaskMeta, BackendError> {
+        let poll = async {
+            loop {
+                let meta = self.get_task_meta(task_id).await?;
+                if meta.status.is_ready() {
+                    return Ok(meta);
+                }
+                time::sleep(interval).await;
+            }
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, poll)
+                .await
+                .map_err(|_| BackendError::Timeout)?,
+            None => poll.await,
+        }
+    }
+
+    /// Get a stream of the meta data of the task whenever its state or its result changes,
+    /// starting with the meta data currently stored and ending with the meta data of its
+    /// [ready](TaskState::is_ready) state.
+    ///
+    /// By default this polls [`ResultBackend::get_task_meta`] every `interval`.
+    fn stream_states<'a>(
+        &'a self,
+        task_id: &'a str,
+        interval: Duration,
+    ) -> BoxStream<'a, Result<TaskMeta, BackendError>> {
+        // The state is `None` once the stream has ended, and holds the last meta data
+        // yielded otherwise.
+        stream::unfold(
+            Some(None),
+            move |last: Option<Option<TaskMeta>>| async move {
+                let last = last?;
+                loop {
+                    let meta = match self.get_task_meta(task_id).await {
+                        Ok(meta) => meta,
+                        Err(e) => return Some((Err(e), None)),
+                    };
+                    if has_changed(last.as_ref(), &meta) {
+                        let next = if meta.status.is_ready() {
+                            None
+                        } else {
+                            Some(Some(meta.clone()))
+                        };
+                        return Some((Ok(meta), next));
+                    }
+                    time::sleep(interval).await;
+                }
+            },
+        )
+        .boxed()
+    }
+}
+
+/// Check if `meta` should be yielded by [`ResultBackend::stream_states`] after `last`, the
+/// meta data it yielded last.
+pub(crate) fn has_changed(last: Option<&TaskMeta>, meta: &TaskMeta) -> bool {
+    match last {
+        Some(last) => last.status != meta.status || last.result != meta.result,
+        None => true,
+    }
+}
+
+/// A [`ResultBackendBuilder`] is used to create a type of result backend with a custom
+/// configuration.
+#[async_trait]
+pub trait ResultBackendBuilder {
+    type Backend: ResultBackend;
+
+    /// Create a new `ResultBackendBuilder`.
+    fn new(backend_url: &str) -> Self;
+
+    /// Construct the `ResultBackend` with the given configuration.
+    async fn build(&self, connection_timeout: u32) -> Result<Self::Backend, BackendError>;
+}
+
+/// The meta data of a task that is stored in a [`ResultBackend`].
+///
+/// This has the same shape as the meta data stored by Python Celery, so results can be
+/// shared between Rust and Python workers and producers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskMeta {
+    /// The ID of the task.
+    pub task_id: String,
+
+    /// The current state of the task.
+    pub status: TaskState,
+
+    /// The serialized return value of the task when it succeeded, or a serialized
+    /// exception when it failed.
+    #[serde(default)]
+    pub result: Value,
+
+    /// A traceback of the error. Only Python workers set this.
+    #[serde(default)]
+    pub traceback: Option<String>,
+
+    /// Results of tasks that were spawned by this task.
+    #[serde(default)]
+    pub children: Vec<Value>,
+
+    /// The time at which the task finished.
+    #[serde(default, with = "date_done_format")]
+    pub date_done: Option<DateTime<Utc>>,
+
+    /// The name of the task. This and the following fields are only stored when
+    /// [`result_extended`](crate::CeleryBuilder::result_extended) is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// The positional arguments of the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub args: Option<Value>,
+
+    /// The keyword arguments of the task. Rust tasks send their parameters as keyword
+    /// arguments.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kwargs: Option<Value>,
+
+    /// Node name of the worker that executed the task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker: Option<String>,
+
+    /// How many times the task was retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+
+    /// The queue the task was consumed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queue: Option<String>,
+
+    /// The `reply_to` property of the task message. This is only used by backends that
+    /// send results as messages and isn't stored.
+    #[serde(skip)]
+    pub reply_to: Option<String>,
+}
+
+impl TaskMeta {
+    /// Meta data of a task that hasn't been executed yet or is unknown.
+    pub fn pending(task_id: &str) -> Self {
+        Self {
+            task_id: task_id.into(),
+            status: TaskState::Pending,
+            result: Value::Null,
+            traceback: None,
+            children: vec![],
+            date_done: None,
+            name: None,
+            args: None,
+            kwargs: None,
+            worker: None,
+            retries: None,
+            queue: None,
+            reply_to: None,
+        }
+    }
+
+    /// Meta data of a task in the given state, such as a custom state set through
+    /// [`Task::update_state`](crate::task::Task::update_state).
+    pub fn with_status(task_id: &str, status: TaskState, result: Value) -> Self {
+        Self {
+            status,
+            result,
+            ..Self::pending(task_id)
+        }
+    }
+
+    /// Meta data of a task that was started by the worker with the given node name. Like
+    /// in Python, the result holds the node name and the process ID of the worker.
+    pub fn started(task_id: &str, hostname: &str) -> Self {
+        Self::with_status(
+            task_id,
+            TaskState::Started,
+            json!({ "pid": std::process::id(), "hostname": hostname }),
+        )
+    }
+
+    /// Meta data of a task that finished successfully with the serialized return value `result`.
+    pub fn success(task_id: &str, result: Value) -> Self {
+        Self {
+            status: TaskState::Success,
+            result,
+            date_done: Some(Utc::now()),
+            ..Self::pending(task_id)
+        }
+    }
+
+    /// Meta data of a task that failed with the given error.
+    pub fn failure(task_id: &str, err: &TaskError) -> Self {
+        Self {
+            status: TaskState::Failure,
+            result: exception_to_value(err),
+            date_done: Some(Utc::now()),
+            ..Self::pending(task_id)
+        }
+    }
+
+    /// Meta data of a task that failed with the given error and will be retried.
+    pub fn retry(task_id: &str, err: &TaskError) -> Self {
+        Self {
+            status: TaskState::Retry,
+            result: exception_to_value(err),
+            ..Self::pending(task_id)
+        }
+    }
+
+    /// Meta data of a task that was revoked for the given reason, e.g. `"terminated"`. Like
+    /// in Python, the result is a `TaskRevokedError`.
+    pub fn revoked(task_id: &str, reason: &str) -> Self {
+        Self {
+            status: TaskState::Revoked,
+            result: json!({
+                "exc_type": "TaskRevokedError",
+                "exc_message": [reason],
+                "exc_module": "celery.exceptions",
+            }),
+            date_done: Some(Utc::now()),
+            ..Self::pending(task_id)
+        }
+    }
+
+    /// Get the error stored in the meta data, if the task failed.
+    pub fn error(&self) -> Option<TaskError> {
+        if matches!(self.status, TaskState::Failure | TaskState::Retry) {
+            Some(value_to_exception(&self.result))
+        } else {
+            None
+        }
+    }
+}
+
+/// The meta data of a group of tasks that is stored in a [`ResultBackend`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupMeta {
+    /// The ID of the group.
+    #[serde(skip)]
+    pub group_id: String,
+
+    /// The results of the tasks in the group, in the same format as Python's
+    /// `GroupResult.as_tuple()`.
+    pub result: Value,
+
+    /// The time at which the group was saved.
+    #[serde(default, with = "date_done_format")]
+    pub date_done: Option<DateTime<Utc>>,
+}
+
+impl GroupMeta {
+    pub fn new(group_id: &str, result: Value) -> Self {
+        Self {
+            group_id: group_id.into(),
+            result,
+            date_done: Some(Utc::now()),
+        }
+    }
+}
+
+/// Serialize a [`TaskError`] the same way Python Celery serializes exceptions.
+///
+/// The module is left unset so that Python will create a matching exception class on the fly
+/// when reading the result.
+pub(crate) fn exception_to_value(err: &TaskError) -> Value {
+    let (exc_type, exc_message) = match err {
+        TaskError::ExpectedError(reason) => ("ExpectedError", json!([reason])),
+        TaskError::UnexpectedError(reason) => ("UnexpectedError", json!([reason])),
+        TaskError::TimeoutError => ("TimeoutError", json!([])),
+        TaskError::Retry(eta) => ("Retry", json!([eta.map(|eta| eta.to_rfc3339())])),
+        TaskError::Replaced => ("Replaced", json!([])),
+        TaskError::WorkerLostError(reason) => ("WorkerLostError", json!([reason])),
+    };
+    json!({
+        "exc_type": exc_type,
+        "exc_message": exc_message,
+        "exc_module": Value::Null,
+    })
+}
+
+/// Deserialize an exception stored by [`exception_to_value`] or by a Python worker into a
+/// [`TaskError`].
+///
+/// Exceptions raised by Python tasks don't have a corresponding variant, so they are
+/// treated as an [`UnexpectedError`](TaskError::UnexpectedError).
+fn value_to_exception(value: &Value) -> TaskError {
+    let exc_type = value
+        .get("exc_type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("UnknownError");
+    let exc_message: Vec<String> = match value.get("exc_message") {
+        Some(Value::Array(args)) => args
+            .iter()
+            .filter(|arg| !arg.is_null())
+            .map(|arg| match arg {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect(),
+        Some(Value::String(s)) => vec![s.clone()],
+        _ => vec![],
+    };
+    let reason = exc_message.join(", ");
+    match exc_type {
+        "ExpectedError" => TaskError::ExpectedError(reason),
+        "UnexpectedError" => TaskError::UnexpectedError(reason),
+        "TimeoutError" | "TimeLimitExceeded" | "SoftTimeLimitExceeded" => TaskError::TimeoutError,
+        "WorkerLostError" => TaskError::WorkerLostError(reason),
+        "Replaced" => TaskError::Replaced,
+        "Retry" => TaskError::Retry(
+            exc_message
+                .first()
+                .and_then(|eta| DateTime::parse_from_rfc3339(eta).ok())
+                .map(DateTime::<Utc>::from),
+        ),
+        _ => TaskError::UnexpectedError(format!("{}: {}", exc_type, reason)),
+    }
+}
+
+/// Python stores `date_done` as a naive ISO 8601 timestamp in UTC, so we write it without an
+/// offset and accept it with or without one.
+mod date_done_format {
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f";
+
+    pub(super) fn serialize<S>(
+        date_done: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match date_done {
+            Some(dt) => serializer.serialize_str(&dt.format(FORMAT).to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(super) fn deserialize<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s: Option<String> = Option::deserialize(deserializer)?;
+        Ok(s.and_then(|s| {
+            DateTime::parse_from_rfc3339(&s)
+                .map(DateTime::<Utc>::from)
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(&s, FORMAT)
+                        .map(|dt| DateTime::<Utc>::from_utc(dt, Utc))
+                })
+                .ok()
+        }))
+    }
+}
+
+/// A utility function that creates the result backend matching the scheme of the given URL.
+///
+/// The broker URL is needed for the `rpc://` backend, which sends results through the broker.
+pub(crate) async fn build_backend(
+    backend_url: &str,
+    broker_url: &str,
+    connection_timeout: u32,
+    result_expires: Option<Duration>,
+) -> Result<Arc<dyn ResultBackend>, BackendError> {
+    let scheme = backend_url.split("://").next().unwrap_or_default();
+    match scheme {
+        "redis" | "rediss" => Ok(Arc::new(
+            RedisBackendBuilder::new(backend_url)
+                .result_expires(result_expires)
+                .build(connection_timeout)
+                .await?,
+        )),
+        "file" => Ok(Arc::new(
+            FilesystemBackendBuilder::new(backend_url)
+                .result_expires(result_expires)
+                .build(connection_timeout)
+                .await?,
+        )),
+        "memory" => Ok(Arc::new(
+            MemoryBackendBuilder::new(backend_url)
+                .build(connection_timeout)
+                .await?,
+        )),
+        #[cfg(any(test, feature = "sqlite_backend"))]
+        "db+sqlite" => Ok(Arc::new(
+            SqliteBackendBuilder::new(backend_url)
+                .result_expires(result_expires)
+                .build(connection_timeout)
+                .await?,
+        )),
+        "rpc" => Ok(Arc::new(
+            RpcBackendBuilder::new(backend_url)
+                .broker_url(broker_url)
+                .build(connection_timeout)
+                .await?,
+        )),
+        _ => Err(BackendError::InvalidBackendUrl(backend_url.into())),
+    }
+}
+
+#[cfg(test)]
+mod tests;
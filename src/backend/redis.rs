@@ -0,0 +1,521 @@
+//! Redis result backend.
+
+use super::{
+    group_key, has_changed, task_key, GroupMeta, ResultBackend, ResultBackendBuilder, TaskMeta,
+    TASK_KEY_PREFIX,
+};
+use crate::error::BackendError;
+use crate::task::TaskState;
+use async_trait::async_trait;
+use futures::future::{AbortHandle, Abortable};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use log::{error, warn};
+use redis::aio::ConnectionManager;
+use redis::{Client, Msg};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::mpsc;
+use tokio::time::{self, Duration};
+
+struct Config {
+    backend_url: String,
+    result_expires: Option<Duration>,
+}
+
+/// Builds a [`RedisBackend`] with a custom configuration.
+pub struct RedisBackendBuilder {
+    config: Config,
+}
+
+impl RedisBackendBuilder {
+    /// Set how long results are kept before Redis expires them. `None` keeps them forever.
+    /// Defaults to one day, like Python.
+    pub fn result_expires(mut self, result_expires: Option<Duration>) -> Self {
+        self.config.result_expires = result_expires;
+        self
+    }
+}
+
+#[async_trait]
+impl ResultBackendBuilder for RedisBackendBuilder {
+    type Backend = RedisBackend;
+
+    /// Create a new `RedisBackendBuilder`.
+    fn new(backend_url: &str) -> Self {
+        Self {
+            config: Config {
+                backend_url: backend_url.into(),
+                result_expires: Some(Duration::from_secs(24 * 60 * 60)),
+            },
+        }
+    }
+
+    /// Build a `RedisBackend`.
+    async fn build(&self, _connection_timeout: u32) -> Result<RedisBackend, BackendError> {
+        let client = Client::open(&self.config.backend_url[..])
+            .map_err(|_| BackendError::InvalidBackendUrl(self.config.backend_url.clone()))?;
+        let manager = client.get_tokio_connection_manager().await?;
+        Ok(RedisBackend {
+            uri: self.config.backend_url.clone(),
+            client,
+            manager,
+            consumer: tokio::sync::Mutex::new(None),
+            result_expires: self.config.result_expires,
+        })
+    }
+}
+
+/// A result backend that stores task results in Redis under the same keys as Python's
+/// `RedisBackend`, i.e. `celery-task-meta-<task_id>`.
+///
+/// Like in Python, the meta data of tasks that are [ready](crate::task::TaskState::is_ready)
+/// is also published on a channel named after the key, so waiting for a result doesn't
+/// need to poll Redis: [`wait_for`](ResultBackend::wait_for) and
+/// [`stream_states`](ResultBackend::stream_states) subscribe to the channel and are notified
+/// as soon as the task is done. All of them share a single connection per backend, which is
+/// subscribed to the channels of all tasks with a pattern, however many results are waited
+/// for at the same time. If it can't be connected, for instance over TLS, results are polled
+/// instead.
+///
+/// Chords are joined natively: the results of the header of a chord are counted with an
+/// atomic transaction as the tasks finish, instead of polling them with the
+/// `celery.chord_unlock` task.
+pub struct RedisBackend {
+    uri: String,
+    client: Client,
+    manager: ConnectionManager,
+    consumer: tokio::sync::Mutex<Option<ResultConsumer>>,
+    result_expires: Option<Duration>,
+}
+
+/// The state of the stream returned by [`RedisBackend::stream_states`].
+enum StreamState {
+    /// Not subscribed to the channel of the task yet.
+    Subscribing,
+
+    /// Subscribed to the channel of the task, with the meta data that was yielded last.
+    Subscribed(BoxStream<'static, Vec<u8>>, Option<Box<TaskMeta>>),
+
+    /// The task is ready or an error occurred.
+    Done,
+}
+
+#[async_trait]
+impl ResultBackend for RedisBackend {
+    fn safe_url(&self) -> String {
+        match redis::parse_redis_url(&self.uri[..]) {
+            Some(url) => format!(
+                "{}://{}:***@{}:{}/{}",
+                url.scheme(),
+                url.username(),
+                url.host_str().unwrap_or_default(),
+                url.port().unwrap_or(6379),
+                url.path(),
+            ),
+            None => {
+                error!("Invalid redis url.");
+                String::from("")
+            }
+        }
+    }
+
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError> {
+        let key = task_key(&meta.task_id);
+        let value = serde_json::to_string(meta)?;
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        let cmd = pipe.cmd("SET").arg(&key).arg(&value);
+        if let Some(result_expires) = self.result_expires {
+            // Redis doesn't accept an expiry of 0 seconds.
+            cmd.arg("EX")
+                .arg(std::cmp::max(result_expires.as_secs(), 1));
+        }
+        cmd.ignore();
+        if meta.status.is_ready() {
+            pipe.cmd("PUBLISH").arg(&key).arg(&value).ignore();
+        }
+        pipe.query_async::<_, ()>(&mut self.manager.clone()).await?;
+        Ok(())
+    }
+
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError> {
+        let value: Option<String> = redis::cmd("GET")
+            .arg(task_key(task_id))
+            .query_async(&mut self.manager.clone())
+            .await?;
+        match value {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(TaskMeta::pending(task_id)),
+        }
+    }
+
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError> {
+        redis::cmd("DEL")
+            .arg(task_key(task_id))
+            .query_async::<_, ()>(&mut self.manager.clone())
+            .await?;
+        Ok(())
+    }
+
+    async fn save_group(&self, meta: &GroupMeta) -> Result<(), BackendError> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(group_key(&meta.group_id))
+            .arg(serde_json::to_string(meta)?);
+        if let Some(result_expires) = self.result_expires {
+            cmd.arg("EX")
+                .arg(std::cmp::max(result_expires.as_secs(), 1));
+        }
+        cmd.query_async::<_, ()>(&mut self.manager.clone()).await?;
+        Ok(())
+    }
+
+    async fn restore_group(&self, group_id: &str) -> Result<Option<GroupMeta>, BackendError> {
+        let value: Option<String> = redis::cmd("GET")
+            .arg(group_key(group_id))
+            .query_async(&mut self.manager.clone())
+            .await?;
+        match value {
+            Some(value) => Ok(Some(GroupMeta {
+                group_id: group_id.into(),
+                ..serde_json::from_str(&value)?
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), BackendError> {
+        redis::cmd("DEL")
+            .arg(group_key(group_id))
+            .query_async::<_, ()>(&mut self.manager.clone())
+            .await?;
+        Ok(())
+    }
+
+    fn supports_native_join(&self) -> bool {
+        true
+    }
+
+    /// Store the size of the chord under `celery-taskset-meta-<group_id>.s`, like Python.
+    async fn apply_chord(&self, group_id: &str, chord_size: usize) -> Result<(), BackendError> {
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(format!("{}.s", group_key(group_id)))
+            .arg(chord_size);
+        if let Some(result_expires) = self.result_expires {
+            cmd.arg("EX")
+                .arg(std::cmp::max(result_expires.as_secs(), 1));
+        }
+        cmd.query_async::<_, ()>(&mut self.manager.clone()).await?;
+        Ok(())
+    }
+
+    /// Add the result to the sorted set `celery-taskset-meta-<group_id>.j` and count the
+    /// results in the same transaction, like Python's `RedisBackend`, so that only the last
+    /// task of the header sees the complete set.
+    async fn on_chord_part_return(
+        &self,
+        group_id: &str,
+        group_index: Option<u32>,
+        meta: &TaskMeta,
+    ) -> Result<Option<Vec<TaskMeta>>, BackendError> {
+        let jkey = format!("{}.j", group_key(group_id));
+        let tkey = format!("{}.t", group_key(group_id));
+        let skey = format!("{}.s", group_key(group_id));
+        let encoded = serde_json::to_string(&json!([1, meta.task_id, meta.status, meta.result]))?;
+        let score = match group_index {
+            Some(group_index) => group_index.to_string(),
+            None => "+inf".into(),
+        };
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .cmd("ZADD")
+            .arg(&jkey)
+            .arg(score)
+            .arg(encoded)
+            .ignore()
+            .cmd("ZCOUNT")
+            .arg(&jkey)
+            .arg("-inf")
+            .arg("+inf")
+            .cmd("GET")
+            .arg(&tkey)
+            .cmd("GET")
+            .arg(&skey);
+        if let Some(result_expires) = self.result_expires {
+            let seconds = std::cmp::max(result_expires.as_secs(), 1);
+            for key in [&jkey, &tkey, &skey].iter() {
+                pipe.cmd("EXPIRE").arg(*key).arg(seconds).ignore();
+            }
+        }
+        let (ready_count, total_diff, chord_size): (i64, Option<i64>, Option<i64>) =
+            pipe.query_async(&mut self.manager.clone()).await?;
+
+        // The total differs from the size of the chord if the header was extended, for
+        // instance by a replaced task.
+        match chord_size {
+            Some(chord_size) if ready_count == chord_size + total_diff.unwrap_or(0) => {}
+            _ => return Ok(None),
+        }
+
+        let (entries,): (Vec<String>,) = redis::pipe()
+            .atomic()
+            .cmd("ZRANGE")
+            .arg(&jkey)
+            .arg(0)
+            .arg(-1)
+            .cmd("DEL")
+            .arg(&jkey)
+            .arg(&tkey)
+            .arg(&skey)
+            .ignore()
+            .query_async(&mut self.manager.clone())
+            .await?;
+        let metas = entries
+            .iter()
+            .map(|entry| {
+                let (_, task_id, status, result): (Value, String, TaskState, Value) =
+                    serde_json::from_str(entry)?;
+                Ok(TaskMeta::with_status(&task_id, status, result))
+            })
+            .collect::<Result<_, BackendError>>()?;
+        Ok(Some(metas))
+    }
+
+    /// Wait for the result to be published on the channel of the task. `interval` is only
+    /// used to poll for the result if subscribing fails or the subscription is lost.
+    async fn wait_for(
+        &self,
+        task_id: &str,
+        timeout: Option<Duration>,
+        interval: Duration,
+    ) -> Result<TaskMeta, BackendError> {
+        let wait = async {
+            let mut states = self.stream_states(task_id, interval);
+            while let Some(meta) = states.next().await {
+                let meta = meta?;
+                if meta.status.is_ready() {
+                    return Ok(meta);
+                }
+            }
+            unreachable!("the stream ends with a ready state or an error");
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, wait)
+                .await
+                .map_err(|_| BackendError::Timeout)?,
+            None => wait.await,
+        }
+    }
+
+    /// Stream the states of the task. The stream subscribes to the channel of the task
+    /// before getting the stored meta data, so a result stored in the meantime can't be
+    /// missed. Ready states are received as soon as they are published, while other states,
+    /// which aren't published, are polled every `interval`.
+    fn stream_states<'a>(
+        &'a self,
+        task_id: &'a str,
+        interval: Duration,
+    ) -> BoxStream<'a, Result<TaskMeta, BackendError>> {
+        stream::unfold(StreamState::Subscribing, move |state| async move {
+            let (mut messages, last) = match state {
+                StreamState::Subscribing => match self.subscribe(task_id).await {
+                    Ok(messages) => (messages.boxed(), None),
+                    Err(e) => {
+                        warn!(
+                            "Failed to subscribe to the result of task {}, polling it instead: {}",
+                            task_id, e
+                        );
+                        (stream::pending().boxed(), None)
+                    }
+                },
+                StreamState::Subscribed(messages, last) => (messages, last),
+                StreamState::Done => return None,
+            };
+            let mut meta = self.get_task_meta(task_id).await;
+            loop {
+                match meta {
+                    Ok(meta) if has_changed(last.as_deref(), &meta) => {
+                        let next = if meta.status.is_ready() {
+                            StreamState::Done
+                        } else {
+                            StreamState::Subscribed(messages, Some(Box::new(meta.clone())))
+                        };
+                        return Some((Ok(meta), next));
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Some((Err(e), StreamState::Done)),
+                };
+                meta = tokio::select! {
+                    message = messages.next() => match message {
+                        Some(message) => {
+                            match serde_json::from_slice::<TaskMeta>(&message) {
+                                Ok(meta) => Ok(meta),
+                                Err(e) => {
+                                    warn!("Received invalid result: {}", e);
+                                    self.get_task_meta(task_id).await
+                                }
+                            }
+                        }
+                        None => {
+                            warn!("Lost subscription to the result of task {}", task_id);
+                            messages = stream::pending().boxed();
+                            self.get_task_meta(task_id).await
+                        }
+                    },
+                    _ = time::sleep(interval) => self.get_task_meta(task_id).await,
+                };
+            }
+        })
+        .boxed()
+    }
+}
+
+impl RedisBackend {
+    /// Subscribe to the channel that the result of the task is published on, through the
+    /// [`ResultConsumer`] of the backend, which is connected the first time it's needed and
+    /// again after its connection is lost.
+    async fn subscribe(&self, task_id: &str) -> Result<Subscription, BackendError> {
+        let mut consumer = self.consumer.lock().await;
+        if let Some(subscription) = consumer
+            .as_ref()
+            .and_then(|consumer| consumer.subscribe(task_key(task_id)))
+        {
+            return Ok(subscription);
+        }
+        let connected = consumer.insert(ResultConsumer::connect(&self.client).await?);
+        Ok(connected
+            .subscribe(task_key(task_id))
+            .expect("a new consumer is connected"))
+    }
+}
+
+/// The connection that a [`RedisBackend`] receives the results of tasks on, shared by
+/// everything that waits for a result, like Python's `ResultConsumer`.
+///
+/// The connection is subscribed to the channels of all tasks at once with a pattern, so
+/// that waiting for another result doesn't need another command. A task reads the messages
+/// and sends them to the [`Subscription`]s to their channel.
+struct ResultConsumer {
+    subscriptions: Arc<Mutex<Subscriptions>>,
+}
+
+/// The subscriptions of a [`ResultConsumer`] to the channels of tasks.
+struct Subscriptions {
+    /// Where to send the messages published on each channel, by ID of the subscription.
+    channels: HashMap<String, HashMap<u64, mpsc::UnboundedSender<Vec<u8>>>>,
+    next_id: u64,
+
+    /// Stops the task of the consumer, which closes its connection.
+    abort: AbortHandle,
+
+    /// Whether the connection was lost or closed.
+    closed: bool,
+}
+
+impl Subscriptions {
+    /// Close the connection and end the streams of all the subscriptions.
+    fn close(&mut self) {
+        self.closed = true;
+        self.channels.clear();
+        self.abort.abort();
+    }
+}
+
+impl ResultConsumer {
+    async fn connect(client: &Client) -> Result<Self, BackendError> {
+        let mut pubsub = client.get_async_connection().await?.into_pubsub();
+        pubsub.psubscribe(format!("{}*", TASK_KEY_PREFIX)).await?;
+        let (abort, registration) = AbortHandle::new_pair();
+        let subscriptions = Arc::new(Mutex::new(Subscriptions {
+            channels: HashMap::new(),
+            next_id: 0,
+            abort,
+            closed: false,
+        }));
+        tokio::spawn(Abortable::new(
+            consume(pubsub.into_on_message(), subscriptions.clone()),
+            registration,
+        ));
+        Ok(Self { subscriptions })
+    }
+
+    /// Subscribe to `channel`, unless the connection was lost. Since the connection is
+    /// already subscribed to the channels of all tasks, no message published after this
+    /// returns can be missed.
+    fn subscribe(&self, channel: String) -> Option<Subscription> {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if subscriptions.closed {
+            return None;
+        }
+        let id = subscriptions.next_id;
+        subscriptions.next_id += 1;
+        let (sender, messages) = mpsc::unbounded_channel();
+        subscriptions
+            .channels
+            .entry(channel.clone())
+            .or_default()
+            .insert(id, sender);
+        Some(Subscription {
+            channel,
+            id,
+            messages,
+            subscriptions: self.subscriptions.clone(),
+        })
+    }
+}
+
+impl Drop for ResultConsumer {
+    fn drop(&mut self) {
+        self.subscriptions.lock().unwrap().close();
+    }
+}
+
+/// The task of a [`ResultConsumer`], which sends the messages published on the channels of
+/// tasks to their subscriptions until the connection is lost.
+async fn consume(
+    mut messages: impl Stream<Item = Msg> + Unpin,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+) {
+    while let Some(message) = messages.next().await {
+        let subscriptions = subscriptions.lock().unwrap();
+        if let Some(senders) = subscriptions.channels.get(message.get_channel_name()) {
+            for sender in senders.values() {
+                sender.send(message.get_payload_bytes().to_vec()).ok();
+            }
+        }
+    }
+    warn!("Lost the connection that results are received on");
+    subscriptions.lock().unwrap().close();
+}
+
+/// The messages published on a channel, until this is dropped. The stream ends if the
+/// connection of the [`ResultConsumer`] is lost.
+struct Subscription {
+    channel: String,
+    id: u64,
+    messages: mpsc::UnboundedReceiver<Vec<u8>>,
+    subscriptions: Arc<Mutex<Subscriptions>>,
+}
+
+impl Stream for Subscription {
+    type Item = Vec<u8>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        self.messages.poll_recv(cx)
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        if let Some(senders) = subscriptions.channels.get_mut(&self.channel) {
+            senders.remove(&self.id);
+            if senders.is_empty() {
+                subscriptions.channels.remove(&self.channel);
+            }
+        }
+    }
+}
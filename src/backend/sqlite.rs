@@ -0,0 +1,361 @@
+//! SQLite result backend.
+
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::time::Duration;
+
+use super::{GroupMeta, ResultBackend, ResultBackendBuilder, TaskMeta};
+use crate::error::BackendError;
+use crate::task::TaskState;
+
+/// The tables used by Python's database backend, including the columns of the extended
+/// task model.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS celery_taskmeta (
+    id INTEGER NOT NULL PRIMARY KEY,
+    task_id VARCHAR(155) UNIQUE,
+    status VARCHAR(50),
+    result BLOB,
+    date_done DATETIME,
+    traceback TEXT,
+    name VARCHAR(155),
+    args BLOB,
+    kwargs BLOB,
+    worker VARCHAR(155),
+    retries INTEGER,
+    queue VARCHAR(155)
+);
+CREATE TABLE IF NOT EXISTS celery_tasksetmeta (
+    id INTEGER NOT NULL PRIMARY KEY,
+    taskset_id VARCHAR(155) UNIQUE,
+    result BLOB,
+    date_done DATETIME
+);
+";
+
+/// The format SQLAlchemy uses to store datetimes in SQLite.
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+struct Config {
+    backend_url: String,
+    result_expires: Option<Duration>,
+}
+
+/// Builds a [`SqliteBackend`] with a custom configuration.
+pub struct SqliteBackendBuilder {
+    config: Config,
+}
+
+impl SqliteBackendBuilder {
+    /// Set how long results are kept before [`cleanup`](ResultBackend::cleanup) removes
+    /// them. `None` keeps them forever. Defaults to one day, like Python.
+    pub fn result_expires(mut self, result_expires: Option<Duration>) -> Self {
+        self.config.result_expires = result_expires;
+        self
+    }
+
+    /// Get the path of the database from the URL. Like SQLAlchemy, `db+sqlite:///results.db`
+    /// is a relative path, `db+sqlite:////var/results.db` is an absolute path and
+    /// `db+sqlite://` is an in-memory database.
+    fn path(&self) -> Result<Option<String>, BackendError> {
+        let url = &self.config.backend_url;
+        match url.strip_prefix("db+sqlite://") {
+            Some("") => Ok(None),
+            Some(path) if path.len() > 1 && path.starts_with('/') => Ok(Some(path[1..].into())),
+            _ => Err(BackendError::InvalidBackendUrl(url.clone())),
+        }
+    }
+}
+
+#[async_trait]
+impl ResultBackendBuilder for SqliteBackendBuilder {
+    type Backend = SqliteBackend;
+
+    /// Create a new `SqliteBackendBuilder`.
+    fn new(backend_url: &str) -> Self {
+        Self {
+            config: Config {
+                backend_url: backend_url.into(),
+                result_expires: Some(Duration::from_secs(24 * 60 * 60)),
+            },
+        }
+    }
+
+    /// Build a `SqliteBackend`, creating the tables if they don't exist yet.
+    async fn build(&self, connection_timeout: u32) -> Result<SqliteBackend, BackendError> {
+        let path = self.path()?;
+        let conn = tokio::task::spawn_blocking(move || -> Result<_, BackendError> {
+            let conn = match path {
+                Some(path) => Connection::open(path)?,
+                None => Connection::open_in_memory()?,
+            };
+            conn.busy_timeout(std::time::Duration::from_secs(connection_timeout as u64))?;
+            conn.execute_batch(SCHEMA)?;
+            Ok(conn)
+        })
+        .await
+        .expect("SQLite task panicked")?;
+        Ok(SqliteBackend {
+            uri: self.config.backend_url.clone(),
+            conn: Arc::new(Mutex::new(conn)),
+            result_expires: self.config.result_expires,
+        })
+    }
+}
+
+/// A result backend that stores results in SQLite using the same tables as Python's
+/// database backend (`celery_taskmeta` and `celery_tasksetmeta`), so the task history can be
+/// read by existing Python tooling.
+///
+/// As in Python, results are stored pickled. Python apps pickle the `GroupResult` object
+/// itself for groups, while this backend stores the result tuple, so group results can only
+/// be shared between Rust apps.
+///
+/// This backend requires the `sqlite_backend` feature.
+pub struct SqliteBackend {
+    uri: String,
+    conn: Arc<Mutex<Connection>>,
+    result_expires: Option<Duration>,
+}
+
+impl SqliteBackend {
+    /// Run a blocking query on the connection.
+    async fn query<F, R>(&self, f: F) -> Result<R, BackendError>
+    where
+        F: FnOnce(&Connection) -> Result<R, BackendError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap()))
+            .await
+            .expect("SQLite task panicked")
+    }
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
+
+fn parse_date(date: Option<String>) -> Option<DateTime<Utc>> {
+    date.and_then(|date| NaiveDateTime::parse_from_str(&date, DATE_FORMAT).ok())
+        .map(|date| DateTime::<Utc>::from_utc(date, Utc))
+}
+
+fn pickle(value: &Value) -> Result<Vec<u8>, BackendError> {
+    Ok(serde_pickle::to_vec(
+        value,
+        serde_pickle::SerOptions::new(),
+    )?)
+}
+
+fn unpickle(value: Option<Vec<u8>>) -> Result<Value, BackendError> {
+    match value {
+        Some(value) => Ok(serde_pickle::from_slice(
+            &value,
+            serde_pickle::DeOptions::new(),
+        )?),
+        None => Ok(Value::Null),
+    }
+}
+
+#[async_trait]
+impl ResultBackend for SqliteBackend {
+    fn safe_url(&self) -> String {
+        self.uri.clone()
+    }
+
+    async fn store_result(&self, meta: &TaskMeta) -> Result<(), BackendError> {
+        let task_id = meta.task_id.clone();
+        let status = meta.status.to_string();
+        let result = pickle(&meta.result)?;
+        // Python sets `date_done` whenever a state is stored.
+        let date_done = format_date(meta.date_done.unwrap_or_else(Utc::now));
+        let traceback = meta.traceback.clone();
+        // Python stores the arguments of extended results serialized, not pickled.
+        let args = meta.args.as_ref().map(serde_json::to_vec).transpose()?;
+        let kwargs = meta.kwargs.as_ref().map(serde_json::to_vec).transpose()?;
+        let name = meta.name.clone();
+        let worker = meta.worker.clone();
+        let retries = meta.retries;
+        let queue = meta.queue.clone();
+        self.query(move |conn| {
+            conn.execute(
+                "INSERT INTO celery_taskmeta
+                     (task_id, status, result, date_done, traceback,
+                      name, args, kwargs, worker, retries, queue)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+                 ON CONFLICT (task_id) DO UPDATE SET
+                     status = excluded.status,
+                     result = excluded.result,
+                     date_done = excluded.date_done,
+                     traceback = excluded.traceback,
+                     name = excluded.name,
+                     args = excluded.args,
+                     kwargs = excluded.kwargs,
+                     worker = excluded.worker,
+                     retries = excluded.retries,
+                     queue = excluded.queue",
+                params![
+                    task_id, status, result, date_done, traceback, name, args, kwargs, worker,
+                    retries, queue
+                ],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get_task_meta(&self, task_id: &str) -> Result<TaskMeta, BackendError> {
+        let id = task_id.to_string();
+        let row = self
+            .query(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT status, result, date_done, traceback,
+                                name, args, kwargs, worker, retries, queue
+                         FROM celery_taskmeta WHERE task_id = ?1",
+                        params![id],
+                        |row| {
+                            Ok((
+                                (
+                                    row.get::<_, Option<String>>(0)?,
+                                    row.get::<_, Option<Vec<u8>>>(1)?,
+                                    row.get::<_, Option<String>>(2)?,
+                                    row.get::<_, Option<String>>(3)?,
+                                ),
+                                (
+                                    row.get::<_, Option<String>>(4)?,
+                                    row.get::<_, Option<Vec<u8>>>(5)?,
+                                    row.get::<_, Option<Vec<u8>>>(6)?,
+                                    row.get::<_, Option<String>>(7)?,
+                                    row.get::<_, Option<u32>>(8)?,
+                                    row.get::<_, Option<String>>(9)?,
+                                ),
+                            ))
+                        },
+                    )
+                    .optional()?)
+            })
+            .await?;
+        let ((status, result, date_done, traceback), (name, args, kwargs, worker, retries, queue)) =
+            match row {
+                Some(row) => row,
+                None => return Ok(TaskMeta::pending(task_id)),
+            };
+        Ok(TaskMeta {
+            status: status.map(TaskState::from).unwrap_or_default(),
+            result: unpickle(result)?,
+            traceback,
+            date_done: parse_date(date_done),
+            name,
+            args: args.map(|args| serde_json::from_slice(&args)).transpose()?,
+            kwargs: kwargs
+                .map(|kwargs| serde_json::from_slice(&kwargs))
+                .transpose()?,
+            worker,
+            retries,
+            queue,
+            ..TaskMeta::pending(task_id)
+        })
+    }
+
+    async fn forget(&self, task_id: &str) -> Result<(), BackendError> {
+        let task_id = task_id.to_string();
+        self.query(move |conn| {
+            conn.execute(
+                "DELETE FROM celery_taskmeta WHERE task_id = ?1",
+                params![task_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn save_group(&self, meta: &GroupMeta) -> Result<(), BackendError> {
+        let group_id = meta.group_id.clone();
+        let result = pickle(&meta.result)?;
+        let date_done = format_date(meta.date_done.unwrap_or_else(Utc::now));
+        self.query(move |conn| {
+            conn.execute(
+                "INSERT INTO celery_tasksetmeta (taskset_id, result, date_done)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT (taskset_id) DO UPDATE SET
+                     result = excluded.result,
+                     date_done = excluded.date_done",
+                params![group_id, result, date_done],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn restore_group(&self, group_id: &str) -> Result<Option<GroupMeta>, BackendError> {
+        let id = group_id.to_string();
+        let row = self
+            .query(move |conn| {
+                Ok(conn
+                    .query_row(
+                        "SELECT result, date_done FROM celery_tasksetmeta WHERE taskset_id = ?1",
+                        params![id],
+                        |row| {
+                            Ok((
+                                row.get::<_, Option<Vec<u8>>>(0)?,
+                                row.get::<_, Option<String>>(1)?,
+                            ))
+                        },
+                    )
+                    .optional()?)
+            })
+            .await?;
+        match row {
+            Some((result, date_done)) => Ok(Some(GroupMeta {
+                group_id: group_id.into(),
+                result: unpickle(result)?,
+                date_done: parse_date(date_done),
+            })),
+            None => Ok(None),
+        }
+    }
+
+    async fn delete_group(&self, group_id: &str) -> Result<(), BackendError> {
+        let group_id = group_id.to_string();
+        self.query(move |conn| {
+            conn.execute(
+                "DELETE FROM celery_tasksetmeta WHERE taskset_id = ?1",
+                params![group_id],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn cleanup(&self) -> Result<(), BackendError> {
+        let result_expires = match self.result_expires {
+            Some(result_expires) => result_expires,
+            None => return Ok(()),
+        };
+        let expires_before = match chrono::Duration::from_std(result_expires)
+            .ok()
+            .and_then(|result_expires| Utc::now().checked_sub_signed(result_expires))
+        {
+            Some(expires_before) => format_date(expires_before),
+            // Nothing can be that old.
+            None => return Ok(()),
+        };
+        self.query(move |conn| {
+            conn.execute(
+                "DELETE FROM celery_taskmeta WHERE date_done < ?1",
+                params![expires_before],
+            )?;
+            conn.execute(
+                "DELETE FROM celery_tasksetmeta WHERE date_done < ?1",
+                params![expires_before],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+}
@@ -0,0 +1,505 @@
+use super::*;
+use chrono::{TimeZone, Timelike};
+use tokio::time::{self, Duration};
+
+#[test]
+fn test_task_key() {
+    assert_eq!(task_key("aaa"), "celery-task-meta-aaa");
+    assert_eq!(group_key("ggg"), "celery-taskset-meta-ggg");
+}
+
+#[test]
+fn test_success_serialization() {
+    let mut meta = TaskMeta::success("aaa", json!(3));
+    meta.date_done = Some(Utc.ymd(2021, 10, 7).and_hms_micro(12, 30, 0, 123_456));
+    let value = serde_json::to_value(&meta).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "task_id": "aaa",
+            "status": "SUCCESS",
+            "result": 3,
+            "traceback": null,
+            "children": [],
+            "date_done": "2021-10-07T12:30:00.123456",
+        })
+    );
+}
+
+#[test]
+fn test_deserialize_python_meta() {
+    // As stored by a Python worker with the JSON result serializer.
+    let raw = r#"{"status": "SUCCESS", "result": [1, 2], "traceback": null, "children": [], "date_done": "2021-10-07T12:30:00.123456", "task_id": "aaa"}"#;
+    let meta: TaskMeta = serde_json::from_str(raw).unwrap();
+    assert_eq!(meta.task_id, "aaa");
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!([1, 2]));
+    let date_done = meta.date_done.unwrap();
+    assert_eq!(date_done.date(), Utc.ymd(2021, 10, 7));
+    assert_eq!(date_done.nanosecond(), 123_456_000);
+    assert!(meta.error().is_none());
+}
+
+#[test]
+fn test_deserialize_python_failure() {
+    let raw = r#"{"status": "FAILURE", "result": {"exc_type": "ZeroDivisionError", "exc_message": ["division by zero"], "exc_module": "builtins"}, "traceback": "Traceback...", "children": [], "date_done": "2021-10-07T12:30:00.123456+00:00", "task_id": "aaa"}"#;
+    let meta: TaskMeta = serde_json::from_str(raw).unwrap();
+    assert_eq!(meta.status, TaskState::Failure);
+    assert!(meta.date_done.is_some());
+    match meta.error() {
+        Some(TaskError::UnexpectedError(reason)) => {
+            assert_eq!(reason, "ZeroDivisionError: division by zero")
+        }
+        other => panic!("unexpected error {:?}", other),
+    };
+}
+
+#[test]
+fn test_failure_roundtrip() {
+    let meta = TaskMeta::failure("aaa", &TaskError::ExpectedError("oops".into()));
+    assert_eq!(
+        meta.result,
+        json!({"exc_type": "ExpectedError", "exc_message": ["oops"], "exc_module": null})
+    );
+    let raw = serde_json::to_string(&meta).unwrap();
+    let meta: TaskMeta = serde_json::from_str(&raw).unwrap();
+    match meta.error() {
+        Some(TaskError::ExpectedError(reason)) => assert_eq!(reason, "oops"),
+        other => panic!("unexpected error {:?}", other),
+    };
+
+    let meta = TaskMeta::failure("aaa", &TaskError::TimeoutError);
+    assert!(matches!(meta.error(), Some(TaskError::TimeoutError)));
+}
+
+#[test]
+fn test_retry_meta() {
+    let meta = TaskMeta::retry("aaa", &TaskError::UnexpectedError("oops".into()));
+    assert_eq!(meta.status, TaskState::Retry);
+    assert!(meta.date_done.is_none());
+    assert!(matches!(meta.error(), Some(TaskError::UnexpectedError(_))));
+}
+
+#[test]
+fn test_extended_serialization() {
+    let meta = TaskMeta::success("aaa", json!(3));
+    let value = serde_json::to_value(&meta).unwrap();
+    assert!(value.get("name").is_none());
+
+    let mut meta = TaskMeta::success("aaa", json!(3));
+    meta.name = Some("add".into());
+    meta.retries = Some(0);
+    let value = serde_json::to_value(&meta).unwrap();
+    assert_eq!(value["name"], json!("add"));
+    assert_eq!(value["retries"], json!(0));
+    assert!(value.get("queue").is_none());
+}
+
+#[test]
+fn test_custom_state() {
+    let meta: TaskMeta = serde_json::from_str(
+        r#"{"task_id": "aaa", "status": "PROGRESS", "result": {"current": 1}}"#,
+    )
+    .unwrap();
+    assert_eq!(meta.status, TaskState::Custom("PROGRESS".into()));
+    assert_eq!(
+        serde_json::to_value(&meta.status).unwrap(),
+        json!("PROGRESS")
+    );
+    assert!(meta.date_done.is_none());
+    assert!(!meta.status.is_ready());
+}
+
+#[tokio::test]
+async fn test_memory_backend_shared_by_url() {
+    let producer = MemoryBackendBuilder::new("memory://shared")
+        .build(0)
+        .await
+        .unwrap();
+    let worker = MemoryBackendBuilder::new("memory://shared")
+        .build(0)
+        .await
+        .unwrap();
+    let other = MemoryBackendBuilder::new("memory://other")
+        .build(0)
+        .await
+        .unwrap();
+
+    worker
+        .store_result(&TaskMeta::success("aaa", json!(3)))
+        .await
+        .unwrap();
+    assert_eq!(
+        producer.get_task_meta("aaa").await.unwrap().result,
+        json!(3)
+    );
+    assert_eq!(
+        other.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+
+    producer.forget("aaa").await.unwrap();
+    assert_eq!(
+        worker.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+}
+
+#[tokio::test]
+async fn test_memory_backend_wait_for() {
+    let backend = Arc::new(
+        MemoryBackendBuilder::new("memory://wait-for")
+            .build(0)
+            .await
+            .unwrap(),
+    );
+    let worker = backend.clone();
+    tokio::spawn(async move {
+        time::sleep(Duration::from_millis(10)).await;
+        worker
+            .store_result(&TaskMeta::success("aaa", json!(3)))
+            .await
+            .unwrap();
+    });
+
+    // The interval is way longer than the timeout, so this only succeeds if the waiter is
+    // notified.
+    let meta = backend
+        .wait_for(
+            "aaa",
+            Some(Duration::from_secs(1)),
+            Duration::from_secs(3600),
+        )
+        .await
+        .unwrap();
+    assert_eq!(meta.result, json!(3));
+
+    assert!(matches!(
+        backend
+            .wait_for(
+                "bbb",
+                Some(Duration::from_millis(10)),
+                Duration::from_secs(3600)
+            )
+            .await,
+        Err(BackendError::Timeout)
+    ));
+}
+
+#[tokio::test]
+async fn test_stream_states() {
+    let backend = Arc::new(
+        MemoryBackendBuilder::new("memory://stream-states")
+            .build(0)
+            .await
+            .unwrap(),
+    );
+    let worker = backend.clone();
+    tokio::spawn(async move {
+        for meta in [
+            TaskMeta::started("aaa", "worker@localhost"),
+            TaskMeta::with_status(
+                "aaa",
+                TaskState::Custom("PROGRESS".into()),
+                json!({"current": 1, "total": 2}),
+            ),
+            TaskMeta::with_status(
+                "aaa",
+                TaskState::Custom("PROGRESS".into()),
+                json!({"current": 2, "total": 2}),
+            ),
+            TaskMeta::success("aaa", json!(3)),
+        ]
+        .iter()
+        {
+            time::sleep(Duration::from_millis(100)).await;
+            worker.store_result(meta).await.unwrap();
+        }
+    });
+
+    let states: Vec<_> = backend
+        .stream_states("aaa", Duration::from_millis(5))
+        .map(|meta| {
+            let meta = meta.unwrap();
+            (meta.status.to_string(), meta.result)
+        })
+        .collect()
+        .await;
+    assert_eq!(
+        states,
+        vec![
+            ("PENDING".into(), Value::Null),
+            (
+                "STARTED".into(),
+                json!({"pid": std::process::id(), "hostname": "worker@localhost"})
+            ),
+            ("PROGRESS".into(), json!({"current": 1, "total": 2})),
+            ("PROGRESS".into(), json!({"current": 2, "total": 2})),
+            ("SUCCESS".into(), json!(3)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_memory_backend_chord() {
+    let backend = MemoryBackendBuilder::new("memory://chord")
+        .build(0)
+        .await
+        .unwrap();
+    assert!(backend.supports_native_join());
+    backend.apply_chord("ggg", 3).await.unwrap();
+
+    let part = |task_id: &str, value: i32| TaskMeta::success(task_id, json!(value));
+    assert!(backend
+        .on_chord_part_return("ggg", Some(2), &part("ccc", 3))
+        .await
+        .unwrap()
+        .is_none());
+    assert!(backend
+        .on_chord_part_return("ggg", Some(0), &part("aaa", 1))
+        .await
+        .unwrap()
+        .is_none());
+    // A task that is delivered again isn't counted twice.
+    assert!(backend
+        .on_chord_part_return("ggg", Some(0), &part("aaa", 1))
+        .await
+        .unwrap()
+        .is_none());
+    let results = backend
+        .on_chord_part_return("ggg", Some(1), &part("bbb", 2))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        results
+            .iter()
+            .map(|meta| meta.task_id.as_str())
+            .collect::<Vec<_>>(),
+        vec!["aaa", "bbb", "ccc"]
+    );
+}
+
+/// Create an empty directory for a filesystem backend test.
+fn backend_dir(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("celery-{}-{}", name, uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_filesystem_backend() {
+    let path = backend_dir("filesystem");
+    let backend = FilesystemBackendBuilder::new(&format!("file://{}", path.display()))
+        .build(0)
+        .await
+        .unwrap();
+    assert_eq!(
+        backend.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+
+    backend
+        .store_result(&TaskMeta::success("aaa", json!(3)))
+        .await
+        .unwrap();
+    let raw = std::fs::read_to_string(path.join("celery-task-meta-aaa")).unwrap();
+    let value: Value = serde_json::from_str(&raw).unwrap();
+    assert_eq!(value["status"], json!("SUCCESS"));
+    assert_eq!(value["result"], json!(3));
+    // Only the result file is left behind.
+    assert_eq!(std::fs::read_dir(&path).unwrap().count(), 1);
+
+    assert_eq!(backend.get_task_meta("aaa").await.unwrap().result, json!(3));
+    backend.forget("aaa").await.unwrap();
+    assert_eq!(
+        backend.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+    backend.forget("aaa").await.unwrap();
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_groups() {
+    let path = backend_dir("filesystem-groups");
+    let backend = FilesystemBackendBuilder::new(&format!("file://{}", path.display()))
+        .build(0)
+        .await
+        .unwrap();
+    assert!(backend.restore_group("ggg").await.unwrap().is_none());
+
+    let meta = GroupMeta::new("ggg", json!([["ggg", null], [[["aaa", null], null]]]));
+    backend.save_group(&meta).await.unwrap();
+    let raw = std::fs::read_to_string(path.join("celery-taskset-meta-ggg")).unwrap();
+    let value: Value = serde_json::from_str(&raw).unwrap();
+    assert_eq!(value["result"], meta.result);
+    let restored = backend.restore_group("ggg").await.unwrap().unwrap();
+    assert_eq!(restored.group_id, "ggg");
+    assert_eq!(restored.result, meta.result);
+
+    backend.delete_group("ggg").await.unwrap();
+    assert!(backend.restore_group("ggg").await.unwrap().is_none());
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_cleanup() {
+    let path = backend_dir("filesystem-cleanup");
+    let url = format!("file://{}", path.display());
+    let backend = FilesystemBackendBuilder::new(&url)
+        .result_expires(Some(Duration::from_millis(50)))
+        .build(0)
+        .await
+        .unwrap();
+    backend
+        .store_result(&TaskMeta::success("aaa", json!(3)))
+        .await
+        .unwrap();
+    std::fs::write(path.join("other"), "").unwrap();
+    // Left behind by an app that stopped while writing a result.
+    let tmp_name = ".celery-task-meta-bbb.0b9b4bd1-cf03-4c3b-bd1d-d64b5b5e1fe1.tmp";
+    std::fs::write(path.join(tmp_name), "").unwrap();
+    time::sleep(Duration::from_millis(100)).await;
+
+    // Expired results are treated as missing until they're swept.
+    assert_eq!(
+        backend.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+    assert!(path.join("celery-task-meta-aaa").exists());
+    backend.cleanup().await.unwrap();
+    assert!(!path.join("celery-task-meta-aaa").exists());
+    assert!(!path.join(tmp_name).exists());
+    assert!(path.join("other").exists());
+
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_filesystem_backend_url() {
+    assert!(matches!(
+        FilesystemBackendBuilder::new("file://relative/path")
+            .build(0)
+            .await,
+        Err(BackendError::InvalidBackendUrl(_))
+    ));
+    assert!(matches!(
+        FilesystemBackendBuilder::new("file:///does/not/exist")
+            .build(0)
+            .await,
+        Err(BackendError::IoError(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_sqlite_backend() {
+    let backend = SqliteBackendBuilder::new("db+sqlite://")
+        .build(1)
+        .await
+        .unwrap();
+    assert_eq!(
+        backend.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+
+    backend
+        .store_result(&TaskMeta::retry(
+            "aaa",
+            &TaskError::UnexpectedError("oops".into()),
+        ))
+        .await
+        .unwrap();
+    let meta = backend.get_task_meta("aaa").await.unwrap();
+    assert_eq!(meta.status, TaskState::Retry);
+    assert!(matches!(meta.error(), Some(TaskError::UnexpectedError(_))));
+
+    let mut meta = TaskMeta::success("aaa", json!({"x": [1, 2.5, "b", null]}));
+    meta.date_done = Some(Utc.ymd(2021, 10, 7).and_hms_micro(12, 30, 0, 123_456));
+    meta.name = Some("add".into());
+    meta.args = Some(json!([]));
+    meta.kwargs = Some(json!({"x": 1, "y": 2}));
+    meta.worker = Some("celery@localhost".into());
+    meta.retries = Some(1);
+    meta.queue = Some("celery".into());
+    backend.store_result(&meta).await.unwrap();
+    assert_eq!(backend.get_task_meta("aaa").await.unwrap(), meta);
+
+    backend.forget("aaa").await.unwrap();
+    assert_eq!(
+        backend.get_task_meta("aaa").await.unwrap().status,
+        TaskState::Pending
+    );
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_python_rows() {
+    let path = backend_dir("sqlite").join("results.db");
+    let url = format!("db+sqlite:///{}", path.display());
+    let backend = SqliteBackendBuilder::new(&url).build(1).await.unwrap();
+
+    // A row as written by Python's database backend: the result is pickled with protocol 4.
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute(
+        "INSERT INTO celery_taskmeta (task_id, status, result, date_done, traceback)
+         VALUES ('aaa', 'SUCCESS', ?1, '2021-10-07 12:30:00.123456', NULL)",
+        [b"\x80\x04\x95\x09\x00\x00\x00\x00\x00\x00\x00]\x94(K\x01K\x02e.".to_vec()],
+    )
+    .unwrap();
+    let meta = backend.get_task_meta("aaa").await.unwrap();
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!([1, 2]));
+    assert_eq!(meta.date_done.unwrap().nanosecond(), 123_456_000);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_groups() {
+    let backend = SqliteBackendBuilder::new("db+sqlite://")
+        .build(1)
+        .await
+        .unwrap();
+    assert!(backend.restore_group("ggg").await.unwrap().is_none());
+
+    let meta = GroupMeta::new("ggg", json!([["ggg", null], [[["aaa", null], null]]]));
+    backend.save_group(&meta).await.unwrap();
+    let restored = backend.restore_group("ggg").await.unwrap().unwrap();
+    assert_eq!(restored.group_id, "ggg");
+    assert_eq!(restored.result, meta.result);
+
+    backend.delete_group("ggg").await.unwrap();
+    assert!(backend.restore_group("ggg").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_sqlite_backend_cleanup() {
+    let backend = SqliteBackendBuilder::new("db+sqlite://")
+        .result_expires(Some(Duration::from_secs(3600)))
+        .build(1)
+        .await
+        .unwrap();
+    let mut old = TaskMeta::success("old", json!(1));
+    old.date_done = Some(Utc::now() - chrono::Duration::hours(2));
+    backend.store_result(&old).await.unwrap();
+    let mut old_group = GroupMeta::new("old", json!(null));
+    old_group.date_done = old.date_done;
+    backend.save_group(&old_group).await.unwrap();
+    backend
+        .store_result(&TaskMeta::success("new", json!(2)))
+        .await
+        .unwrap();
+
+    backend.cleanup().await.unwrap();
+    assert_eq!(
+        backend.get_task_meta("old").await.unwrap().status,
+        TaskState::Pending
+    );
+    assert!(backend.restore_group("old").await.unwrap().is_none());
+    assert_eq!(
+        backend.get_task_meta("new").await.unwrap().status,
+        TaskState::Success
+    );
+}
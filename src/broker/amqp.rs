@@ -2,23 +2,38 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future;
+use futures::stream::{BoxStream, StreamExt};
 use lapin::message::Delivery;
 use lapin::options::{
-    BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicPublishOptions, BasicQosOptions,
-    QueueDeclareOptions,
+    BasicAckOptions, BasicCancelOptions, BasicConsumeOptions, BasicGetOptions, BasicPublishOptions,
+    BasicQosOptions, ExchangeDeclareOptions, QueueBindOptions, QueueDeclareOptions,
 };
 use lapin::types::{AMQPValue, FieldArray, FieldTable};
 use lapin::uri::{self, AMQPUri};
-use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, Queue};
-use log::debug;
-use std::collections::HashMap;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind, Queue};
+use log::{debug, error, warn};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use tokio::sync::{Mutex, RwLock};
 use tokio_amqp::LapinTokioExt;
 
 use super::{Broker, BrokerBuilder};
 use crate::error::{BrokerError, ProtocolError};
-use crate::protocol::{Message, MessageHeaders, MessageProperties, TryDeserializeMessage};
+use crate::protocol::{
+    ControlMessage, ControlReplyTo, DeadLetter, Event, Message, MessageHeaders, MessageProperties,
+    TryDeserializeMessage, DEAD_LETTER_ERROR_HEADER, DEAD_LETTER_REASON_HEADER, EVENT_EXCHANGE,
+    ORIGINAL_QUEUE_HEADER,
+};
+
+/// How long remote control commands wait in the mailbox queue of a worker, in milliseconds,
+/// like Python's `control_queue_ttl` setting.
+const CONTROL_QUEUE_TTL: i32 = 300_000;
+
+/// How long the mailbox queue of a worker is kept after the worker stops consuming from it,
+/// in milliseconds, like Python's `control_queue_expires` setting.
+const CONTROL_QUEUE_EXPIRES: i32 = 10_000;
 
 struct Config {
     broker_url: String,
@@ -278,6 +293,283 @@ impl Broker for AMQPBroker {
         Ok(())
     }
 
+    async fn consume_control(
+        &self,
+        namespace: &str,
+        hostname: &str,
+    ) -> Result<BoxStream<'static, ControlMessage>, BrokerError> {
+        let exchange = format!("{}.pidbox", namespace);
+        let queue = format!("{}.{}.pidbox", hostname, namespace);
+        let mut arguments = FieldTable::default();
+        arguments.insert(
+            "x-message-ttl".into(),
+            AMQPValue::LongInt(CONTROL_QUEUE_TTL),
+        );
+        arguments.insert(
+            "x-expires".into(),
+            AMQPValue::LongInt(CONTROL_QUEUE_EXPIRES),
+        );
+
+        let consume_channel = self.consume_channel.read().await;
+        consume_channel
+            .exchange_declare(
+                &exchange,
+                ExchangeKind::Fanout,
+                ExchangeDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        consume_channel
+            .queue_declare(
+                &queue,
+                QueueDeclareOptions {
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                arguments,
+            )
+            .await?;
+        consume_channel
+            .queue_bind(
+                &queue,
+                &exchange,
+                "",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        let consumer = consume_channel
+            .basic_consume(
+                &queue,
+                "",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+
+        Ok(consumer
+            .filter_map(|delivery| {
+                future::ready(match delivery {
+                    Ok((_, delivery)) => match serde_json::from_slice(&delivery.data) {
+                        Ok(message) => Some(message),
+                        Err(e) => {
+                            error!("Invalid control message: {}", e);
+                            None
+                        }
+                    },
+                    Err(e) => {
+                        error!("Control delivery failed: {}", e);
+                        None
+                    }
+                })
+            })
+            .boxed())
+    }
+
+    async fn send_control(
+        &self,
+        namespace: &str,
+        message: &ControlMessage,
+    ) -> Result<(), BrokerError> {
+        let exchange = format!("{}.pidbox", namespace);
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            .with_content_encoding("utf-8".into())
+            .with_delivery_mode(1);
+
+        let produce_channel = self.produce_channel.read().await;
+        produce_channel
+            .exchange_declare(
+                &exchange,
+                ExchangeKind::Fanout,
+                ExchangeDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        produce_channel
+            .basic_publish(
+                &exchange,
+                "",
+                BasicPublishOptions::default(),
+                serde_json::to_vec(message)?,
+                properties,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn send_control_reply(
+        &self,
+        reply_to: &ControlReplyTo,
+        ticket: &str,
+        reply: &Value,
+    ) -> Result<(), BrokerError> {
+        let mut headers = FieldTable::default();
+        headers.insert("ticket".into(), AMQPValue::LongString(ticket.into()));
+        headers.insert("clock".into(), AMQPValue::LongUInt(0));
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            .with_content_encoding("utf-8".into())
+            .with_headers(headers)
+            .with_delivery_mode(1);
+
+        let produce_channel = self.produce_channel.read().await;
+        produce_channel
+            .exchange_declare(
+                &reply_to.exchange,
+                ExchangeKind::Direct,
+                ExchangeDeclareOptions::default(),
+                FieldTable::default(),
+            )
+            .await?;
+        produce_channel
+            .basic_publish(
+                &reply_to.exchange,
+                &reply_to.routing_key,
+                BasicPublishOptions::default(),
+                serde_json::to_vec(reply)?,
+                properties,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn send_event(&self, event: &Event) -> Result<(), BrokerError> {
+        let mut headers = FieldTable::default();
+        headers.insert(
+            "hostname".into(),
+            AMQPValue::LongString(event.hostname.clone().into()),
+        );
+        let properties = BasicProperties::default()
+            .with_content_type("application/json".into())
+            .with_content_encoding("utf-8".into())
+            .with_headers(headers)
+            .with_delivery_mode(1);
+
+        let produce_channel = self.produce_channel.read().await;
+        // Declared like Python's event exchange, which is durable.
+        produce_channel
+            .exchange_declare(
+                EVENT_EXCHANGE,
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..ExchangeDeclareOptions::default()
+                },
+                FieldTable::default(),
+            )
+            .await?;
+        produce_channel
+            .basic_publish(
+                EVENT_EXCHANGE,
+                &event.routing_key(),
+                BasicPublishOptions::default(),
+                serde_json::to_vec(event)?,
+                properties,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn send_dead_letter(
+        &self,
+        delivery: &Self::Delivery,
+        queue: &str,
+        dead_letter: &DeadLetter,
+    ) -> Result<(), BrokerError> {
+        let mut headers = delivery
+            .1
+            .properties
+            .headers()
+            .clone()
+            .unwrap_or_else(FieldTable::default);
+        for (key, value) in dead_letter.headers().iter() {
+            headers.insert((*key).into(), AMQPValue::LongString((*value).into()));
+        }
+
+        // The body is republished as is, since it may not be deserializable.
+        let properties = delivery.1.properties.clone().with_headers(headers);
+        self.produce_channel
+            .read()
+            .await
+            .basic_publish(
+                "",
+                queue,
+                BasicPublishOptions::default(),
+                delivery.1.data.clone(),
+                properties,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn replay_dead_letters(&self, queue: &str) -> Result<usize, BrokerError> {
+        let produce_channel = self.produce_channel.read().await;
+        let mut remaining = None;
+        let mut replayed = 0;
+        while remaining != Some(0) {
+            let message = match produce_channel
+                .basic_get(queue, BasicGetOptions::default())
+                .await?
+            {
+                Some(message) => message,
+                None => break,
+            };
+            // Only replay the messages that were in the queue when we started.
+            remaining = Some(remaining.unwrap_or(message.message_count + 1) - 1);
+
+            let delivery = message.delivery;
+            let headers = delivery
+                .properties
+                .headers()
+                .clone()
+                .unwrap_or_else(FieldTable::default);
+            // Messages that weren't dead-lettered by a worker are moved to the back of the
+            // queue, since we don't know where to send them.
+            let (target, headers) = match get_header_str(&headers, ORIGINAL_QUEUE_HEADER) {
+                Some(original_queue) => {
+                    let headers: BTreeMap<_, _> = headers
+                        .inner()
+                        .iter()
+                        .filter(|(key, _)| {
+                            ![
+                                DEAD_LETTER_REASON_HEADER,
+                                DEAD_LETTER_ERROR_HEADER,
+                                ORIGINAL_QUEUE_HEADER,
+                            ]
+                            .contains(&key.as_str())
+                        })
+                        .map(|(key, value)| (key.clone(), value.clone()))
+                        .collect();
+                    (original_queue, headers.into())
+                }
+                None => {
+                    warn!("Dead letter without an original queue in {}", queue);
+                    (queue.to_string(), headers)
+                }
+            };
+
+            let properties = delivery.properties.clone().with_headers(headers);
+            produce_channel
+                .basic_publish(
+                    "",
+                    &target,
+                    BasicPublishOptions::default(),
+                    delivery.data.clone(),
+                    properties,
+                )
+                .await?;
+            delivery.ack(BasicAckOptions::default()).await?;
+            if target != queue {
+                replayed += 1;
+            }
+        }
+        Ok(replayed)
+    }
+
     async fn increase_prefetch_count(&self) -> Result<(), BrokerError> {
         let new_count = {
             let mut prefetch_count = self.prefetch_count.lock().await;
@@ -406,6 +698,9 @@ impl Message {
         if let Some(ref group) = self.headers.group {
             headers.insert("group".into(), AMQPValue::LongString(group.clone().into()));
         }
+        if let Some(group_index) = self.headers.group_index {
+            headers.insert("group_index".into(), AMQPValue::LongUInt(group_index));
+        }
         if let Some(ref meth) = self.headers.meth {
             headers.insert("meth".into(), AMQPValue::LongString(meth.clone().into()));
         }
@@ -510,6 +805,7 @@ impl TryDeserializeMessage for Delivery {
                 root_id: get_header_str(headers, "root_id"),
                 parent_id: get_header_str(headers, "parent_id"),
                 group: get_header_str(headers, "group"),
+                group_index: get_header_u32(headers, "group_index"),
                 meth: get_header_str(headers, "meth"),
                 shadow: get_header_str(headers, "shadow"),
                 eta: get_header_dt(headers, "eta"),
@@ -611,6 +907,7 @@ mod tests {
                 root_id: Some("aaa".into()),
                 parent_id: Some("000".into()),
                 group: Some("A".into()),
+                group_index: Some(2),
                 meth: Some("method_name".into()),
                 shadow: Some("add-these".into()),
                 eta: Some(now),
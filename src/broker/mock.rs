@@ -2,13 +2,17 @@
 
 use super::{Broker, BrokerBuilder};
 use crate::error::{BrokerError, ProtocolError};
-use crate::protocol::{Message, TryDeserializeMessage};
+use crate::protocol::{
+    ControlMessage, ControlReplyTo, DeadLetter, Event, Message, TryDeserializeMessage,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use futures::{
+    stream::{self, BoxStream, StreamExt},
     task::{Context, Poll},
     Stream,
 };
+use serde_json::Value;
 use std::collections::HashMap;
 use std::time::SystemTime;
 use tokio::sync::RwLock;
@@ -52,6 +56,19 @@ pub struct MockBroker {
     /// The keys are the task IDs, and the values are tuples of the message object,
     /// queue it was sent to, and time it was sent.
     pub sent_tasks: RwLock<HashMap<String, (Message, String, SystemTime)>>,
+
+    /// Holds all broadcast control commands, with the mailbox they were sent to.
+    pub control_messages: RwLock<Vec<(String, ControlMessage)>>,
+
+    /// Holds all sent replies to control commands, with where they were sent to and their
+    /// ticket.
+    pub control_replies: RwLock<Vec<(ControlReplyTo, String, Value)>>,
+
+    /// Holds all sent events.
+    pub events: RwLock<Vec<Event>>,
+
+    /// Holds all dead-lettered deliveries, with the dead-letter queue they were sent to.
+    pub dead_letters: RwLock<Vec<(Delivery, String, DeadLetter)>>,
 }
 
 impl MockBroker {
@@ -61,6 +78,10 @@ impl MockBroker {
 
     pub async fn reset(&self) {
         self.sent_tasks.write().await.clear();
+        self.control_messages.write().await.clear();
+        self.control_replies.write().await.clear();
+        self.events.write().await.clear();
+        self.dead_letters.write().await.clear();
     }
 }
 
@@ -81,7 +102,7 @@ impl Broker for MockBroker {
         queue: &str,
         error_handler: Box<E>,
     ) -> Result<(String, Self::DeliveryStream), BrokerError> {
-        unimplemented!();
+        Ok((format!("mock-{}", queue), MockMessageStream))
     }
 
     #[allow(unused)]
@@ -112,6 +133,79 @@ impl Broker for MockBroker {
         Ok(())
     }
 
+    #[allow(unused)]
+    async fn consume_control(
+        &self,
+        namespace: &str,
+        hostname: &str,
+    ) -> Result<BoxStream<'static, ControlMessage>, BrokerError> {
+        Ok(stream::pending().boxed())
+    }
+
+    async fn send_control(
+        &self,
+        namespace: &str,
+        message: &ControlMessage,
+    ) -> Result<(), BrokerError> {
+        self.control_messages
+            .write()
+            .await
+            .push((namespace.into(), message.clone()));
+        Ok(())
+    }
+
+    async fn send_control_reply(
+        &self,
+        reply_to: &ControlReplyTo,
+        ticket: &str,
+        reply: &Value,
+    ) -> Result<(), BrokerError> {
+        self.control_replies
+            .write()
+            .await
+            .push((reply_to.clone(), ticket.into(), reply.clone()));
+        Ok(())
+    }
+
+    async fn send_event(&self, event: &Event) -> Result<(), BrokerError> {
+        self.events.write().await.push(event.clone());
+        Ok(())
+    }
+
+    async fn send_dead_letter(
+        &self,
+        delivery: &Self::Delivery,
+        queue: &str,
+        dead_letter: &DeadLetter,
+    ) -> Result<(), BrokerError> {
+        self.dead_letters
+            .write()
+            .await
+            .push((delivery.clone(), queue.into(), dead_letter.clone()));
+        Ok(())
+    }
+
+    /// Send the dead-lettered messages back to their queue. Invalid messages are kept.
+    async fn replay_dead_letters(&self, queue: &str) -> Result<usize, BrokerError> {
+        let dead_letters = std::mem::take(&mut *self.dead_letters.write().await);
+        let mut replayed = 0;
+        for (delivery, dead_letter_queue, dead_letter) in dead_letters {
+            match &delivery.0 {
+                Some(message) if dead_letter_queue == queue => {
+                    self.send(message, &dead_letter.queue).await?;
+                    replayed += 1;
+                }
+                _ => {
+                    self.dead_letters
+                        .write()
+                        .await
+                        .push((delivery, dead_letter_queue, dead_letter))
+                }
+            }
+        }
+        Ok(replayed)
+    }
+
     async fn increase_prefetch_count(&self) -> Result<(), BrokerError> {
         Ok(())
     }
@@ -130,12 +224,13 @@ impl Broker for MockBroker {
     }
 }
 
+/// A delivery of a message, or of an invalid message if it is `None`.
 #[derive(Debug, Clone)]
-pub struct Delivery;
+pub struct Delivery(pub Option<Message>);
 
 impl TryDeserializeMessage for Delivery {
     fn try_deserialize_message(&self) -> Result<Message, ProtocolError> {
-        unimplemented!();
+        self.0.clone().ok_or(ProtocolError::MissingHeaders)
     }
 }
 
@@ -146,6 +241,6 @@ impl Stream for MockMessageStream {
 
     #[allow(unused)]
     fn poll_next(self: std::pin::Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
-        unimplemented!();
+        Poll::Pending
     }
 }
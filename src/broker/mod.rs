@@ -3,13 +3,15 @@
 
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
 use futures::Stream;
 use log::error;
+use serde_json::Value;
 use tokio::time::{self, Duration};
 
 use crate::error::BrokerError;
 use crate::{
-    protocol::{Message, TryDeserializeMessage},
+    protocol::{ControlMessage, ControlReplyTo, DeadLetter, Event, Message, TryDeserializeMessage},
     routing::Rule,
 };
 
@@ -75,6 +77,52 @@ pub trait Broker: Send + Sync + Sized {
     /// Send a [`Message`](protocol/struct.Message.html) into a queue.
     async fn send(&self, message: &Message, queue: &str) -> Result<(), BrokerError>;
 
+    /// Consume the remote control commands broadcast to the workers of the `namespace`
+    /// mailbox (`"celery"` by default), for the worker with the given node name.
+    ///
+    /// This is the `<namespace>.pidbox` fanout exchange with AMQP, and the corresponding
+    /// channel with Redis, which the `celery inspect` and `celery control` commands of
+    /// Python Celery send to.
+    async fn consume_control(
+        &self,
+        namespace: &str,
+        hostname: &str,
+    ) -> Result<BoxStream<'static, ControlMessage>, BrokerError>;
+
+    /// Broadcast a remote control command to the workers of the `namespace` mailbox.
+    async fn send_control(
+        &self,
+        namespace: &str,
+        message: &ControlMessage,
+    ) -> Result<(), BrokerError>;
+
+    /// Send the reply to a remote control command, in the format of kombu mailboxes.
+    async fn send_control_reply(
+        &self,
+        reply_to: &ControlReplyTo,
+        ticket: &str,
+        reply: &Value,
+    ) -> Result<(), BrokerError>;
+
+    /// Send an event to the [`EVENT_EXCHANGE`](crate::protocol::EVENT_EXCHANGE), with its
+    /// [`routing_key`](Event::routing_key), for monitors like Flower.
+    async fn send_event(&self, event: &Event) -> Result<(), BrokerError>;
+
+    /// Send a delivery that the worker gave up on to the dead-letter queue `queue`, as it
+    /// was received but with the headers of `dead_letter` added. This works even if the
+    /// delivery can't be deserialized into a [`Message`].
+    async fn send_dead_letter(
+        &self,
+        delivery: &Self::Delivery,
+        queue: &str,
+        dead_letter: &DeadLetter,
+    ) -> Result<(), BrokerError>;
+
+    /// Send the messages that are in the dead-letter queue `queue` back to the queues they
+    /// were consumed from, without the dead-letter headers. Messages that are dead-lettered
+    /// again in the meantime aren't replayed twice. Returns the number of replayed messages.
+    async fn replay_dead_letters(&self, queue: &str) -> Result<usize, BrokerError>;
+
     /// Increase the `prefetch_count`. This has to be done when a task with a future
     /// ETA is consumed.
     async fn increase_prefetch_count(&self) -> Result<(), BrokerError>;
@@ -2,16 +2,22 @@
 #![allow(dead_code)]
 use super::{Broker, BrokerBuilder};
 use crate::error::{BrokerError, ProtocolError};
-use crate::protocol::Delivery;
 use crate::protocol::Message;
 use crate::protocol::TryDeserializeMessage;
+use crate::protocol::{
+    ControlMessage, ControlReplyTo, DeadLetter, Delivery, Event, DEAD_LETTER_ERROR_HEADER,
+    DEAD_LETTER_REASON_HEADER, EVENT_EXCHANGE, ORIGINAL_QUEUE_HEADER,
+};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use futures::future;
+use futures::stream::{BoxStream, StreamExt};
 use futures::Stream;
 use log::{debug, error, warn};
 use redis::aio::ConnectionManager;
 use redis::Client;
 use redis::RedisError;
+use serde_json::{json, Value};
 use std::clone::Clone;
 use std::collections::HashSet;
 use std::fmt;
@@ -23,6 +29,9 @@ use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+/// Separates the fields of the bindings of exchanges stored by kombu.
+const BINDING_SEPARATOR: &str = "\x06\x16";
+
 struct Config {
     broker_url: String,
     prefetch_count: u16,
@@ -30,6 +39,17 @@ struct Config {
     heartbeat: Option<u16>,
 }
 
+/// Moves the last message of the list `KEYS[1]`, if it's still `ARGV[1]`, to the front of
+/// the list `KEYS[2]` as `ARGV[2]`.
+const MOVE_LAST_SCRIPT: &str = r#"
+if redis.call("LINDEX", KEYS[1], -1) == ARGV[1] then
+    redis.call("RPOP", KEYS[1])
+    redis.call("LPUSH", KEYS[2], ARGV[2])
+    return 1
+end
+return 0
+"#;
+
 pub struct RedisBrokerBuilder {
     config: Config,
 }
@@ -116,6 +136,38 @@ pub struct RedisBroker {
     waker_tx: Sender<Waker>,
 }
 
+impl RedisBroker {
+    /// Push a message to the queues bound to `exchange` with a routing key or pattern for
+    /// which `matches` is `true`, like kombu does for direct and topic exchanges.
+    async fn push_to_bindings<F: Fn(&str) -> bool + Send>(
+        &self,
+        exchange: &str,
+        message: &str,
+        matches: F,
+    ) -> Result<(), BrokerError> {
+        let mut conn = self.manager.clone();
+        let bindings: Vec<String> = redis::cmd("SMEMBERS")
+            .arg(format!("_kombu.binding.{}", exchange))
+            .query_async(&mut conn)
+            .await?;
+        for binding in bindings {
+            let mut fields = binding.split(BINDING_SEPARATOR);
+            if let (Some(routing_key), Some(_), Some(queue)) =
+                (fields.next(), fields.next(), fields.next())
+            {
+                if matches(routing_key) {
+                    redis::cmd("LPUSH")
+                        .arg(queue)
+                        .arg(message)
+                        .query_async::<_, ()>(&mut conn)
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Clone)]
 pub struct Channel {
     connection: ConnectionManager,
@@ -194,12 +246,43 @@ impl Channel {
         redis::cmd("HDEL")
             .arg(&self.process_map_name())
             .arg(&delivery.properties.correlation_id)
-            .query_async(&mut self.connection.clone())
+            .query_async::<_, ()>(&mut self.connection.clone())
             .await?;
         Ok(())
     }
 }
 
+/// Get the control message out of a message published by kombu on a fanout exchange.
+fn parse_control_message(payload: &[u8]) -> Result<ControlMessage, BrokerError> {
+    let envelope: Value = serde_json::from_slice(payload)?;
+    let body = envelope["body"].as_str().unwrap_or_default();
+    let body = if envelope["properties"]["body_encoding"] == "base64" {
+        base64::decode(body)
+            .map_err(|e| ProtocolError::InvalidProperty(format!("body error: {}", e)))?
+    } else {
+        body.as_bytes().to_vec()
+    };
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Check if the routing key of a message matches the pattern of a binding to a topic
+/// exchange, in which `*` matches one word and `#` matches zero or more words.
+fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], words: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => words.is_empty(),
+            Some((&"#", rest)) => (0..=words.len()).any(|i| matches(rest, &words[i..])),
+            Some((&word, rest)) => match words.split_first() {
+                Some((first, others)) => (word == "*" || word == *first) && matches(rest, others),
+                None => false,
+            },
+        }
+    }
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let words: Vec<&str> = routing_key.split('.').collect();
+    matches(&pattern, &words)
+}
+
 type ConsumerOutput = Result<Delivery, BrokerError>;
 type ConsumerOutputFuture = Box<dyn Future<Output = ConsumerOutput>>;
 
@@ -342,6 +425,206 @@ impl Broker for RedisBroker {
         Ok(())
     }
 
+    /// Consume remote control commands, which kombu publishes on the
+    /// `/<db>.<namespace>.pidbox` channel.
+    async fn consume_control(
+        &self,
+        namespace: &str,
+        _hostname: &str,
+    ) -> Result<BoxStream<'static, ControlMessage>, BrokerError> {
+        let db = self.client.get_connection_info().redis.db;
+        let mut pubsub = self.client.get_async_connection().await?.into_pubsub();
+        pubsub
+            .subscribe(format!("/{}.{}.pidbox", db, namespace))
+            .await?;
+        Ok(pubsub
+            .into_on_message()
+            .filter_map(|message| {
+                future::ready(match parse_control_message(message.get_payload_bytes()) {
+                    Ok(message) => Some(message),
+                    Err(e) => {
+                        error!("Invalid control message: {}", e);
+                        None
+                    }
+                })
+            })
+            .boxed())
+    }
+
+    /// Broadcast a remote control command on the channel of the mailbox, like kombu's fanout
+    /// exchanges.
+    async fn send_control(
+        &self,
+        namespace: &str,
+        message: &ControlMessage,
+    ) -> Result<(), BrokerError> {
+        let exchange = format!("{}.pidbox", namespace);
+        let db = self.client.get_connection_info().redis.db;
+        let message = serde_json::to_string(&json!({
+            "body": base64::encode(serde_json::to_vec(message)?),
+            "content-encoding": "utf-8",
+            "content-type": "application/json",
+            "headers": {},
+            "properties": {
+                "delivery_info": {
+                    "exchange": exchange,
+                    "routing_key": "",
+                },
+                "body_encoding": "base64",
+                "delivery_tag": Uuid::new_v4().to_string(),
+                "priority": 0,
+            },
+        }))?;
+        redis::cmd("PUBLISH")
+            .arg(format!("/{}.{}", db, exchange))
+            .arg(message)
+            .query_async::<_, ()>(&mut self.manager.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// Send the reply to a remote control command to the queues bound to the reply exchange
+    /// with its routing key, like kombu's direct exchanges.
+    async fn send_control_reply(
+        &self,
+        reply_to: &ControlReplyTo,
+        ticket: &str,
+        reply: &Value,
+    ) -> Result<(), BrokerError> {
+        let message = serde_json::to_string(&json!({
+            "body": base64::encode(serde_json::to_vec(reply)?),
+            "content-encoding": "utf-8",
+            "content-type": "application/json",
+            "headers": {
+                "ticket": ticket,
+                "clock": 0,
+            },
+            "properties": {
+                "delivery_info": {
+                    "exchange": reply_to.exchange,
+                    "routing_key": reply_to.routing_key,
+                },
+                "body_encoding": "base64",
+                "delivery_tag": Uuid::new_v4().to_string(),
+                "priority": 0,
+            },
+        }))?;
+        self.push_to_bindings(&reply_to.exchange, &message, |routing_key| {
+            routing_key == reply_to.routing_key
+        })
+        .await
+    }
+
+    /// Send an event to the queues bound to the event exchange with a pattern that matches
+    /// its routing key, like kombu's topic exchanges.
+    async fn send_event(&self, event: &Event) -> Result<(), BrokerError> {
+        let routing_key = event.routing_key();
+        let message = serde_json::to_string(&json!({
+            "body": base64::encode(serde_json::to_vec(event)?),
+            "content-encoding": "utf-8",
+            "content-type": "application/json",
+            "headers": {
+                "hostname": event.hostname,
+            },
+            "properties": {
+                "delivery_info": {
+                    "exchange": EVENT_EXCHANGE,
+                    "routing_key": routing_key,
+                },
+                "body_encoding": "base64",
+                "delivery_tag": Uuid::new_v4().to_string(),
+                "priority": 0,
+            },
+        }))?;
+        self.push_to_bindings(EVENT_EXCHANGE, &message, |pattern| {
+            topic_matches(pattern, &routing_key)
+        })
+        .await
+    }
+
+    /// Push the message of a delivery to the dead-letter queue. The message is taken as it
+    /// was received from the process map of its queue, since it may not be deserializable.
+    async fn send_dead_letter(
+        &self,
+        delivery: &Self::Delivery,
+        queue: &str,
+        dead_letter: &DeadLetter,
+    ) -> Result<(), BrokerError> {
+        let (channel, delivery) = delivery;
+        let mut conn = self.manager.clone();
+        let raw: Option<String> = redis::cmd("HGET")
+            .arg(channel.process_map_name())
+            .arg(&delivery.properties.correlation_id)
+            .query_async(&mut conn)
+            .await?;
+        let mut message: Value = match raw {
+            Some(raw) => serde_json::from_str(&raw)?,
+            None => {
+                serde_json::from_slice(&delivery.try_deserialize_message()?.json_serialized()?)?
+            }
+        };
+        if let Some(headers) = message["headers"].as_object_mut() {
+            for (key, value) in dead_letter.headers().iter() {
+                headers.insert((*key).into(), json!(value));
+            }
+        }
+        redis::cmd("LPUSH")
+            .arg(queue)
+            .arg(serde_json::to_string(&message)?)
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn replay_dead_letters(&self, queue: &str) -> Result<usize, BrokerError> {
+        let mut conn = self.manager.clone();
+        let script = redis::Script::new(MOVE_LAST_SCRIPT);
+        // Only replay the messages that were in the queue when we started.
+        let count: usize = redis::cmd("LLEN").arg(queue).query_async(&mut conn).await?;
+        let mut replayed = 0;
+        for _ in 0..count {
+            let raw: Option<String> = redis::cmd("LINDEX")
+                .arg(queue)
+                .arg(-1)
+                .query_async(&mut conn)
+                .await?;
+            let raw = match raw {
+                Some(raw) => raw,
+                None => break,
+            };
+            let mut message: Value = serde_json::from_str(&raw)?;
+            let headers = message["headers"].as_object_mut();
+            // Messages that weren't dead-lettered by a worker are moved to the back of the
+            // queue, since we don't know where to send them.
+            let (target, original) = match headers
+                .and_then(|headers| headers.remove(ORIGINAL_QUEUE_HEADER).map(|q| (headers, q)))
+            {
+                Some((headers, Value::String(original_queue))) => {
+                    headers.remove(DEAD_LETTER_REASON_HEADER);
+                    headers.remove(DEAD_LETTER_ERROR_HEADER);
+                    (original_queue, true)
+                }
+                _ => {
+                    warn!("Dead letter without an original queue in {}", queue);
+                    (queue.to_string(), false)
+                }
+            };
+            // The message is only moved if it wasn't taken from the queue in the meantime,
+            // in a single step so it can't be lost.
+            let moved: bool = script
+                .key(queue)
+                .key(target)
+                .arg(raw)
+                .arg(serde_json::to_string(&message)?)
+                .invoke_async(&mut conn)
+                .await?;
+            if moved && original {
+                replayed += 1;
+            }
+        }
+        Ok(replayed)
+    }
+
     /// Increase the `prefetch_count`. This has to be done when a task with a future
     /// ETA is consumed.
     async fn increase_prefetch_count(&self) -> Result<(), BrokerError> {
@@ -359,7 +642,7 @@ impl Broker for RedisBroker {
     /// Clone all channels and connection.
     async fn close(&self) -> Result<(), BrokerError> {
         let mut conn = self.manager.clone();
-        redis::cmd("QUIT").query_async(&mut conn).await?;
+        redis::cmd("QUIT").query_async::<_, ()>(&mut conn).await?;
         Ok(())
     }
 
@@ -418,3 +701,20 @@ impl Broker for RedisBroker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("#", "task.succeeded"));
+        assert!(topic_matches("task.#", "task.succeeded"));
+        assert!(topic_matches("*.heartbeat", "worker.heartbeat"));
+        assert!(topic_matches("task.succeeded", "task.succeeded"));
+        assert!(topic_matches("#.heartbeat", "worker.heartbeat"));
+        assert!(!topic_matches("task.*", "worker.heartbeat"));
+        assert!(!topic_matches("*", "task.succeeded"));
+        assert!(!topic_matches("task.succeeded.#.x", "task.succeeded"));
+    }
+}
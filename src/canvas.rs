@@ -0,0 +1,370 @@
+//! Work-flow primitives to compose tasks, like Python's `celery.canvas`.
+//!
+//! Tasks are chained with [`Signature::then`](crate::task::Signature::then), or with the
+//! [`chain!`](crate::chain) macro, and executed in parallel with a [`Group`], created with
+//! [`group`] or the [`group!`](crate::group) macro. A [`Chord`] runs a callback with the
+//! results of a group once all its tasks are done.
+//!
+//! Running a task over many parameter sets is cheaper with [`map`], [`starmap`] and
+//! [`chunks`], which send a few messages that each run the task over a list of parameters
+//! on the worker, instead of one message per task.
+
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+
+pub use crate::app::{MapParams, MapTask, StarmapParams, StarmapTask};
+use crate::error::ProtocolError;
+use crate::task::{signature_list, RawSignature, Signature, Task};
+
+/// Chain signatures so that each task runs after the previous one succeeds, with the return
+/// value of the previous task as its
+/// [upstream parameter](crate::task::Signature::upstream_param), unless it is
+/// [immutable](crate::task::Signature::immutable).
+///
+/// `chain![a, b, c]` is the same as `a.then(b).then(c)` (see
+/// [`Signature::then`](crate::task::Signature::then)).
+///
+/// # Examples
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// #[celery::task]
+/// fn mul(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x * y)
+/// }
+///
+/// // Computes ((1 + 2) * 3) + 4.
+/// let signature = celery::chain![add::new(1, 2), mul::partial(3), add::partial(4)];
+/// ```
+#[macro_export]
+macro_rules! chain {
+    ($first:expr $(, $next:expr)* $(,)?) => {
+        $first$(.then($next))*
+    };
+}
+
+/// A group of signatures whose tasks are executed in parallel, sent with
+/// [`Celery::send_group`](crate::Celery::send_group).
+///
+/// All the tasks of a group are sent with the same [`group`](crate::task::Request::group)
+/// ID, and their results are collected in a [`GroupResult`](crate::task::GroupResult).
+#[derive(Debug, Clone, Default)]
+pub struct Group {
+    pub(crate) tasks: Vec<RawSignature>,
+}
+
+impl Group {
+    /// Create an empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a signature to the group. The signature can be a chain, in which case the
+    /// result of the last task of the chain is collected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of the signature can't be serialized to JSON.
+    pub fn push<T: Task>(mut self, signature: Signature<T>) -> Self {
+        self.tasks
+            .push(RawSignature::try_from(signature).expect("invalid task parameters"));
+        self
+    }
+
+    /// Get the number of tasks in the group.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Check if the group is empty.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+}
+
+/// Create a [`Group`] from signatures of the same task. Use [`Group::push`] or the
+/// [`group!`](crate::group) macro to group different tasks.
+///
+/// # Examples
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// let group = celery::canvas::group((0..10).map(|i| add::new(i, i)));
+/// ```
+pub fn group<T, I>(signatures: I) -> Group
+where
+    T: Task,
+    I: IntoIterator<Item = Signature<T>>,
+{
+    signatures.into_iter().fold(Group::new(), Group::push)
+}
+
+/// A group of tasks, the header, followed by a callback, the body, that runs once all the
+/// tasks of the header are done. Chords are sent with
+/// [`Celery::send_chord`](crate::Celery::send_chord), which requires a result backend.
+///
+/// The return values of the tasks of the header are passed to the body as a list, in the
+/// order in which the tasks were added to the header, as its first parameter. If a task of
+/// the header fails, the body doesn't run: it is marked as failed and its errbacks are sent
+/// instead.
+///
+/// Result backends that [support native joins](crate::backend::ResultBackend::supports_native_join),
+/// like Redis, count the tasks of the header as they finish. With other backends, the results
+/// of the header are polled by the built-in `celery.chord_unlock` task, like in Python.
+#[derive(Debug, Clone)]
+pub struct Chord {
+    pub(crate) header: Group,
+    pub(crate) body: RawSignature,
+}
+
+/// Serialize a chord like Python serializes chord signatures, with the tasks of the header and
+/// the body as keyword arguments of a `celery.chord` signature.
+impl From<Chord> for RawSignature {
+    fn from(chord: Chord) -> Self {
+        let mut kwargs = Map::new();
+        kwargs.insert("header".into(), json!(chord.header.tasks));
+        kwargs.insert("body".into(), json!(chord.body));
+        kwargs.insert("kwargs".into(), json!({}));
+        let mut signature = RawSignature::new("celery.chord", vec![], kwargs);
+        signature.subtask_type = Some("chord".into());
+        signature
+    }
+}
+
+impl TryFrom<RawSignature> for Chord {
+    type Error = ProtocolError;
+
+    /// Deserialize a chord signature. Like Python, the header can be either a list of
+    /// signatures or a group signature.
+    fn try_from(mut signature: RawSignature) -> Result<Self, Self::Error> {
+        if signature.subtask_type.as_deref() != Some("chord") {
+            return Err(ProtocolError::InvalidProperty("subtask_type".into()));
+        }
+        let header = match signature.kwargs.remove("header") {
+            Some(Value::Object(mut group)) => group
+                .get_mut("kwargs")
+                .and_then(|kwargs| kwargs.get_mut("tasks"))
+                .map(Value::take)
+                .unwrap_or_default(),
+            Some(header) => header,
+            None => return Err(ProtocolError::MissingRequiredProperty("header".into())),
+        };
+        let body = signature
+            .kwargs
+            .remove("body")
+            .ok_or_else(|| ProtocolError::MissingRequiredProperty("body".into()))?;
+        Ok(Chord {
+            header: Group {
+                tasks: signature_list(header)?,
+            },
+            body: serde_json::from_value(body)?,
+        })
+    }
+}
+
+/// Create a [`Chord`] that runs `body` with the results of the tasks of `header`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// #[celery::task]
+/// fn sum(numbers: Vec<i32>) -> TaskResult<i32> {
+///     Ok(numbers.iter().sum())
+/// }
+///
+/// // Computes (1 + 2) + (3 + 4).
+/// let chord = celery::canvas::chord(
+///     celery::group![add::new(1, 2), add::new(3, 4)],
+///     sum::partial(),
+/// );
+/// ```
+///
+/// # Panics
+///
+/// Panics if the parameters of `body` can't be serialized to JSON.
+pub fn chord<U: Task>(header: Group, body: Signature<U>) -> Chord {
+    Chord {
+        header,
+        body: RawSignature::try_from(body).expect("invalid task parameters"),
+    }
+}
+
+/// Create a signature of the built-in [`MapTask`] that runs the task of `task` once for each
+/// item of `it`, with the item as its first parameter, and returns the list of return values.
+/// The worker runs the task within a single message, so the task must be registered on it.
+///
+/// The [`task`](macro@crate::task) attribute macro generates a typed `T::map(...)`
+/// constructor for tasks that have a single parameter.
+///
+/// The signature is [immutable](Signature::immutable) and is sent to the queue of `task`,
+/// if it has one. Otherwise it is routed by the name `celery.map`, like in Python.
+///
+/// # Panics
+///
+/// Panics if the parameters of `task` can't be serialized to JSON.
+pub fn map<T: Task>(task: Signature<T>, it: Vec<Value>) -> Signature<MapTask> {
+    let queue = task.queue.clone();
+    let mut signature = Signature::<MapTask>::new(MapParams {
+        task: RawSignature::try_from(task).expect("invalid task parameters"),
+        it,
+    })
+    .immutable();
+    signature.queue = queue;
+    signature
+}
+
+/// Create a signature of the built-in [`StarmapTask`] that runs the task of `task` once for
+/// each item of `it`, with the item as its positional arguments, and returns the list of
+/// return values. The worker runs the task within a single message, so the task must be
+/// registered on it.
+///
+/// The [`task`](macro@crate::task) attribute macro generates a typed `T::starmap(...)`
+/// constructor that takes tuples of parameters:
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// // Returns [3, 7].
+/// let signature = add::starmap(vec![(1, 2), (3, 4)]);
+/// ```
+///
+/// The signature is [immutable](Signature::immutable) and is sent to the queue of `task`,
+/// if it has one. Otherwise it is routed by the name `celery.starmap`, like in Python.
+///
+/// # Panics
+///
+/// Panics if the parameters of `task` can't be serialized to JSON.
+pub fn starmap<T: Task>(task: Signature<T>, it: Vec<Vec<Value>>) -> Signature<StarmapTask> {
+    let queue = task.queue.clone();
+    let mut signature = Signature::<StarmapTask>::new(StarmapParams {
+        task: RawSignature::try_from(task).expect("invalid task parameters"),
+        it,
+    })
+    .immutable();
+    signature.queue = queue;
+    signature
+}
+
+/// Split `it` into chunks of `n` items and create a [`Group`] with a [`starmap`] signature
+/// for each chunk, like Python's `chunks`. The result of the group is a list with the
+/// return values of each chunk.
+///
+/// The [`task`](macro@crate::task) attribute macro generates a typed `T::chunks(...)`
+/// constructor that takes tuples of parameters:
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// // Sends 10 messages that each run 100 additions.
+/// let group = add::chunks((0..1000).map(|i| (i, i)), 100);
+/// ```
+///
+/// # Panics
+///
+/// Panics if `n` is 0 or if the parameters of `task` can't be serialized to JSON.
+pub fn chunks<T: Task>(task: Signature<T>, it: Vec<Vec<Value>>, n: usize) -> Group {
+    assert!(n > 0, "chunks must have at least one item");
+    let task = RawSignature::try_from(task).expect("invalid task parameters");
+    let queue = task.option_str("queue").map(String::from);
+    let tasks = it
+        .chunks(n)
+        .map(|chunk| {
+            let mut signature = Signature::<StarmapTask>::new(StarmapParams {
+                task: task.clone(),
+                it: chunk.to_vec(),
+            })
+            .immutable();
+            signature.queue = queue.clone();
+            signature
+        })
+        .collect::<Vec<_>>();
+    group(tasks)
+}
+
+/// Create a [`Group`] from signatures of any tasks.
+///
+/// `group![a, b, c]` is the same as `Group::new().push(a).push(b).push(c)` (see [`Group::push`]).
+///
+/// # Examples
+///
+/// ```rust
+/// # use celery::prelude::*;
+/// #[celery::task]
+/// fn add(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x + y)
+/// }
+///
+/// #[celery::task]
+/// fn mul(x: i32, y: i32) -> TaskResult<i32> {
+///     Ok(x * y)
+/// }
+///
+/// let group = celery::group![add::new(1, 2), mul::new(3, 4), add::new(5, 6).then(mul::partial(7))];
+/// ```
+#[macro_export]
+macro_rules! group {
+    ($($signature:expr),* $(,)?) => {
+        $crate::canvas::Group::new()$(.push($signature))*
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chord_from_python_signature() {
+        let signature: RawSignature = serde_json::from_value(json!({
+            "task": "celery.chord",
+            "args": [],
+            "kwargs": {
+                "header": {
+                    "task": "celery.group",
+                    "args": [],
+                    "kwargs": {"tasks": [{"task": "add", "args": [1, 2], "kwargs": {}}]},
+                    "subtask_type": "group",
+                },
+                "body": {"task": "sum", "args": [], "kwargs": {}},
+                "kwargs": {},
+            },
+            "options": {},
+            "subtask_type": "chord",
+            "immutable": false,
+        }))
+        .unwrap();
+        let chord = Chord::try_from(signature).unwrap();
+        assert_eq!(chord.header.tasks[0].task, "add");
+        assert_eq!(chord.header.tasks[0].args, vec![json!(1), json!(2)]);
+        assert_eq!(chord.body.task, "sum");
+
+        // Chords are serialized with a list of signatures as the header, which Python
+        // accepts too.
+        let chord = Chord::try_from(RawSignature::from(chord)).unwrap();
+        assert_eq!(chord.header.len(), 1);
+        assert_eq!(chord.body.task, "sum");
+    }
+}
@@ -94,7 +94,16 @@ macro_rules! __beat_internal {
 ///
 /// - `default_queue`: Set the
 /// [`CeleryBuilder::default_queue`](struct.CeleryBuilder.html#method.default_queue).
+/// - `dead_letter_queue`: Set the [`CeleryBuilder::dead_letter_queue`](struct.CeleryBuilder.html#method.dead_letter_queue).
 /// - `prefetch_count`: Set the [`CeleryBuilder::prefect_count`](struct.CeleryBuilder.html#method.prefect_count).
+/// - `concurrency`: Set the [`CeleryBuilder::concurrency`](struct.CeleryBuilder.html#method.concurrency).
+/// - `blocking_pool_size`: Set the [`CeleryBuilder::blocking_pool_size`](struct.CeleryBuilder.html#method.blocking_pool_size).
+/// - `prefork`: Set the [`CeleryBuilder::prefork`](struct.CeleryBuilder.html#method.prefork).
+/// - `enable_remote_control`: Set the [`CeleryBuilder::enable_remote_control`](struct.CeleryBuilder.html#method.enable_remote_control).
+/// - `control_exchange`: Set the [`CeleryBuilder::control_exchange`](struct.CeleryBuilder.html#method.control_exchange).
+/// - `worker_state_db`: Set the [`CeleryBuilder::worker_state_db`](struct.CeleryBuilder.html#method.worker_state_db).
+/// - `send_events`: Set the [`CeleryBuilder::send_events`](struct.CeleryBuilder.html#method.send_events).
+/// - `send_sent_event`: Set the [`CeleryBuilder::send_sent_event`](struct.CeleryBuilder.html#method.send_sent_event).
 /// - `heartbeat`: Set the [`CeleryBuilder::heartbeat`](struct.CeleryBuilder.html#method.heartbeat).
 /// - `task_time_limit`: Set an app-level [`TaskOptions::time_limit`](task/struct.TaskOptions.html#structfield.time_limit).
 /// - `task_hard_time_limit`: Set an app-level [`TaskOptions::hard_time_limit`](task/struct.TaskOptions.html#structfield.hard_time_limit).
@@ -103,12 +112,18 @@ macro_rules! __beat_internal {
 /// - `task_max_retry_delay`: Set an app-level [`TaskOptions::max_retry_delay`](task/struct.TaskOptions.html#structfield.max_retry_delay).
 /// - `task_retry_for_unexpected`: Set an app-level [`TaskOptions::retry_for_unexpected`](task/struct.TaskOptions.html#structfield.retry_for_unexpected).
 /// - `acks_late`: Set an app-level [`TaskOptions::acks_late`](task/struct.TaskOptions.html#structfield.acks_late).
+/// - `task_ignore_result`: Set an app-level [`TaskOptions::ignore_result`](task/struct.TaskOptions.html#structfield.ignore_result).
+/// - `task_track_started`: Set an app-level [`TaskOptions::track_started`](task/struct.TaskOptions.html#structfield.track_started).
+/// - `task_rate_limit`: Set an app-level [`TaskOptions::rate_limit`](task/struct.TaskOptions.html#structfield.rate_limit).
 /// - `broker_connection_timeout`: Set the
 /// [`CeleryBuilder::broker_connection_timeout`](struct.CeleryBuilder.html#method.broker_connection_timeout).
 /// - `broker_connection_retry`: Set the
 /// [`CeleryBuilder::broker_connection_retry`](struct.CeleryBuilder.html#method.broker_connection_retry).
 /// - `broker_connection_max_retries`: Set the
 /// [`CeleryBuilder::broker_connection_max_retries`](struct.CeleryBuilder.html#method.broker_connection_max_retries).
+/// - `result_backend`: Set the [`CeleryBuilder::result_backend`](struct.CeleryBuilder.html#method.result_backend).
+/// - `result_expires`: Set the [`CeleryBuilder::result_expires`](struct.CeleryBuilder.html#method.result_expires).
+/// - `result_extended`: Set the [`CeleryBuilder::result_extended`](struct.CeleryBuilder.html#method.result_extended).
 ///
 /// # Examples
 ///
@@ -11,6 +11,10 @@ pub enum CeleryError {
     #[error("at least one queue required to consume from")]
     NoQueueToConsume,
 
+    /// Raised when dead letters are replayed but no dead-letter queue was configured.
+    #[error("no dead-letter queue configured")]
+    NoDeadLetterQueue,
+
     /// Forced shutdown.
     #[error("forced shutdown")]
     ForcedShutdown,
@@ -28,10 +32,18 @@ pub enum CeleryError {
     #[error("protocol error")]
     ProtocolError(#[from] ProtocolError),
 
+    /// Any result backend error.
+    #[error("result backend error")]
+    BackendError(#[from] BackendError),
+
     /// There is already a task registered to this name.
     #[error("there is already a task registered as '{0}'")]
     TaskRegistrationError(String),
 
+    /// A rate limit isn't written like Python's rate limits, e.g. `"10/s"`.
+    #[error("invalid rate limit '{0}'")]
+    InvalidRateLimit(String),
+
     #[error("received unregistered task {0}")]
     UnregisteredTaskError(String),
 }
@@ -104,6 +116,21 @@ pub enum TaskError {
     /// to manually trigger a retry from within a task.
     #[error("task retry triggered")]
     Retry(Option<DateTime<Utc>>),
+
+    /// A task returns this error variant when it replaces itself with a new signature.
+    ///
+    /// This error variant should generally not be used directly. Instead, you should call
+    /// the `Task::replace` trait method from within a bound task, which keeps the replacement
+    /// for the worker to send.
+    #[error("task replaced")]
+    Replaced,
+
+    /// Raised when the child process that runs a task is killed or exits before returning
+    /// the result of the task, see [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork).
+    ///
+    /// Like in Python, these errors don't trigger a retry.
+    #[error("worker lost: {0}")]
+    WorkerLostError(String),
 }
 
 /// Errors that can occur while tracing a task.
@@ -120,6 +147,10 @@ pub(crate) enum TraceError {
     /// Raised when a task should be retried.
     #[error("retrying task")]
     Retry(Option<DateTime<Utc>>),
+
+    /// Raised when a task is terminated while executing.
+    #[error("task terminated")]
+    Revoked,
 }
 
 /// Errors that can occur at the broker level.
@@ -180,6 +211,60 @@ impl BrokerError {
     }
 }
 
+/// Errors that can occur at the result backend level.
+#[derive(Error, Debug)]
+pub enum BackendError {
+    /// Raised when a result backend URL can't be parsed.
+    #[error("invalid result backend URL '{0}'")]
+    InvalidBackendUrl(String),
+
+    /// Raised when the result backend doesn't support an operation.
+    #[error("operation not supported by the result backend: {0}")]
+    NotSupported(String),
+
+    /// Raised when trying to retrieve a result without a result backend.
+    #[error("no result backend configured")]
+    NotConfigured,
+
+    /// Raised when a result isn't ready before the timeout expires.
+    #[error("timed out waiting for the result")]
+    Timeout,
+
+    /// The task failed with the given error.
+    #[error("task failed: {0}")]
+    TaskFailed(TaskError),
+
+    /// The task with the given ID was revoked.
+    #[error("task {0} was revoked")]
+    TaskRevoked(String),
+
+    /// Serialization error.
+    #[error("Serialization error \"{0}\"")]
+    SerializationError(#[from] serde_json::Error),
+
+    /// Any other Redis error that could happen.
+    #[error("Redis error \"{0}\"")]
+    RedisError(#[from] redis::RedisError),
+
+    /// Any AMQP error that could happen.
+    #[error("AMQP error \"{0}\"")]
+    AMQPError(#[from] lapin::Error),
+
+    /// Any IO error that could happen.
+    #[error("IO error \"{0}\"")]
+    IoError(#[from] std::io::Error),
+
+    /// Any SQLite error that could happen.
+    #[cfg(any(test, feature = "sqlite_backend"))]
+    #[error("SQLite error \"{0}\"")]
+    SqliteError(#[from] rusqlite::Error),
+
+    /// Raised when a result can't be pickled or unpickled.
+    #[cfg(any(test, feature = "sqlite_backend"))]
+    #[error("pickle error \"{0}\"")]
+    PickleError(#[from] serde_pickle::Error),
+}
+
 /// An invalid glob pattern for a routing rule.
 #[derive(Error, Debug)]
 #[error("invalid glob routing rule")]
@@ -207,6 +292,11 @@ pub enum ProtocolError {
     /// Raised when field value is invalid.
     #[error("invalid property '{0}'")]
     InvalidProperty(String),
+
+    /// Raised when trying to send a partial signature on its own instead of as part of a
+    /// work-flow.
+    #[error("the signature of task '{0}' is partial")]
+    PartialSignature(String),
 }
 
 impl From<serde_json::Error> for ProtocolError {
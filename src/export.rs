@@ -1,6 +1,7 @@
 pub use crate::async_trait::async_trait;
 pub use crate::serde::{Deserialize, Serialize};
 pub use crate::tokio::runtime::Runtime;
+pub use serde_json::{to_value, Map};
 pub use std::sync::Arc;
 pub type Result<T> = std::result::Result<T, crate::error::CeleryError>;
 pub type BeatResult<T> = std::result::Result<T, crate::error::BeatError>;
@@ -89,8 +89,10 @@
 mod app;
 mod routing;
 pub use app::{Celery, CeleryBuilder};
+pub mod backend;
 pub mod beat;
 pub mod broker;
+pub mod canvas;
 pub mod error;
 pub mod prelude;
 pub mod protocol;
@@ -115,10 +117,14 @@ mod codegen;
 /// - `max_retry_delay`: Set a task-level [`TaskOptions::max_retry_delay`](task/struct.TaskOptions.html#structfield.max_retry_delay).
 /// - `retry_for_unexpected`: Set a task-level [`TaskOptions::retry_for_unexpected`](task/struct.TaskOptions.html#structfield.retry_for_unexpected).
 /// - `acks_late`: Set a task-level [`TaskOptions::acks_late`](task/struct.TaskOptions.html#structfield.acks_late).
+/// - `ignore_result`: Set a task-level [`TaskOptions::ignore_result`](task/struct.TaskOptions.html#structfield.ignore_result).
+/// - `track_started`: Set a task-level [`TaskOptions::track_started`](task/struct.TaskOptions.html#structfield.track_started).
+/// - `rate_limit`: Set a task-level [`TaskOptions::rate_limit`](task/struct.TaskOptions.html#structfield.rate_limit), written like Python's rate limits, e.g. `"10/s"`.
 /// - `content_type`: Set a task-level [`TaskOptions::content_type`](task/struct.TaskOptions.html#structfield.content_type).
 /// - `bind`: A bool. If true, the task will be run like an instance method and so the function's
 /// first argument should be a reference to `Self`. Note however that Rust won't allow you to call
 /// the argument `self`. Instead, you could use `task` or just `t`.
+/// - `blocking`: A bool. If true, the function, which can't be async, is run on the blocking thread pool of the worker with [`Task::run_blocking`](task/trait.Task.html#method.run_blocking) so that CPU-bound or blocking work doesn't stall other tasks.
 /// - `on_failure`: An async callback function to run when the task fails. Should accept a reference to
 /// a task instance and a reference to a [`TaskError`](error/enum.TaskError.html).
 /// - `on_success`: An async callback function to run when the task succeeds. Should accept a reference to
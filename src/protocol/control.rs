@@ -0,0 +1,86 @@
+//! Defines the remote control protocol, which is Python's kombu mailbox protocol.
+
+use globset::Glob;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Where the reply to a [`ControlMessage`] has to be sent.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ControlReplyTo {
+    /// The exchange to send the reply to, usually `reply.celery.pidbox`.
+    pub exchange: String,
+
+    /// The routing key of the reply, which identifies the client that is waiting for it.
+    pub routing_key: String,
+}
+
+/// A remote control command broadcast to workers, like the ones sent by `celery inspect`
+/// and `celery control`.
+#[derive(PartialEq, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ControlMessage {
+    /// The name of the command, e.g. `ping`.
+    #[serde(default)]
+    pub method: String,
+
+    /// The arguments of the command.
+    #[serde(default)]
+    pub arguments: Map<String, Value>,
+
+    /// The node names of the workers that should execute the command, or `None` for all
+    /// workers.
+    #[serde(default)]
+    pub destination: Option<Vec<String>>,
+
+    /// A pattern that the node names of the workers that should execute the command must
+    /// match.
+    #[serde(default)]
+    pub pattern: Option<String>,
+
+    /// How `pattern` is matched. Only `glob`, the default, is supported.
+    #[serde(default)]
+    pub matcher: Option<String>,
+
+    /// Where to send the reply, if one is expected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<ControlReplyTo>,
+
+    /// The ticket that identifies the replies to this command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+}
+
+impl ControlMessage {
+    /// Create a command for all workers that doesn't expect a reply.
+    pub fn new(method: &str, arguments: Map<String, Value>) -> Self {
+        Self {
+            method: method.into(),
+            arguments,
+            ..Default::default()
+        }
+    }
+
+    /// Check if the worker with the given node name should execute the command.
+    pub fn is_for(&self, hostname: &str) -> bool {
+        if let Some(ref destination) = self.destination {
+            if !destination.is_empty() && !destination.iter().any(|d| d == hostname) {
+                return false;
+            }
+        }
+        match self.pattern {
+            Some(ref pattern) if !pattern.is_empty() => {
+                if !matches!(self.matcher.as_deref(), None | Some("glob")) {
+                    warn!(
+                        "Unsupported matcher {:?} for control commands",
+                        self.matcher
+                    );
+                    return false;
+                }
+                Glob::new(pattern)
+                    .map(|glob| glob.compile_matcher().is_match(hostname))
+                    .unwrap_or(false)
+            }
+            _ => true,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Defines how messages that workers give up on are sent to a dead-letter queue, see
+//! [`CeleryBuilder::dead_letter_queue`](crate::CeleryBuilder::dead_letter_queue).
+
+use std::fmt;
+
+/// The header of dead-lettered messages that holds the [`DeadLetterReason`].
+pub const DEAD_LETTER_REASON_HEADER: &str = "x-dead-letter-reason";
+
+/// The header of dead-lettered messages that holds the error that made the worker give up
+/// on the message.
+pub const DEAD_LETTER_ERROR_HEADER: &str = "x-dead-letter-error";
+
+/// The header of dead-lettered messages that holds the queue that the message was consumed
+/// from, to which it's sent back when it's replayed.
+pub const ORIGINAL_QUEUE_HEADER: &str = "x-original-queue";
+
+/// Why a message was sent to the dead-letter queue.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeadLetterReason {
+    /// The message couldn't be deserialized.
+    Undeserializable,
+
+    /// The task of the message isn't registered on the worker.
+    Unregistered,
+
+    /// The task failed after being retried as many times as allowed by its
+    /// [`max_retries`](crate::task::TaskOptions::max_retries).
+    RetriesExhausted,
+}
+
+impl DeadLetterReason {
+    /// The value of the [`DEAD_LETTER_REASON_HEADER`], e.g. `retries-exhausted`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeadLetterReason::Undeserializable => "undeserializable",
+            DeadLetterReason::Unregistered => "unregistered",
+            DeadLetterReason::RetriesExhausted => "retries-exhausted",
+        }
+    }
+}
+
+impl fmt::Display for DeadLetterReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why and from where a message is dead-lettered. This is added to the original headers of
+/// the message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeadLetter {
+    /// Why the message is dead-lettered.
+    pub reason: DeadLetterReason,
+
+    /// The queue that the message was consumed from.
+    pub queue: String,
+
+    /// The error that made the worker give up on the message.
+    pub error: String,
+}
+
+impl DeadLetter {
+    /// The headers to add to the message, as names and values.
+    pub fn headers(&self) -> [(&'static str, &str); 3] {
+        [
+            (DEAD_LETTER_REASON_HEADER, self.reason.as_str()),
+            (DEAD_LETTER_ERROR_HEADER, &self.error),
+            (ORIGINAL_QUEUE_HEADER, &self.queue),
+        ]
+    }
+}
@@ -0,0 +1,62 @@
+//! Defines the events that workers and producers send to monitors like Flower, in the format
+//! of Python's `celery.events.Event`.
+
+use chrono::{Local, Offset};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The exchange that events are sent to.
+pub const EVENT_EXCHANGE: &str = "celeryev";
+
+/// An event like `task-succeeded` or `worker-heartbeat`, sent to the [`EVENT_EXCHANGE`].
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// The type of the event, e.g. `task-succeeded`.
+    #[serde(rename = "type")]
+    pub event_type: String,
+
+    /// The node name of the worker or producer that sent the event.
+    pub hostname: String,
+
+    /// When the event was sent, in seconds since the Unix epoch.
+    pub timestamp: f64,
+
+    /// The offset of the local time zone of the sender, in hours west of UTC.
+    pub utcoffset: i32,
+
+    /// The PID of the sender.
+    pub pid: u32,
+
+    /// The logical clock of the sender.
+    pub clock: u64,
+
+    /// The fields specific to the type of the event, e.g. `uuid` and `runtime` for
+    /// `task-succeeded`.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+impl Event {
+    /// Create an event sent now by the current process.
+    pub fn new(event_type: &str, hostname: &str, clock: u64, fields: Map<String, Value>) -> Self {
+        Self {
+            event_type: event_type.into(),
+            hostname: hostname.into(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs_f64())
+                .unwrap_or_default(),
+            utcoffset: -Local::now().offset().fix().local_minus_utc() / 3600,
+            pid: process::id(),
+            clock,
+            fields,
+        }
+    }
+
+    /// The routing key of the event, e.g. `task.succeeded` for `task-succeeded`.
+    pub fn routing_key(&self) -> String {
+        self.event_type.replace('-', ".")
+    }
+}
@@ -7,15 +7,28 @@
 use chrono::{DateTime, Duration, Utc};
 use log::{debug, warn};
 use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::{from_slice, from_value, json, Value};
+use serde_json::{from_slice, from_value, json, Map, Value};
 use std::convert::TryFrom;
 use std::process;
 use std::time::SystemTime;
 use uuid::Uuid;
 
 use crate::error::{ContentTypeError, ProtocolError};
-use crate::task::{Signature, Task};
+use crate::task::{
+    signature_list, DynamicParams, DynamicTask, RawSignature, Signature, SignatureParams, Task,
+};
+
+mod control;
+pub use control::{ControlMessage, ControlReplyTo};
+mod dead_letter;
+pub use dead_letter::{
+    DeadLetter, DeadLetterReason, DEAD_LETTER_ERROR_HEADER, DEAD_LETTER_REASON_HEADER,
+    ORIGINAL_QUEUE_HEADER,
+};
+mod event;
+pub use event::{Event, EVENT_EXCHANGE};
 
 static ORIGIN: Lazy<Option<String>> = Lazy::new(|| {
     hostname::get()
@@ -39,6 +52,81 @@ impl Default for MessageContentType {
     }
 }
 
+impl MessageContentType {
+    /// The MIME type of the format, used as the content type of messages.
+    pub(crate) fn mime_type(&self) -> &'static str {
+        use MessageContentType::*;
+        match self {
+            Json => "application/json",
+            Yaml => "application/x-yaml",
+            Pickle => "application/x-python-serialize",
+            MsgPack => "application/x-msgpack",
+        }
+    }
+
+    /// The name of the format in Python, used for the `serializer` option of signatures.
+    pub(crate) fn serializer(&self) -> &'static str {
+        use MessageContentType::*;
+        match self {
+            Json => "json",
+            Yaml => "yaml",
+            Pickle => "pickle",
+            MsgPack => "msgpack",
+        }
+    }
+
+    /// Get the format from its name in Python.
+    pub(crate) fn from_serializer(serializer: &str) -> Option<Self> {
+        use MessageContentType::*;
+        match serializer {
+            "json" => Some(Json),
+            "yaml" => Some(Yaml),
+            "pickle" => Some(Pickle),
+            "msgpack" => Some(MsgPack),
+            _ => None,
+        }
+    }
+}
+
+/// Serialize a message body with the format of the given MIME type.
+fn serialize_body<B: Serialize>(content_type: &str, body: &B) -> Result<Vec<u8>, ProtocolError> {
+    match content_type {
+        "application/json" => Ok(serde_json::to_vec(body)?),
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-yaml" => Ok(serde_yaml::to_vec(body)?),
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-python-serialize" => {
+            Ok(serde_pickle::to_vec(body, serde_pickle::SerOptions::new())?)
+        }
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-msgpack" => Ok(rmp_serde::to_vec(body)?),
+        _ => Err(ProtocolError::BodySerializationError(
+            ContentTypeError::Unknown,
+        )),
+    }
+}
+
+fn deserialize_body<B: DeserializeOwned>(
+    content_type: &str,
+    raw_body: &[u8],
+) -> Result<B, ProtocolError> {
+    match content_type {
+        "application/json" => Ok(serde_json::from_slice(raw_body)?),
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-yaml" => Ok(serde_yaml::from_slice(raw_body)?),
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-python-serialize" => Ok(serde_pickle::from_slice(
+            raw_body,
+            serde_pickle::DeOptions::new(),
+        )?),
+        #[cfg(any(test, feature = "extra_content_types"))]
+        "application/x-msgpack" => Ok(rmp_serde::from_slice(raw_body)?),
+        _ => Err(ProtocolError::BodySerializationError(
+            ContentTypeError::Unknown,
+        )),
+    }
+}
+
 /// Create a message with a custom configuration.
 pub struct MessageBuilder<T>
 where
@@ -46,6 +134,9 @@ where
 {
     message: Message,
     params: Option<T::Params>,
+    args: Option<Vec<Value>>,
+    kwargs: Option<Map<String, Value>>,
+    embed: MessageBodyEmbed,
 }
 
 impl<T> MessageBuilder<T>
@@ -71,6 +162,9 @@ where
                 raw_body: Vec::new(),
             },
             params: None,
+            args: None,
+            kwargs: None,
+            embed: MessageBodyEmbed::default(),
         }
     }
     /// Set which serialization method is used in the body.
@@ -78,14 +172,7 @@ where
     /// JSON is the default, and is also the only option unless the feature "extra_content_types" is enabled.
     #[cfg(any(test, feature = "extra_content_types"))]
     pub fn content_type(mut self, content_type: MessageContentType) -> Self {
-        use MessageContentType::*;
-        let content_type_name = match content_type {
-            Json => "application/json",
-            Yaml => "application/x-yaml",
-            Pickle => "application/x-python-serialize",
-            MsgPack => "application/x-msgpack",
-        };
-        self.message.properties.content_type = content_type_name.into();
+        self.message.properties.content_type = content_type.mime_type().into();
         self
     }
 
@@ -134,6 +221,11 @@ where
         self
     }
 
+    pub fn group_index(mut self, group_index: u32) -> Self {
+        self.message.headers.group_index = Some(group_index);
+        self
+    }
+
     pub fn meth(mut self, meth: String) -> Self {
         self.message.headers.meth = Some(meth);
         self
@@ -201,29 +293,59 @@ where
         self
     }
 
+    /// Set the signature of the callback of the chord the task is part of.
+    pub fn chord(mut self, chord: RawSignature) -> Self {
+        self.embed.chord = Some(chord);
+        self
+    }
+
+    /// Set untyped positional arguments, for tasks that are only known by name like
+    /// [`DynamicTask`]. They are ignored if [`params`](MessageBuilder::params) are set.
+    pub fn args(mut self, args: Vec<Value>) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    /// Set untyped keyword arguments, for tasks that are only known by name like
+    /// [`DynamicTask`]. They are ignored if [`params`](MessageBuilder::params) are set.
+    pub fn kwargs(mut self, kwargs: Map<String, Value>) -> Self {
+        self.kwargs = Some(kwargs);
+        self
+    }
+
+    /// Set the signatures of the tasks to run after this one, in reverse order like Python
+    /// does, i.e. the next task is the last one.
+    pub fn chain(mut self, chain: Vec<RawSignature>) -> Self {
+        self.embed.chain = Some(chain);
+        self
+    }
+
+    /// Set the signatures of the tasks to send with the return value of this task.
+    pub fn callbacks(mut self, callbacks: Vec<RawSignature>) -> Self {
+        self.embed.callbacks = Some(callbacks);
+        self
+    }
+
+    /// Set the signatures of the tasks to send if this task fails.
+    pub fn errbacks(mut self, errbacks: Vec<RawSignature>) -> Self {
+        self.embed.errbacks = Some(errbacks);
+        self
+    }
+
     /// Get the `Message` with the custom configuration.
     pub fn build(mut self) -> Result<Message, ProtocolError> {
+        let content_type = self.message.properties.content_type.as_str();
         if let Some(params) = self.params.take() {
-            let body = MessageBody::<T>::new(params);
-
-            let raw_body = match self.message.properties.content_type.as_str() {
-                "application/json" => serde_json::to_vec(&body)?,
-                #[cfg(any(test, feature = "extra_content_types"))]
-                "application/x-yaml" => serde_yaml::to_vec(&body)?,
-                #[cfg(any(test, feature = "extra_content_types"))]
-                "application/x-python-serialize" => {
-                    serde_pickle::to_vec(&body, serde_pickle::SerOptions::new())?
-                }
-                #[cfg(any(test, feature = "extra_content_types"))]
-                "application/x-msgpack" => rmp_serde::to_vec(&body)?,
-                _ => {
-                    return Err(ProtocolError::BodySerializationError(
-                        ContentTypeError::Unknown,
-                    ));
-                }
-            };
-            self.message.raw_body = raw_body;
-        };
+            let body = MessageBody::<T>(vec![], params, self.embed);
+            self.message.raw_body = serialize_body(content_type, &body)?;
+        } else if self.args.is_some() || self.kwargs.is_some() {
+            let body = (
+                self.args.unwrap_or_default(),
+                self.kwargs.unwrap_or_default(),
+                self.embed,
+            );
+            self.message.raw_body = serialize_body(content_type, &body)?;
+        }
         Ok(self.message)
     }
 }
@@ -259,15 +381,17 @@ impl Message {
                     {
                         if !args.is_empty() {
                             // Non-empty args, need to try to coerce them into kwargs.
+                            // They fill the parameters that aren't keyword arguments, in
+                            // order, so that the return value of the previous task of a chain
+                            // goes to the parameter that a partial signature leaves out.
                             let mut kwargs = kwargs.clone();
                             let embed = embed.clone();
-                            let arg_names = T::ARGS;
-                            for (i, arg) in args.iter().enumerate() {
-                                if let Some(arg_name) = arg_names.get(i) {
-                                    kwargs.insert((*arg_name).into(), arg.clone());
-                                } else {
-                                    break;
-                                }
+                            let arg_names: Vec<_> = T::ARGS
+                                .iter()
+                                .filter(|arg_name| !kwargs.contains_key(**arg_name))
+                                .collect();
+                            for (arg_name, arg) in arg_names.into_iter().zip(args) {
+                                kwargs.insert((*arg_name).into(), arg.clone());
                             }
                             return Ok(MessageBody(
                                 vec![],
@@ -292,13 +416,12 @@ impl Message {
                             // Non-empty args, need to try to coerce them into kwargs.
                             let mut kwargs = kwargs.clone();
                             let embed = embed.clone();
-                            let arg_names = T::ARGS;
-                            for (i, arg) in args.iter().enumerate() {
-                                if let Some(arg_name) = arg_names.get(i) {
-                                    kwargs.insert((*arg_name).into(), arg.clone());
-                                } else {
-                                    break;
-                                }
+                            let arg_names: Vec<_> = T::ARGS
+                                .iter()
+                                .filter(|arg_name| !kwargs.contains_key(&(**arg_name).into()))
+                                .collect();
+                            for (arg_name, arg) in arg_names.into_iter().zip(args) {
+                                kwargs.insert((*arg_name).into(), arg.clone());
                             }
                             return Ok(MessageBody(
                                 vec![],
@@ -323,14 +446,13 @@ impl Message {
                             // Non-empty args, need to try to coerce them into kwargs.
                             let mut kwargs = kwargs.clone();
                             let embed = embed.clone();
-                            let arg_names = T::ARGS;
-                            for (i, arg) in args.iter().enumerate() {
-                                if let Some(arg_name) = arg_names.get(i) {
-                                    let key = HashableValue::String((*arg_name).into());
-                                    kwargs.insert(key, arg.clone());
-                                } else {
-                                    break;
-                                }
+                            let arg_names: Vec<_> = T::ARGS
+                                .iter()
+                                .map(|arg_name| HashableValue::String((*arg_name).into()))
+                                .filter(|key| !kwargs.contains_key(key))
+                                .collect();
+                            for (key, arg) in arg_names.into_iter().zip(args) {
+                                kwargs.insert(key, arg.clone());
                             }
                             return Ok(MessageBody(
                                 vec![],
@@ -356,37 +478,18 @@ impl Message {
                             // Non-empty args, need to try to coerce them into kwargs.
                             let mut kwargs = kwargs.clone();
                             let embed = embed.clone();
-                            let arg_names = T::ARGS;
-                            for (i, arg) in args.iter().enumerate() {
-                                if let Some(arg_name) = arg_names.get(i) {
-                                    // messagepack is storing the map as a vec where each item
-                                    // is a tuple of (key, value). here we will look for an item
-                                    // with the matching key and replace it, or insert a new entry
-                                    // at the end of the vec
-                                    let existing_entry = kwargs
+                            // messagepack is storing the map as a vec where each item is
+                            // a tuple of (key, value).
+                            let arg_names: Vec<_> = T::ARGS
+                                .iter()
+                                .filter(|arg_name| {
+                                    !kwargs
                                         .iter()
-                                        .enumerate()
-                                        .filter(|(_, (key, _))| {
-                                            if let Value::String(key) = key {
-                                                if let Some(key) = key.as_str() {
-                                                    key == *arg_name
-                                                } else {
-                                                    false
-                                                }
-                                            } else {
-                                                false
-                                            }
-                                        })
-                                        .map(|(i, _)| i)
-                                        .next();
-                                    if let Some(index) = existing_entry {
-                                        kwargs[index] = ((*arg_name).into(), arg.clone());
-                                    } else {
-                                        kwargs.push(((*arg_name).into(), arg.clone()));
-                                    }
-                                } else {
-                                    break;
-                                }
+                                        .any(|(key, _)| key.as_str() == Some(**arg_name))
+                                })
+                                .collect();
+                            for (arg_name, arg) in arg_names.into_iter().zip(args) {
+                                kwargs.push(((*arg_name).into(), arg.clone()));
                             }
                             return Ok(MessageBody(
                                 vec![],
@@ -404,6 +507,14 @@ impl Message {
         }
     }
 
+    /// Deserialize the body into untyped positional and keyword arguments, for tasks that are
+    /// only known by name like [`DynamicTask`].
+    pub fn raw_params(&self) -> Result<(DynamicParams, MessageBodyEmbed), ProtocolError> {
+        let (args, kwargs, embed) =
+            deserialize_body(&self.properties.content_type, &self.raw_body)?;
+        Ok((DynamicParams { args, kwargs }, embed))
+    }
+
     /// Get the task ID.
     pub fn task_id(&self) -> &str {
         &self.headers.id
@@ -440,6 +551,7 @@ impl Message {
                 "root_id": root_id,
                 "parent_id": self.headers.parent_id.clone(),
                 "group": self.headers.group.clone(),
+                "group_index": self.headers.group_index,
                 "meth": self.headers.meth.clone(),
                 "shadow": self.headers.shadow.clone(),
                 "eta": eta,
@@ -516,7 +628,128 @@ where
             builder = builder.hard_time_limit(time_limit);
         }
 
-        builder.params(task_sig.params).build()
+        if !task_sig.chain.is_empty() {
+            task_sig.chain.reverse();
+            builder = builder.chain(task_sig.chain);
+        }
+
+        if !task_sig.callbacks.is_empty() {
+            builder = builder.callbacks(task_sig.callbacks);
+        }
+
+        if !task_sig.errbacks.is_empty() {
+            builder = builder.errbacks(task_sig.errbacks);
+        }
+
+        match task_sig.params {
+            SignatureParams::Params(params) => builder.params(params).build(),
+            SignatureParams::Partial(_) => Err(ProtocolError::PartialSignature(T::NAME.into())),
+        }
+    }
+}
+
+impl TryFrom<RawSignature> for Message {
+    type Error = ProtocolError;
+
+    /// Create a message from an untyped signature. The options of the signature are
+    /// interpreted like the arguments of Python's `apply_async`.
+    fn try_from(mut signature: RawSignature) -> Result<Self, Self::Error> {
+        let now = DateTime::<Utc>::from(SystemTime::now());
+        let parse_date = |name: &str, value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|date| date.with_timezone(&Utc))
+                .map_err(|_| ProtocolError::InvalidProperty(name.into()))
+        };
+        let seconds = |seconds: f64| Duration::milliseconds((seconds * 1000.0) as i64);
+
+        let id = signature
+            .option_str("task_id")
+            .map(String::from)
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let mut builder = MessageBuilder::<DynamicTask>::new(id).task(signature.task.clone());
+
+        if let Some(serializer) = signature.option_str("serializer") {
+            let content_type = MessageContentType::from_serializer(serializer).ok_or(
+                ProtocolError::BodySerializationError(ContentTypeError::Unknown),
+            )?;
+            // Other content types fail to serialize without the "extra_content_types"
+            // feature, so the builder method isn't always available.
+            builder.message.properties.content_type = content_type.mime_type().into();
+        }
+
+        match signature.options.get("countdown") {
+            Some(countdown) => {
+                let countdown = countdown
+                    .as_f64()
+                    .ok_or_else(|| ProtocolError::InvalidProperty("countdown".into()))?;
+                builder = builder.eta(now + seconds(countdown));
+            }
+            None => {
+                if let Some(eta) = signature.option_str("eta") {
+                    builder = builder.eta(parse_date("eta", eta)?);
+                }
+            }
+        };
+        match signature.options.get("expires") {
+            Some(Value::String(expires)) => {
+                builder = builder.expires(parse_date("expires", expires)?)
+            }
+            Some(Value::Number(expires)) => {
+                builder = builder.expires(now + seconds(expires.as_f64().unwrap_or(0.0)))
+            }
+            Some(Value::Null) | None => (),
+            Some(_) => return Err(ProtocolError::InvalidProperty("expires".into())),
+        };
+
+        let option_string = |name: &str| signature.option_str(name).map(String::from);
+        let option_u32 = |name: &str| {
+            signature
+                .options
+                .get(name)
+                .and_then(Value::as_u64)
+                .map(|value| value as u32)
+        };
+        if let Some(root_id) = option_string("root_id") {
+            builder = builder.root_id(root_id);
+        }
+        if let Some(parent_id) = option_string("parent_id") {
+            builder = builder.parent_id(parent_id);
+        }
+        if let Some(group) = option_string("group_id") {
+            builder = builder.group(group);
+        }
+        if let Some(group_index) = option_u32("group_index") {
+            builder = builder.group_index(group_index);
+        }
+        if let Some(reply_to) = option_string("reply_to") {
+            builder = builder.reply_to(reply_to);
+        }
+        // Python time limits are hard time limits, Python soft time limits are Rust time
+        // limits.
+        if let Some(time_limit) = option_u32("time_limit") {
+            builder = builder.hard_time_limit(time_limit);
+        }
+        if let Some(soft_time_limit) = option_u32("soft_time_limit") {
+            builder = builder.time_limit(soft_time_limit);
+        }
+
+        if let Some(chain) = signature.options.remove("chain") {
+            builder = builder.chain(from_value(chain)?);
+        }
+        if let Some(chord) = signature.options.remove("chord") {
+            builder = builder.chord(from_value(chord)?);
+        }
+        if let Some(link) = signature.options.remove("link") {
+            builder = builder.callbacks(signature_list(link)?);
+        }
+        if let Some(link_error) = signature.options.remove("link_error") {
+            builder = builder.errbacks(signature_list(link_error)?);
+        }
+
+        builder
+            .args(signature.args)
+            .kwargs(signature.kwargs)
+            .build()
     }
 }
 
@@ -581,6 +814,9 @@ pub struct MessageHeaders {
     /// The unique ID of the task's group, if this task is a member.
     pub group: Option<String>,
 
+    /// The position of the task in its group.
+    pub group_index: Option<u32>,
+
     /// Currently unused but could be used in the future to specify class+method pairs.
     pub meth: Option<String>,
 
@@ -632,27 +868,27 @@ where
 }
 
 /// Contains callback / errback signatures and work-flow primitives.
-#[derive(Eq, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Eq, PartialEq, Debug, Default, Clone, Serialize, Deserialize)]
 pub struct MessageBodyEmbed {
-    /// An array of serialized signatures of tasks to call with the result of this task.
+    /// An array of signatures of tasks to call with the result of this task.
     #[serde(default)]
-    pub callbacks: Option<Vec<String>>,
+    pub callbacks: Option<Vec<RawSignature>>,
 
-    /// An array of serialized signatures of tasks to call if this task results in an error.
+    /// An array of signatures of tasks to call if this task results in an error.
     ///
-    /// Note that `errbacks` work differently from `callbacks` because the error returned by
-    /// a task may not be serializable. Therefore the `errbacks` tasks are passed the task ID
-    /// instead of the error itself.
+    /// Note that `errbacks` work differently from `callbacks`: instead of a return value,
+    /// the `errbacks` tasks are passed the ID of the task.
     #[serde(default)]
-    pub errbacks: Option<Vec<String>>,
+    pub errbacks: Option<Vec<RawSignature>>,
 
-    /// An array of serialized signatures of the remaining tasks in the chain.
+    /// An array of signatures of the remaining tasks in the chain, in reverse order: the
+    /// next task is the last one.
     #[serde(default)]
-    pub chain: Option<Vec<String>>,
+    pub chain: Option<Vec<RawSignature>>,
 
-    /// The serialized signature of the chord callback.
+    /// The signature of the chord callback.
     #[serde(default)]
-    pub chord: Option<String>,
+    pub chord: Option<RawSignature>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -699,6 +935,7 @@ impl Delivery {
                 root_id: self.headers.root_id.clone(),
                 parent_id: self.headers.parent_id.clone(),
                 group: self.headers.group.clone(),
+                group_index: self.headers.group_index,
                 meth: self.headers.meth.clone(),
                 shadow: self.headers.shadow.clone(),
                 eta: self.headers.eta,
@@ -2,8 +2,8 @@ use async_trait::async_trait;
 
 use super::*;
 use crate::error::TaskError;
-use crate::task::{Request, Task, TaskOptions};
-use chrono::{DateTime, SecondsFormat, Utc};
+use crate::task::{DynamicTask, Request, Task, TaskOptions};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
 use std::time::SystemTime;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -180,6 +180,7 @@ fn test_serialization() {
             root_id: Some("aaa".into()),
             parent_id: Some("000".into()),
             group: Some("A".into()),
+            group_index: Some(2),
             meth: Some("method_name".into()),
             shadow: Some("add-these".into()),
             eta: Some(now),
@@ -217,6 +218,7 @@ fn test_serialization() {
     assert_eq!(ser_msg_json["headers"]["root_id"], String::from("aaa"));
     assert_eq!(ser_msg_json["headers"]["parent_id"], String::from("000"));
     assert_eq!(ser_msg_json["headers"]["group"], String::from("A"));
+    assert_eq!(ser_msg_json["headers"]["group_index"], 2);
     assert_eq!(ser_msg_json["headers"]["meth"], String::from("method_name"));
     assert_eq!(ser_msg_json["headers"]["shadow"], String::from("add-these"));
     assert_eq!(ser_msg_json["headers"]["retries"], 1);
@@ -231,3 +233,174 @@ fn test_serialization() {
     assert_eq!(body.len(), 73);
     assert_eq!(&body, JSON.as_bytes());
 }
+
+/// The body of a message sent by Python for `chain(test.s(4), test.s())`.
+const PYTHON_CHAIN_JSON: &str = r#"[[4], {}, {"callbacks": null, "errbacks": null, "chain": [{"task": "test", "args": [], "kwargs": {}, "options": {"task_id": "bbb", "reply_to": "ccc"}, "subtask_type": null, "immutable": false, "chord_size": null}], "chord": null}]"#;
+
+#[test]
+fn test_deserialize_body_with_chain() {
+    let message = Message {
+        properties: MessageProperties {
+            correlation_id: "aaa".into(),
+            content_type: "application/json".into(),
+            content_encoding: "utf-8".into(),
+            reply_to: None,
+        },
+        headers: MessageHeaders {
+            id: "aaa".into(),
+            task: "test".into(),
+            ..Default::default()
+        },
+        raw_body: Vec::from(PYTHON_CHAIN_JSON),
+    };
+    let (params, embed) = message.body::<TestTask>().unwrap().parts();
+    assert_eq!(params.a, 4);
+    let chain = embed.chain.unwrap();
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0].task, "test");
+    assert_eq!(chain[0].option_str("task_id"), Some("bbb"));
+    assert!(!chain[0].immutable);
+}
+
+#[test]
+fn test_raw_signature_to_message() {
+    let mut signature = RawSignature::new("test", vec![json!(4)], serde_json::Map::new());
+    signature.options = json!({
+        "task_id": "aaa",
+        "countdown": 10,
+        "expires": "2021-10-07T12:30:00+00:00",
+        "time_limit": 30,
+        "soft_time_limit": 20,
+        "parent_id": "bbb",
+        "root_id": "ccc",
+        "reply_to": "ddd",
+        "serializer": "yaml",
+        "chain": [RawSignature::new("test", vec![], serde_json::Map::new())],
+        // Python accepts either a single signature or a list.
+        "link": RawSignature::new("callback", vec![], serde_json::Map::new()),
+        "link_error": [RawSignature::new("errback", vec![], serde_json::Map::new())],
+    })
+    .as_object()
+    .unwrap()
+    .clone();
+    let message = Message::try_from(signature).unwrap();
+
+    assert_eq!(message.task_id(), "aaa");
+    assert_eq!(message.headers.task, "test");
+    assert_eq!(message.headers.parent_id, Some("bbb".into()));
+    assert_eq!(message.headers.root_id, Some("ccc".into()));
+    assert_eq!(message.properties.reply_to, Some("ddd".into()));
+    assert_eq!(message.properties.content_type, "application/x-yaml");
+    assert_eq!(message.headers.timelimit, (Some(30), Some(20)));
+    assert_eq!(
+        message.headers.expires,
+        Some(Utc.ymd(2021, 10, 7).and_hms(12, 30, 0))
+    );
+    let countdown = message.headers.eta.unwrap() - Utc::now();
+    assert!(countdown.num_seconds() > 8 && countdown.num_seconds() <= 10);
+
+    let (params, embed) = message.body::<TestTask>().unwrap().parts();
+    assert_eq!(params.a, 4);
+    assert_eq!(embed.chain.unwrap().len(), 1);
+    assert_eq!(embed.callbacks.unwrap()[0].task, "callback");
+    assert_eq!(embed.errbacks.unwrap()[0].task, "errback");
+}
+
+#[test]
+fn test_signature_links_to_message() {
+    let signature = || {
+        Signature::<TestTask>::new(TestTaskParams { a: 4 })
+            .link(Signature::<TestTask>::new(TestTaskParams { a: 5 }))
+            .link_error(Signature::<TestTask>::new(TestTaskParams { a: 6 }))
+    };
+
+    let raw = RawSignature::try_from(signature()).unwrap();
+    assert_eq!(raw.options["link"][0]["kwargs"], json!({"a": 5}));
+    assert_eq!(raw.options["link_error"][0]["kwargs"], json!({"a": 6}));
+
+    let message = Message::try_from(signature()).unwrap();
+    let (_, embed) = message.body::<TestTask>().unwrap().parts();
+    assert_eq!(embed.callbacks.unwrap()[0].kwargs["a"], 5);
+    assert_eq!(embed.errbacks.unwrap()[0].kwargs["a"], 6);
+}
+
+#[test]
+fn test_raw_params() {
+    let message = MessageBuilder::<DynamicTask>::new("aaa".into())
+        .task("tasks.add".into())
+        .content_type(MessageContentType::Yaml)
+        .args(vec![json!(1)])
+        .kwargs(json!({"y": 2}).as_object().unwrap().clone())
+        .chain(vec![RawSignature::new(
+            "test",
+            vec![],
+            serde_json::Map::new(),
+        )])
+        .build()
+        .unwrap();
+    assert_eq!(message.headers.task, "tasks.add");
+
+    let (params, embed) = message.raw_params().unwrap();
+    assert_eq!(params.args, vec![json!(1)]);
+    assert_eq!(params.kwargs["y"], 2);
+    assert_eq!(embed.chain.unwrap()[0].task, "test");
+
+    // The positional arguments of typed tasks are named.
+    let message = Message::try_from(Signature::<TestTask>::new(TestTaskParams { a: 4 })).unwrap();
+    let (params, _) = message.raw_params().unwrap();
+    assert!(params.args.is_empty());
+    assert_eq!(params.kwargs["a"], 4);
+}
+
+#[test]
+fn test_partial_signature_to_message() {
+    let signature = Signature::<TestTask>::partial(serde_json::Map::new());
+    assert!(matches!(
+        Message::try_from(signature),
+        Err(ProtocolError::PartialSignature(_))
+    ));
+}
+
+#[test]
+fn test_control_message_destination() {
+    let message: ControlMessage = serde_json::from_value(json!({
+        "method": "ping",
+        "arguments": {},
+        "destination": ["w1@host", "w2@host"],
+        "pattern": null,
+        "matcher": null,
+    }))
+    .unwrap();
+    assert!(message.is_for("w1@host"));
+    assert!(!message.is_for("w3@host"));
+
+    let message: ControlMessage = serde_json::from_value(json!({
+        "method": "ping",
+        "destination": null,
+        "pattern": "w*@host",
+        "matcher": "glob",
+    }))
+    .unwrap();
+    assert!(message.is_for("w1@host"));
+    assert!(!message.is_for("w1@other"));
+
+    let message: ControlMessage = serde_json::from_value(json!({"method": "ping"})).unwrap();
+    assert!(message.is_for("w1@host"));
+}
+
+#[test]
+fn test_event_serialization() {
+    let mut fields = Map::new();
+    fields.insert("uuid".into(), json!("aaa"));
+    let event = Event::new("task-succeeded", "w1@host", 3, fields);
+    assert_eq!(event.routing_key(), "task.succeeded");
+
+    let value = serde_json::to_value(&event).unwrap();
+    assert_eq!(value["type"], "task-succeeded");
+    assert_eq!(value["hostname"], "w1@host");
+    assert_eq!(value["clock"], 3);
+    assert_eq!(value["uuid"], "aaa");
+    assert_eq!(value["pid"], std::process::id());
+    assert!(value["timestamp"].is_f64());
+    assert_eq!(serde_json::from_value::<Event>(value).unwrap(), event);
+}
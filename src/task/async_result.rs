@@ -1,13 +1,232 @@
+use futures::stream::{self, BoxStream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map};
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+use super::TaskState;
+use crate::app::ControlSender;
+use crate::backend::{ResultBackend, TaskMeta};
+use crate::error::{BackendError, BrokerError, CeleryError, TaskError};
+
+/// The default interval between two polls of the result backend when waiting for a result.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
 /// An [`AsyncResult`] is a handle for the result of a task.
-#[derive(Debug, Clone)]
+///
+/// The methods for querying the state and the return value of the task require the
+/// [`Celery`](crate::Celery) app to be configured with a
+/// [`result_backend`](crate::CeleryBuilder::result_backend), otherwise they will return a
+/// [`BackendError::NotConfigured`] error.
+#[derive(Clone)]
 pub struct AsyncResult {
     pub task_id: String,
+
+    /// The result of the previous task, if the task is part of a chain.
+    pub parent: Option<Box<AsyncResult>>,
+
+    backend: Option<Arc<dyn ResultBackend>>,
+
+    /// Broadcasts remote control commands to the workers, to revoke the task.
+    control: Option<Arc<dyn ControlSender>>,
 }
 
 impl AsyncResult {
     pub fn new(task_id: &str) -> Self {
         Self {
             task_id: task_id.into(),
+            parent: None,
+            backend: None,
+            control: None,
+        }
+    }
+
+    /// Set the result of the previous task in a chain.
+    pub fn with_parent(mut self, parent: AsyncResult) -> Self {
+        self.parent = Some(Box::new(parent));
+        self
+    }
+
+    /// Set the result backend used to retrieve the result.
+    pub fn with_backend(mut self, backend: Arc<dyn ResultBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the mailbox used to revoke the task.
+    pub(crate) fn with_control(mut self, control: Arc<dyn ControlSender>) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    fn backend(&self) -> Result<&Arc<dyn ResultBackend>, BackendError> {
+        self.backend.as_ref().ok_or(BackendError::NotConfigured)
+    }
+
+    /// Get the meta data of the task that is currently stored in the result backend.
+    pub async fn meta(&self) -> Result<TaskMeta, BackendError> {
+        self.backend()?.get_task_meta(&self.task_id).await
+    }
+
+    /// Get the current state of the task.
+    pub async fn state(&self) -> Result<TaskState, BackendError> {
+        Ok(self.meta().await?.status)
+    }
+
+    /// Get the result stored for the task in its current state: the return value if it
+    /// succeeded, the error if it failed, or the meta data of a `STARTED` state or of a custom
+    /// state set through [`Task::update_state`](crate::task::Task::update_state).
+    pub async fn info(&self) -> Result<serde_json::Value, BackendError> {
+        Ok(self.meta().await?.result)
+    }
+
+    /// Check if the task has finished executing, successfully or not.
+    pub async fn ready(&self) -> Result<bool, BackendError> {
+        Ok(self.state().await?.is_ready())
+    }
+
+    /// Check if the task finished executing successfully.
+    pub async fn successful(&self) -> Result<bool, BackendError> {
+        Ok(self.state().await? == TaskState::Success)
+    }
+
+    /// Check if the task finished executing without success.
+    pub async fn failed(&self) -> Result<bool, BackendError> {
+        Ok(self.state().await?.is_exception())
+    }
+
+    /// Wait for the task to finish and get its return value, polling the result backend
+    /// every half second. Backends that are notified when a result is ready, like the
+    /// [`RedisBackend`](crate::backend::RedisBackend), don't poll.
+    ///
+    /// The return value is deserialized into `T`, which would typically be the
+    /// [`Returns`](crate::task::Task::Returns) type of the task. If the task failed, the error
+    /// is returned as a [`BackendError::TaskFailed`].
+    ///
+    /// If `timeout` is given and the task doesn't finish in time, a [`BackendError::Timeout`]
+    /// is returned.
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<T, BackendError> {
+        self.wait(timeout, DEFAULT_POLL_INTERVAL).await
+    }
+
+    /// Like [`AsyncResult::get`], but with a custom `interval` between two polls of the
+    /// result backend.
+    ///
+    /// Some result backends are notified when a result is ready, in which case `interval`
+    /// may be ignored.
+    pub async fn wait<T: DeserializeOwned>(
+        &self,
+        timeout: Option<Duration>,
+        interval: Duration,
+    ) -> Result<T, BackendError> {
+        let meta = self
+            .backend()?
+            .wait_for(&self.task_id, timeout, interval)
+            .await?;
+        match meta.status {
+            TaskState::Success => Ok(serde_json::from_value(meta.result)?),
+            TaskState::Revoked => Err(BackendError::TaskRevoked(self.task_id.clone())),
+            _ => Err(BackendError::TaskFailed(meta.error().unwrap_or_else(
+                || TaskError::UnexpectedError(format!("task ended in state {}", meta.status)),
+            ))),
+        }
+    }
+
+    /// Get a stream of the meta data of the task whenever its state changes, starting with
+    /// its current state and ending once the task is ready. This is useful to follow the
+    /// progress that a task reports through [`Task::update_state`](crate::task::Task::update_state):
+    ///
+    /// ```rust,no_run
+    /// # use celery::task::AsyncResult;
+    /// # use futures::StreamExt;
+    /// # async fn follow(result: AsyncResult) -> Result<(), celery::error::BackendError> {
+    /// let mut states = result.stream_states();
+    /// while let Some(meta) = states.next().await {
+    ///     let meta = meta?;
+    ///     println!("{}: {}", meta.status, meta.result);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Like [`AsyncResult::get`], this polls the result backend every half second unless
+    /// the backend is notified of new states.
+    pub fn stream_states(&self) -> BoxStream<'_, Result<TaskMeta, BackendError>> {
+        match self.backend() {
+            Ok(backend) => backend.stream_states(&self.task_id, DEFAULT_POLL_INTERVAL),
+            Err(e) => stream::once(async { Err(e) }).boxed(),
+        }
+    }
+
+    /// Remove the result of the task from the result backend.
+    pub async fn forget(&self) -> Result<(), BackendError> {
+        self.backend()?.forget(&self.task_id).await
+    }
+
+    /// Revoke the task, like Python's `AsyncResult.revoke`: a `revoke` command is broadcast to
+    /// all workers, which discard the task when they receive it, or when its ETA is reached.
+    /// The task is then stored as `REVOKED`, so [`AsyncResult::get`] returns a
+    /// [`BackendError::TaskRevoked`] error.
+    ///
+    /// If `terminate` is `true`, workers also terminate the task if it's already executing.
+    /// Tasks that run in a child process (see
+    /// [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork)) are killed, while blocking
+    /// functions that run on a thread pool keep running in the background.
+    ///
+    /// This requires the result to have been returned by a [`Celery`](crate::Celery) app,
+    /// otherwise a [`BrokerError::NotConnected`] error is returned.
+    pub async fn revoke(&self, terminate: bool) -> Result<(), CeleryError> {
+        let control = self.control.as_ref().ok_or(BrokerError::NotConnected)?;
+        let mut arguments = Map::new();
+        arguments.insert("task_id".into(), json!(self.task_id));
+        arguments.insert("terminate".into(), json!(terminate));
+        arguments.insert("signal".into(), json!(null));
+        control.broadcast("revoke", arguments).await
+    }
+
+    /// Convert the result to the tuple format of Python's `AsyncResult.as_tuple()`.
+    pub(crate) fn as_tuple(&self) -> ResultTuple {
+        ResultTuple(
+            (
+                self.task_id.clone(),
+                self.parent
+                    .as_ref()
+                    .map(|parent| Box::new(parent.as_tuple())),
+            ),
+            None,
+        )
+    }
+
+    /// Create a result from the tuple format of Python's `AsyncResult.as_tuple()`.
+    pub(crate) fn from_tuple(tuple: ResultTuple, backend: Option<Arc<dyn ResultBackend>>) -> Self {
+        let ResultTuple((task_id, parent), _) = tuple;
+        Self {
+            task_id,
+            parent: parent.map(|parent| Box::new(Self::from_tuple(*parent, backend.clone()))),
+            backend,
+            control: None,
         }
     }
 }
+
+/// The tuple format Python uses to serialize results, `((id, parent), children)`, where
+/// `parent` is the tuple of the parent result and `children` is only set for groups.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ResultTuple(
+    pub(crate) (String, Option<Box<ResultTuple>>),
+    pub(crate) Option<Vec<ResultTuple>>,
+);
+
+impl fmt::Debug for AsyncResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncResult")
+            .field("task_id", &self.task_id)
+            .field("parent", &self.parent)
+            .finish()
+    }
+}
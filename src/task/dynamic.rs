@@ -0,0 +1,102 @@
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+
+use super::{Request, Task, TaskOptions, TaskResult};
+use crate::error::TaskError;
+
+/// The function that executes a dynamic task, see
+/// [`Celery::register_task_handler`](crate::Celery::register_task_handler).
+pub(crate) type DynamicHandler = Arc<
+    dyn Fn(Vec<Value>, Map<String, Value>) -> BoxFuture<'static, TaskResult<Value>> + Send + Sync,
+>;
+
+/// A task that is only known by its name, with untyped parameters.
+///
+/// Workers execute dynamic tasks with the handlers registered through
+/// [`Celery::register_task_handler`](crate::Celery::register_task_handler), which receive the
+/// positional and keyword arguments of the task as raw JSON values. This is how a Rust worker
+/// can execute tasks sent by Python apps without defining a matching [`Task`] type.
+///
+/// Dynamic tasks are sent with [`Celery::send_task_by_name`](crate::Celery::send_task_by_name)
+/// or a [`RawSignature`](super::RawSignature). A `MessageBuilder::<DynamicTask>` can also be
+/// used to build messages for them, setting the name with
+/// [`MessageBuilder::task`](crate::protocol::MessageBuilder::task) and the arguments with
+/// [`MessageBuilder::args`](crate::protocol::MessageBuilder::args) and
+/// [`MessageBuilder::kwargs`](crate::protocol::MessageBuilder::kwargs).
+pub struct DynamicTask {
+    request: Request<Self>,
+    options: TaskOptions,
+    name: &'static str,
+    handler: Option<DynamicHandler>,
+}
+
+/// The parameters of a [`DynamicTask`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DynamicParams {
+    /// The positional arguments of the task.
+    pub args: Vec<Value>,
+
+    /// The keyword arguments of the task.
+    pub kwargs: Map<String, Value>,
+}
+
+impl DynamicTask {
+    pub(crate) fn with_handler(
+        request: Request<Self>,
+        options: TaskOptions,
+        name: &'static str,
+        handler: DynamicHandler,
+    ) -> Self {
+        Self {
+            request,
+            options,
+            name,
+            handler: Some(handler),
+        }
+    }
+}
+
+#[async_trait]
+impl Task for DynamicTask {
+    const NAME: &'static str = "dynamic";
+    const ARGS: &'static [&'static str] = &[];
+
+    type Params = DynamicParams;
+    type Returns = Value;
+
+    /// Create a dynamic task without a handler. Running it fails, since only the app knows
+    /// the handlers of dynamic tasks.
+    fn from_request(request: Request<Self>, options: TaskOptions) -> Self {
+        Self {
+            request,
+            options,
+            name: Self::NAME,
+            handler: None,
+        }
+    }
+
+    fn request(&self) -> &Request<Self> {
+        &self.request
+    }
+
+    fn options(&self) -> &TaskOptions {
+        &self.options
+    }
+
+    async fn run(&self, params: Self::Params) -> TaskResult<Self::Returns> {
+        match self.handler {
+            Some(ref handler) => handler(params.args, params.kwargs).await,
+            None => Err(TaskError::UnexpectedError(format!(
+                "no handler registered for task {}",
+                self.name
+            ))),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+}
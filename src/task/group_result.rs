@@ -0,0 +1,141 @@
+use futures::future;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use super::{AsyncResult, ResultTuple};
+use crate::backend::{GroupMeta, ResultBackend};
+use crate::error::BackendError;
+
+/// A [`GroupResult`] is a handle for the results of the tasks of a
+/// [`Group`](crate::canvas::Group).
+///
+/// Like for [`AsyncResult`], querying the results requires the [`Celery`](crate::Celery) app
+/// to be configured with a [`result_backend`](crate::CeleryBuilder::result_backend),
+/// otherwise a [`BackendError::NotConfigured`] error is returned.
+#[derive(Clone)]
+pub struct GroupResult {
+    /// The ID of the group.
+    pub id: String,
+
+    /// The results of the tasks of the group, in the order in which they were added.
+    pub results: Vec<AsyncResult>,
+
+    backend: Option<Arc<dyn ResultBackend>>,
+}
+
+impl GroupResult {
+    pub fn new(id: &str, results: Vec<AsyncResult>) -> Self {
+        Self {
+            id: id.into(),
+            results,
+            backend: None,
+        }
+    }
+
+    /// Set the result backend used to save and delete the group.
+    pub fn with_backend(mut self, backend: Arc<dyn ResultBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn backend(&self) -> Result<&Arc<dyn ResultBackend>, BackendError> {
+        self.backend.as_ref().ok_or(BackendError::NotConfigured)
+    }
+
+    /// Get the number of tasks of the group that finished executing successfully.
+    pub async fn completed_count(&self) -> Result<usize, BackendError> {
+        let successful =
+            future::try_join_all(self.results.iter().map(AsyncResult::successful)).await?;
+        Ok(successful
+            .into_iter()
+            .filter(|successful| *successful)
+            .count())
+    }
+
+    /// Check if all the tasks of the group have finished executing, successfully or not.
+    pub async fn ready(&self) -> Result<bool, BackendError> {
+        let ready = future::try_join_all(self.results.iter().map(AsyncResult::ready)).await?;
+        Ok(ready.into_iter().all(|ready| ready))
+    }
+
+    /// Check if all the tasks of the group finished executing successfully.
+    pub async fn successful(&self) -> Result<bool, BackendError> {
+        Ok(self.completed_count().await? == self.results.len())
+    }
+
+    /// Check if any task of the group finished executing without success.
+    pub async fn failed(&self) -> Result<bool, BackendError> {
+        let failed = future::try_join_all(self.results.iter().map(AsyncResult::failed)).await?;
+        Ok(failed.into_iter().any(|failed| failed))
+    }
+
+    /// Wait for all the tasks of the group to finish and get their return values, in the
+    /// order in which the tasks were added to the group.
+    ///
+    /// If any task failed, its error is returned as a [`BackendError::TaskFailed`]. If
+    /// `timeout` is given and the tasks don't all finish in time, a
+    /// [`BackendError::Timeout`] is returned.
+    pub async fn join<T: DeserializeOwned>(
+        &self,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<T>, BackendError> {
+        let join = future::try_join_all(self.results.iter().map(|result| result.get(None)));
+        match timeout {
+            Some(timeout) => time::timeout(timeout, join)
+                .await
+                .map_err(|_| BackendError::Timeout)?,
+            None => join.await,
+        }
+    }
+
+    /// Save the group to the result backend, so that it can be restored later with
+    /// [`Celery::restore_group`](crate::Celery::restore_group), possibly by another app.
+    pub async fn save(&self) -> Result<(), BackendError> {
+        let meta = GroupMeta::new(&self.id, serde_json::to_value(self.as_tuple())?);
+        self.backend()?.save_group(&meta).await
+    }
+
+    /// Remove the saved group from the result backend. The results of the tasks are kept.
+    pub async fn delete(&self) -> Result<(), BackendError> {
+        self.backend()?.delete_group(&self.id).await
+    }
+
+    /// Remove the results of all the tasks of the group from the result backend.
+    pub async fn forget(&self) -> Result<(), BackendError> {
+        future::try_join_all(self.results.iter().map(AsyncResult::forget)).await?;
+        Ok(())
+    }
+
+    /// Convert the group to the tuple format of Python's `GroupResult.as_tuple()`.
+    pub(crate) fn as_tuple(&self) -> ResultTuple {
+        ResultTuple(
+            (self.id.clone(), None),
+            Some(self.results.iter().map(AsyncResult::as_tuple).collect()),
+        )
+    }
+
+    /// Create a group from the tuple format of Python's `GroupResult.as_tuple()`.
+    pub(crate) fn from_tuple(tuple: ResultTuple, backend: Option<Arc<dyn ResultBackend>>) -> Self {
+        let ResultTuple((id, _), results) = tuple;
+        Self {
+            id,
+            results: results
+                .unwrap_or_default()
+                .into_iter()
+                .map(|result| AsyncResult::from_tuple(result, backend.clone()))
+                .collect(),
+            backend,
+        }
+    }
+}
+
+impl fmt::Debug for GroupResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GroupResult")
+            .field("id", &self.id)
+            .field("results", &self.results)
+            .finish()
+    }
+}
@@ -4,31 +4,42 @@ use async_trait::async_trait;
 use chrono::{DateTime, NaiveDateTime, Utc};
 use rand::distributions::{Distribution, Uniform};
 use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::error::TaskError;
+use crate::backend::TaskMeta;
+use crate::error::{BackendError, TaskError};
 
 mod async_result;
+mod dynamic;
+mod group_result;
 mod options;
 mod request;
 mod signature;
+mod state;
 
 pub use async_result::AsyncResult;
-pub use options::TaskOptions;
+pub(crate) use async_result::ResultTuple;
+pub(crate) use dynamic::DynamicHandler;
+pub use dynamic::{DynamicParams, DynamicTask};
+pub use group_result::GroupResult;
+pub use options::{RateLimit, TaskOptions};
 pub use request::Request;
-pub use signature::Signature;
+pub(crate) use signature::{signature_list, SignatureParams};
+pub use signature::{RawSignature, Signature};
+pub use state::TaskState;
 
 /// The return type for a task.
 pub type TaskResult<R> = Result<R, TaskError>;
 
 #[doc(hidden)]
 pub trait AsTaskResult {
-    type Returns: Send + Sync + std::fmt::Debug;
+    type Returns: Send + Sync + std::fmt::Debug + Serialize;
 }
 
 impl<R> AsTaskResult for TaskResult<R>
 where
-    R: Send + Sync + std::fmt::Debug,
+    R: Send + Sync + std::fmt::Debug + Serialize,
 {
     type Returns = R;
 }
@@ -48,6 +59,12 @@ pub trait Task: Send + Sync + std::marker::Sized {
     /// positional arguments.
     const ARGS: &'static [&'static str];
 
+    /// Whether the task runs its function with [`run_blocking`](Task::run_blocking), like
+    /// tasks defined with `blocking = true` in the [`task`](macro@crate::task) attribute
+    /// macro. Workers wait for a thread of the blocking pool for these tasks before their
+    /// time limit starts.
+    const BLOCKING: bool = false;
+
     /// Default task options.
     const DEFAULTS: TaskOptions = TaskOptions {
         time_limit: None,
@@ -57,6 +74,9 @@ pub trait Task: Send + Sync + std::marker::Sized {
         max_retry_delay: None,
         retry_for_unexpected: None,
         acks_late: None,
+        ignore_result: None,
+        track_started: None,
+        rate_limit: None,
         content_type: None,
     };
 
@@ -64,7 +84,10 @@ pub trait Task: Send + Sync + std::marker::Sized {
     type Params: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de>;
 
     /// The return type of the task.
-    type Returns: Send + Sync + std::fmt::Debug;
+    ///
+    /// This needs to be serializable so that it can be stored in a
+    /// [`ResultBackend`](crate::backend::ResultBackend).
+    type Returns: Send + Sync + std::fmt::Debug + Serialize;
 
     /// Used to initialize a task instance from a request.
     fn from_request(request: Request<Self>, options: TaskOptions) -> Self;
@@ -128,6 +151,59 @@ pub trait Task: Send + Sync + std::marker::Sized {
         Err(TaskError::Retry(Some(eta)))
     }
 
+    /// This can be called from within a bound task to replace it with a signature, a chain
+    /// or a [`Chord`](crate::canvas::Chord), like Python's `Task.replace`. The replacement
+    /// takes over the ID of the task, so its result becomes the result of the task, as well
+    /// as its place in its chain, group and chord, and its callbacks and errbacks:
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// #[celery::task]
+    /// fn add(x: i32, y: i32) -> TaskResult<i32> {
+    ///     Ok(x + y)
+    /// }
+    ///
+    /// #[celery::task]
+    /// fn sum(numbers: Vec<i32>) -> TaskResult<i32> {
+    ///     Ok(numbers.iter().sum())
+    /// }
+    ///
+    /// #[celery::task(bind = true)]
+    /// fn sum_pairs(task: &Self, pairs: Vec<(i32, i32)>) -> TaskResult<i32> {
+    ///     if pairs.len() < 100 {
+    ///         return Ok(pairs.iter().map(|(x, y)| x + y).sum());
+    ///     }
+    ///     // Too much work for a single task: add the pairs in parallel instead.
+    ///     let header = celery::canvas::group(pairs.into_iter().map(|(x, y)| add::new(x, y)));
+    ///     task.replace(celery::canvas::chord(header, sum::partial()))
+    /// }
+    /// ```
+    ///
+    /// The task returns a [`TaskError::Replaced`] error, which makes the worker send the
+    /// replacement instead of storing a result. The callbacks and errbacks of the task, and
+    /// the options that tie it to a group or a chord, are set on the task whose result is the
+    /// result of the replacement: the last task of a chain or the body of a chord.
+    ///
+    /// Tasks that run in place, like the items of `celery.map`, or in a child process, see
+    /// [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork), can't be replaced and fail
+    /// with an [`UnexpectedError`](TaskError::UnexpectedError) instead.
+    fn replace<S>(&self, signature: S) -> TaskResult<Self::Returns>
+    where
+        S: TryInto<RawSignature>,
+        S::Error: std::fmt::Display,
+    {
+        let replacement = self.request().replacement.as_ref().ok_or_else(|| {
+            TaskError::UnexpectedError(
+                "tasks can't be replaced when they run in place or in a child process".into(),
+            )
+        })?;
+        let signature = signature.try_into().map_err(|e| {
+            TaskError::UnexpectedError(format!("invalid replacement signature: {}", e))
+        })?;
+        *replacement.lock().unwrap() = Some(signature);
+        Err(TaskError::Replaced)
+    }
+
     /// Get a future ETA at which time the task should be retried. By default this
     /// uses a capped exponential backoff strategy.
     fn retry_eta(&self) -> Option<DateTime<Utc>> {
@@ -163,6 +239,13 @@ pub trait Task: Send + Sync + std::marker::Sized {
             .unwrap_or(true)
     }
 
+    fn hard_time_limit(&self) -> Option<u32> {
+        self.request()
+            .hard_time_limit
+            .or(Self::DEFAULTS.hard_time_limit)
+            .or(self.options().hard_time_limit)
+    }
+
     fn time_limit(&self) -> Option<u32> {
         self.request().time_limit.or_else(|| {
             // Take min or `time_limit` and `hard_time_limit`.
@@ -203,6 +286,106 @@ pub trait Task: Send + Sync + std::marker::Sized {
             .or(self.options().acks_late)
             .unwrap_or(false)
     }
+
+    fn ignore_result(&self) -> bool {
+        Self::DEFAULTS
+            .ignore_result
+            .or(self.options().ignore_result)
+            .unwrap_or(false)
+    }
+
+    fn track_started(&self) -> bool {
+        Self::DEFAULTS
+            .track_started
+            .or(self.options().track_started)
+            .unwrap_or(false)
+    }
+
+    fn rate_limit(&self) -> Option<RateLimit> {
+        Self::DEFAULTS.rate_limit.or(self.options().rate_limit)
+    }
+
+    /// Run a blocking function, like CPU-bound work or blocking IO, on the blocking thread
+    /// pool of the worker so that it doesn't stall the other tasks. The number of blocking
+    /// functions that run at the same time is limited by
+    /// [`CeleryBuilder::blocking_pool_size`](crate::CeleryBuilder::blocking_pool_size).
+    ///
+    /// Tasks defined with `blocking = true` in the [`task`](macro@crate::task) attribute
+    /// macro run their whole body this way:
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// #[celery::task(blocking = true, time_limit = 10)]
+    /// fn fibonacci(n: u64) -> TaskResult<u64> {
+    ///     Ok(if n < 2 { n } else { (1..n).fold((0, 1), |(a, b), _| (b, a + b)).1 })
+    /// }
+    /// ```
+    ///
+    /// The time limit of such tasks, and of other tasks that set [`BLOCKING`](Task::BLOCKING),
+    /// only starts once a thread of the pool is available. If the task runs over its time
+    /// limit, it fails with a [`TimeoutError`](TaskError::TimeoutError) as usual, but the
+    /// function keeps running on its thread until it returns since threads can't be stopped.
+    /// If the function panics, the task fails with an
+    /// [`UnexpectedError`](TaskError::UnexpectedError).
+    async fn run_blocking<F, R>(&self, f: F) -> TaskResult<R>
+    where
+        F: FnOnce() -> TaskResult<R> + Send + 'static,
+        R: Send + 'static,
+    {
+        let request = self.request();
+        let reserved = request.blocking_permit.lock().unwrap().take();
+        let permit = match (reserved, &request.blocking_permits) {
+            (Some(permit), _) => Some(permit),
+            (None, Some(permits)) => permits.clone().acquire_owned().await.ok(),
+            (None, None) => None,
+        };
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f()
+        })
+        .await
+        .unwrap_or_else(|e| Err(TaskError::UnexpectedError(format!("blocking task {}", e))))
+    }
+
+    /// This can be called from within a task function to store a custom state in the result
+    /// backend, for example to report progress:
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// # use celery::task::TaskState;
+    /// # use serde_json::json;
+    /// #[celery::task(bind = true)]
+    /// async fn generate_report(task: &Self, pages: u32) -> TaskResult<()> {
+    ///     for page in 0..pages {
+    ///         // ...
+    ///         task.update_state(
+    ///             TaskState::Custom("PROGRESS".into()),
+    ///             json!({ "current": page + 1, "total": pages }),
+    ///         )
+    ///         .await
+    ///         .ok();
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    ///
+    /// Producers can read the state and the meta data through
+    /// [`AsyncResult::state`] and [`AsyncResult::info`].
+    ///
+    /// This returns a [`BackendError::NotConfigured`] if the app doesn't have a result backend.
+    async fn update_state<M>(&self, state: TaskState, meta: M) -> Result<(), BackendError>
+    where
+        M: Serialize + Send + 'static,
+    {
+        let request = self.request();
+        let backend = request
+            .backend
+            .as_ref()
+            .ok_or(BackendError::NotConfigured)?;
+        let mut task_meta = TaskMeta::with_status(&request.id, state, serde_json::to_value(meta)?);
+        task_meta.reply_to = request.reply_to.clone();
+        backend.store_result(&task_meta).await
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -1,3 +1,7 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::CeleryError;
 use crate::protocol::MessageContentType;
 
 /// Configuration options pertaining to a task.
@@ -39,9 +43,12 @@ pub struct TaskOptions {
     /// option when sending tasks to a Python consumer.
     /// If you desire to set a "hard time limit", use this option.
     ///
-    /// *Note that this is really only for compatability with Python workers*.
+    /// *Note that this is mostly for compatability with Python workers*.
     /// `time_limit` and `hard_time_limit` are treated the same by Rust workers, and if both
-    /// are set, the minimum of the two will be used.
+    /// are set, the minimum of the two will be used. Only workers that run tasks in child
+    /// processes (see [`prefork`](crate::CeleryBuilder::prefork)) enforce the hard time limit
+    /// by killing the child, failing the task with a
+    /// [`WorkerLostError`](crate::error::TaskError::WorkerLostError).
     ///
     /// This can be set with
     /// - [`task_hard_time_limit`](crate::CeleryBuilder::task_hard_time_limit) at the app level,
@@ -104,6 +111,43 @@ pub struct TaskOptions {
     /// If this option is left unspecified, the default behavior will be to ack early.
     pub acks_late: Option<bool>,
 
+    /// Whether or not to skip storing the state and return value of the task in the
+    /// [result backend](crate::backend::ResultBackend).
+    ///
+    /// This can be set with
+    /// - [`task_ignore_result`](crate::CeleryBuilder::task_ignore_result) at the app level, and
+    /// - [`ignore_result`](../attr.task.html#parameters) at the task level.
+    ///
+    /// If this option is left unspecified, the default behavior will be to store results
+    /// when the app has a result backend.
+    pub ignore_result: Option<bool>,
+
+    /// Whether or not to store a `STARTED` state in the
+    /// [result backend](crate::backend::ResultBackend) when the task starts executing.
+    ///
+    /// This can be set with
+    /// - [`task_track_started`](crate::CeleryBuilder::task_track_started) at the app level, and
+    /// - [`track_started`](../attr.task.html#parameters) at the task level.
+    ///
+    /// If this option is left unspecified, the default behavior will be to not track when
+    /// tasks start, like Python.
+    pub track_started: Option<bool>,
+
+    /// The maximum number of tasks of this type that each worker starts per second, minute
+    /// or hour, written like Python's rate limits: `"10/s"`, `"100/m"` or `"1000/h"`.
+    ///
+    /// Workers hold back the messages of tasks that exceed their rate limit, without
+    /// acknowledging them, until they are allowed to run. The rate limit of a task can be
+    /// changed at runtime with the `rate_limit` remote control command.
+    ///
+    /// This can be set with
+    /// - [`task_rate_limit`](crate::CeleryBuilder::task_rate_limit) at the app level, and
+    /// - [`rate_limit`](../attr.task.html#parameters) at the task level.
+    ///
+    /// If this option is left unspecified, the default behavior will be to not limit the rate
+    /// of tasks.
+    pub rate_limit: Option<RateLimit>,
+
     /// Which serialization format to use for task messages.
     ///
     /// This can be set with
@@ -123,6 +167,9 @@ impl TaskOptions {
         self.max_retry_delay = self.max_retry_delay.or(other.max_retry_delay);
         self.retry_for_unexpected = self.retry_for_unexpected.or(other.retry_for_unexpected);
         self.acks_late = self.acks_late.or(other.acks_late);
+        self.ignore_result = self.ignore_result.or(other.ignore_result);
+        self.track_started = self.track_started.or(other.track_started);
+        self.rate_limit = self.rate_limit.or(other.rate_limit);
         self.content_type = self.content_type.or(other.content_type);
     }
 
@@ -132,6 +179,70 @@ impl TaskOptions {
     }
 }
 
+/// A rate limit of tasks, see [`TaskOptions::rate_limit`].
+///
+/// It's parsed from Python's syntax: a number of tasks followed by `/s`, `/m` or `/h`, or
+/// by nothing for a number of tasks per second. A rate of `0` means there is no limit.
+///
+/// ```rust
+/// # use celery::task::RateLimit;
+/// let rate_limit: RateLimit = "100/m".parse().unwrap();
+/// assert_eq!(rate_limit, RateLimit { tasks: 100.0, period: 60 });
+/// assert_eq!(rate_limit.to_string(), "100/m");
+/// ```
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    /// The number of tasks per period.
+    pub tasks: f64,
+
+    /// The period, in seconds.
+    pub period: u32,
+}
+
+impl RateLimit {
+    /// The number of tasks per second, or `None` if there is no limit.
+    pub fn per_second(&self) -> Option<f64> {
+        if self.tasks > 0.0 && self.period > 0 {
+            Some(self.tasks / self.period as f64)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = CeleryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || CeleryError::InvalidRateLimit(s.into());
+        let (tasks, modifier) = match s.split_once('/') {
+            Some((tasks, modifier)) => (tasks, modifier),
+            None => (s, "s"),
+        };
+        let period = match modifier {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            _ => return Err(invalid()),
+        };
+        match tasks.trim().parse::<f64>() {
+            Ok(tasks) if tasks.is_finite() && tasks >= 0.0 => Ok(Self { tasks, period }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.period {
+            1 => write!(f, "{}/s", self.tasks),
+            60 => write!(f, "{}/m", self.tasks),
+            3600 => write!(f, "{}/h", self.tasks),
+            period => write!(f, "{}/s", self.tasks / period as f64),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,4 +266,22 @@ mod tests {
         assert_eq!(options.max_retries, Some(3));
         assert_eq!(options.acks_late, Some(true));
     }
+
+    #[test]
+    fn test_rate_limit() {
+        let rate_limit: RateLimit = "10/s".parse().unwrap();
+        assert_eq!(rate_limit.per_second(), Some(10.0));
+        let rate_limit: RateLimit = "120/m".parse().unwrap();
+        assert_eq!(rate_limit.per_second(), Some(2.0));
+        assert_eq!(rate_limit.to_string(), "120/m");
+        let rate_limit: RateLimit = "3600/h".parse().unwrap();
+        assert_eq!(rate_limit.per_second(), Some(1.0));
+        let rate_limit: RateLimit = "0.5".parse().unwrap();
+        assert_eq!(rate_limit.per_second(), Some(0.5));
+        let rate_limit: RateLimit = "0".parse().unwrap();
+        assert_eq!(rate_limit.per_second(), None);
+        assert!("10/d".parse::<RateLimit>().is_err());
+        assert!("-1/s".parse::<RateLimit>().is_err());
+        assert!("fast".parse::<RateLimit>().is_err());
+    }
 }
@@ -1,9 +1,13 @@
-use super::Task;
+use super::{RawSignature, Task};
+use crate::app::TaskSender;
+use crate::backend::ResultBackend;
 use crate::error::ProtocolError;
-use crate::protocol::Message;
+use crate::protocol::{Message, MessageBodyEmbed};
 use chrono::{DateTime, Utc};
 use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::time::Duration;
 
 /// A [`Request`] contains information and state related to the currently executing task.
@@ -15,11 +19,21 @@ where
     /// The unique ID of the executing task.
     pub id: String,
 
+    /// The unique ID of the first task in the work-flow this task is part of.
+    pub root_id: Option<String>,
+
+    /// The unique ID of the task that triggered this task within a work-flow.
+    pub parent_id: Option<String>,
+
     /// The unique ID of the task's group, if this task is a member.
     pub group: Option<String>,
 
-    /// The unique ID of the chord this task belongs to (if the task is part of the header).
-    pub chord: Option<String>,
+    /// The position of the task in its group.
+    pub group_index: Option<u32>,
+
+    /// The signature of the callback of the chord this task belongs to, if the task is part
+    /// of the header.
+    pub chord: Option<RawSignature>,
 
     /// Custom ID used for things like de-duplication. Usually the same as `id`.
     pub correlation_id: String,
@@ -45,8 +59,44 @@ where
     /// Where to send reply to (queue name).
     pub reply_to: Option<String>,
 
+    /// Name of the queue the task was consumed from.
+    pub queue: Option<String>,
+
     /// The time limit (in seconds) allocated for this task to execute.
     pub time_limit: Option<u32>,
+
+    /// The hard time limit (in seconds) of the task, after which it's killed when it runs in
+    /// a child process, see [`CeleryBuilder::prefork`](crate::CeleryBuilder::prefork).
+    pub hard_time_limit: Option<u32>,
+
+    /// The signatures of the remaining tasks of the chain this task is part of, in reverse
+    /// order: the next task is the last one.
+    pub chain: Vec<RawSignature>,
+
+    /// The signatures of the tasks to send with the return value of this task when it
+    /// succeeds.
+    pub callbacks: Vec<RawSignature>,
+
+    /// The signatures of the tasks to send with the ID of this task when it fails for good.
+    pub errbacks: Vec<RawSignature>,
+
+    /// The result backend of the app executing the task.
+    pub(crate) backend: Option<Arc<dyn ResultBackend>>,
+
+    /// Sends tasks through the app executing the task.
+    pub(crate) sender: Option<Arc<dyn TaskSender>>,
+
+    /// Limits the number of blocking functions of tasks that run at the same time, see
+    /// [`Task::run_blocking`].
+    pub(crate) blocking_permits: Option<Arc<Semaphore>>,
+
+    /// The permit that the worker reserved for a [`BLOCKING`](Task::BLOCKING) task before
+    /// its time limit started, which is used by its first call to [`Task::run_blocking`].
+    pub(crate) blocking_permit: Arc<Mutex<Option<OwnedSemaphorePermit>>>,
+
+    /// Where [`Task::replace`] keeps the replacement of the task, if the task can be
+    /// replaced where it runs.
+    pub(crate) replacement: Option<Arc<Mutex<Option<RawSignature>>>>,
 }
 
 impl<T> Request<T>
@@ -64,7 +114,10 @@ where
         };
         Self {
             id: m.headers.id,
+            root_id: m.headers.root_id,
+            parent_id: m.headers.parent_id,
             group: m.headers.group,
+            group_index: m.headers.group_index,
             chord: None,
             correlation_id: m.properties.correlation_id,
             params: p,
@@ -74,10 +127,33 @@ where
             expires: m.headers.expires,
             hostname: None,
             reply_to: m.properties.reply_to,
+            queue: None,
             time_limit,
+            hard_time_limit: m.headers.timelimit.0,
+            chain: vec![],
+            callbacks: vec![],
+            errbacks: vec![],
+            backend: None,
+            sender: None,
+            blocking_permits: None,
+            blocking_permit: Arc::default(),
+            replacement: None,
         }
     }
 
+    /// Take the signature that the task replaced itself with, if any.
+    pub(crate) fn take_replacement(&self) -> Option<RawSignature> {
+        self.replacement.as_ref()?.lock().unwrap().take()
+    }
+
+    /// Set the work-flow primitives of the request from the body of its message.
+    pub(crate) fn set_embed(&mut self, embed: MessageBodyEmbed) {
+        self.chain = embed.chain.unwrap_or_default();
+        self.chord = embed.chord;
+        self.callbacks = embed.callbacks.unwrap_or_default();
+        self.errbacks = embed.errbacks.unwrap_or_default();
+    }
+
     /// Check if the request has a future ETA.
     pub fn is_delayed(&self) -> bool {
         self.eta.is_some()
@@ -117,7 +193,9 @@ where
 
     fn try_from(m: Message) -> Result<Self, Self::Error> {
         let body = m.body::<T>()?;
-        let (task_params, _) = body.parts();
-        Ok(Self::new(m, task_params))
+        let (task_params, embed) = body.parts();
+        let mut request = Self::new(m, task_params);
+        request.set_embed(embed);
+        Ok(request)
     }
 }
@@ -1,12 +1,17 @@
 use super::{Task, TaskOptions};
+use crate::error::ProtocolError;
 use crate::protocol::MessageContentType;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
 
 /// Wraps the parameters and execution options for a single task invocation.
 ///
 /// When you define a task through the [`task`](macro@crate::task) attribute macro, calling
 /// `T::new(...)` with the arguments that your task function take will create a
-/// [`Signature<T>`](Signature).
+/// [`Signature<T>`](Signature). Calling `T::partial(...)` with all the arguments but the first
+/// one creates a [partial](Signature::partial) signature, to be used as a link of a chain.
 ///
 /// # Examples
 ///
@@ -26,7 +31,7 @@ where
     T: Task,
 {
     /// The parameters for the task invocation.
-    pub(crate) params: T::Params,
+    pub(crate) params: SignatureParams<T::Params>,
 
     /// A queue to send the task to.
     pub(crate) queue: Option<String>,
@@ -50,6 +55,30 @@ where
 
     /// Additional options.
     pub(crate) options: TaskOptions,
+
+    /// The signatures of the tasks to run after this one, in order.
+    pub(crate) chain: Vec<RawSignature>,
+
+    /// The signatures of the tasks to send with the return value of this task.
+    pub(crate) callbacks: Vec<RawSignature>,
+
+    /// The signatures of the tasks to send if this task fails.
+    pub(crate) errbacks: Vec<RawSignature>,
+
+    /// If the signature is immutable, the return value of the previous task isn't passed to
+    /// it.
+    pub(crate) immutable: bool,
+}
+
+/// The parameters of a [`Signature`].
+#[derive(Clone)]
+pub(crate) enum SignatureParams<P> {
+    /// All the parameters of the task.
+    Params(P),
+
+    /// The serialized parameters of a partial signature, which are all the parameters of the
+    /// task but the first one.
+    Partial(Map<String, Value>),
 }
 
 impl<T> Signature<T>
@@ -58,6 +87,41 @@ where
 {
     /// Create a new `Signature` from task parameters.
     pub fn new(params: T::Params) -> Self {
+        Self::with_params(SignatureParams::Params(params))
+    }
+
+    /// Create a new partial `Signature` from some of the parameters of the task, serialized
+    /// by name. When the signature is a link of a chain (see [`Signature::then`]) or a
+    /// callback (see [`Signature::link`]), the first parameter that is left out is filled in
+    /// with the return value of the previous task.
+    ///
+    /// The [`task`](macro@crate::task) attribute macro generates a typed `T::partial(...)`
+    /// constructor that takes all the parameters but the first one. Calling this directly
+    /// lets you choose which parameter receives the return value of the previous task:
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// # use celery::task::Signature;
+    /// # use serde_json::json;
+    /// # #[celery::task]
+    /// # fn add(x: i32, y: i32) -> TaskResult<i32> {
+    /// #     Ok(x + y)
+    /// # }
+    /// #[celery::task]
+    /// fn pow(base: i32, exponent: u32) -> TaskResult<i32> {
+    ///     Ok(base.pow(exponent))
+    /// }
+    ///
+    /// // Computes 2 ^ (1 + 2).
+    /// let params = json!({ "base": 2 }).as_object().unwrap().clone();
+    /// let signature = add::new(1, 2).then(Signature::<pow>::partial(params));
+    /// assert_eq!(Signature::<pow>::partial(Default::default()).upstream_param(), Some("base"));
+    /// ```
+    pub fn partial(params: Map<String, Value>) -> Self {
+        Self::with_params(SignatureParams::Partial(params))
+    }
+
+    fn with_params(params: SignatureParams<T::Params>) -> Self {
         Self {
             params,
             queue: None,
@@ -66,6 +130,10 @@ where
             expires_in: None,
             expires: None,
             options: T::DEFAULTS,
+            chain: vec![],
+            callbacks: vec![],
+            errbacks: vec![],
+            immutable: false,
         }
     }
 
@@ -74,6 +142,112 @@ where
         T::NAME
     }
 
+    /// Make the signature immutable, like Python's `.si()`: the return value of the
+    /// previous task isn't passed to it when it is a link of a chain or a callback, so it
+    /// runs with its own parameters only.
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// # #[celery::task]
+    /// # fn add(x: i32, y: i32) -> TaskResult<i32> {
+    /// #     Ok(x + y)
+    /// # }
+    /// // Runs add(5, 6) after add(1, 2), ignoring its result.
+    /// let signature = add::new(1, 2).then(add::new(5, 6).immutable());
+    /// ```
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Get the name of the parameter that receives the return value of the previous task
+    /// when the signature is a link of a chain or a callback: the first parameter that a
+    /// [partial](Signature::partial) signature leaves out. Returns `None` if the signature
+    /// is [immutable](Signature::immutable) or has all its parameters.
+    pub fn upstream_param(&self) -> Option<&'static str> {
+        match self.params {
+            SignatureParams::Partial(ref params) if !self.immutable => T::ARGS
+                .iter()
+                .find(|arg_name| !params.contains_key(**arg_name))
+                .copied(),
+            _ => None,
+        }
+    }
+
+    /// Run `next` after this task succeeds, passing the return value of this task to the
+    /// [upstream parameter](Signature::upstream_param) of `next`. This is usually used with
+    /// a [partial](Signature::partial) signature:
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// # #[celery::task]
+    /// # fn add(x: i32, y: i32) -> TaskResult<i32> {
+    /// #     Ok(x + y)
+    /// # }
+    /// # #[celery::task]
+    /// # fn mul(x: i32, y: i32) -> TaskResult<i32> {
+    /// #     Ok(x * y)
+    /// # }
+    /// // Computes (1 + 2) * 3.
+    /// let signature = add::new(1, 2).then(mul::partial(3));
+    /// ```
+    ///
+    /// The chain is sent along with the task in the same format as Python chains, so the
+    /// tasks of a chain can be implemented in either language.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `next` can't be serialized to JSON.
+    pub fn then<U: Task>(mut self, mut next: Signature<U>) -> Self {
+        let rest = std::mem::take(&mut next.chain);
+        self.chain
+            .push(RawSignature::try_from(next).expect("invalid task parameters"));
+        self.chain.extend(rest);
+        self
+    }
+
+    /// Send `callback` with the return value of this task as its
+    /// [upstream parameter](Signature::upstream_param) when this task succeeds, like
+    /// Python's `link`. Unlike with [`then`](Signature::then), the
+    /// callback doesn't become part of the work-flow: the result of the signature is still
+    /// the result of this task, and a task can have several callbacks.
+    ///
+    /// ```rust
+    /// # use celery::prelude::*;
+    /// # #[celery::task]
+    /// # fn add(x: i32, y: i32) -> TaskResult<i32> {
+    /// #     Ok(x + y)
+    /// # }
+    /// # #[celery::task]
+    /// # fn notify(sum: i32, channel: String) -> TaskResult<()> {
+    /// #     Ok(())
+    /// # }
+    /// let signature = add::new(1, 2).link(notify::partial("sums".into()));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `callback` can't be serialized to JSON.
+    pub fn link<U: Task>(mut self, callback: Signature<U>) -> Self {
+        self.callbacks
+            .push(RawSignature::try_from(callback).expect("invalid task parameters"));
+        self
+    }
+
+    /// Send `errback` when this task fails and won't be retried anymore, like Python's
+    /// `link_error`. The ID of the task is passed as the first parameter of `errback`, which
+    /// can get the error of the task from the result backend with an
+    /// [`AsyncResult`](crate::task::AsyncResult).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters of `errback` can't be serialized to JSON.
+    pub fn link_error<U: Task>(mut self, errback: Signature<U>) -> Self {
+        self.errbacks
+            .push(RawSignature::try_from(errback).expect("invalid task parameters"));
+        self
+    }
+
     /// Set the queue.
     pub fn with_queue(mut self, queue: &str) -> Self {
         self.queue = Some(queue.into());
@@ -126,3 +300,192 @@ where
         self
     }
 }
+
+/// An untyped signature in the format that Python Celery uses to serialize signatures,
+/// for instance in the chains of task messages.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct RawSignature {
+    /// The name of the task.
+    pub task: String,
+
+    /// The positional arguments of the task.
+    #[serde(default)]
+    pub args: Vec<Value>,
+
+    /// The keyword arguments of the task.
+    #[serde(default)]
+    pub kwargs: Map<String, Value>,
+
+    /// Options to send the task with, such as `queue`, `countdown`, `eta`, `expires` or
+    /// `task_id`, named like the arguments of Python's `apply_async`.
+    #[serde(default)]
+    pub options: Map<String, Value>,
+
+    /// The type of work-flow primitive the signature stands for, if any.
+    #[serde(default)]
+    pub subtask_type: Option<String>,
+
+    /// If the signature is immutable, the return value of the previous task isn't passed to
+    /// it.
+    #[serde(default)]
+    pub immutable: bool,
+
+    /// The number of tasks in the header of a chord.
+    #[serde(default)]
+    pub chord_size: Option<usize>,
+}
+
+impl RawSignature {
+    /// Create a new `RawSignature` for the task with the given name.
+    pub fn new(task: &str, args: Vec<Value>, kwargs: Map<String, Value>) -> Self {
+        Self {
+            task: task.into(),
+            args,
+            kwargs,
+            options: Map::new(),
+            subtask_type: None,
+            immutable: false,
+            chord_size: None,
+        }
+    }
+
+    /// Get an option as a string.
+    pub(crate) fn option_str(&self, name: &str) -> Option<&str> {
+        self.options.get(name).and_then(Value::as_str)
+    }
+
+    /// Get the ID the task will be sent with, generating one if it isn't set yet.
+    pub(crate) fn task_id(&mut self) -> Result<String, ProtocolError> {
+        self.options
+            .entry("task_id")
+            .or_insert_with(|| uuid::Uuid::new_v4().to_string().into())
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| ProtocolError::InvalidProperty("task_id".into()))
+    }
+
+    /// Set the IDs of the tasks of the chain that runs after this task, if any, and return
+    /// them in the order in which the tasks run.
+    pub(crate) fn freeze_chain(&mut self) -> Result<Vec<String>, ProtocolError> {
+        let mut chain: Vec<RawSignature> = match self.options.get("chain") {
+            Some(chain) => serde_json::from_value(chain.clone())?,
+            None => return Ok(vec![]),
+        };
+        // The chain is stored in reverse order.
+        let ids = chain
+            .iter_mut()
+            .rev()
+            .map(RawSignature::task_id)
+            .collect::<Result<_, _>>()?;
+        self.options
+            .insert("chain".into(), serde_json::to_value(chain)?);
+        Ok(ids)
+    }
+
+    /// Set an option of the task whose result is the result of the signature: the last task
+    /// of its chain if it has one, or the task itself otherwise. This is how work-flow options
+    /// like `group_id` or `chord` are set on chains, like in Python.
+    pub(crate) fn set_last_option(
+        &mut self,
+        name: &str,
+        value: Value,
+    ) -> Result<(), ProtocolError> {
+        self.update_last(|last| {
+            last.options.insert(name.into(), value);
+            Ok(())
+        })
+    }
+
+    /// Update the signature of the task whose result is the result of the signature: the
+    /// last task of its chain if it has one, or the task itself otherwise.
+    pub(crate) fn update_last<F>(&mut self, f: F) -> Result<(), ProtocolError>
+    where
+        F: FnOnce(&mut RawSignature) -> Result<(), ProtocolError>,
+    {
+        if let Some(chain) = self.options.get_mut("chain") {
+            let mut links: Vec<RawSignature> = serde_json::from_value(chain.clone())?;
+            // The chain is stored in reverse order.
+            if let Some(last) = links.first_mut() {
+                f(last)?;
+                *chain = serde_json::to_value(links)?;
+                return Ok(());
+            }
+        }
+        f(self)
+    }
+}
+
+/// Deserialize the signatures of an option like `link` or `link_error`, which Python allows
+/// to be either a single signature or a list of them.
+pub(crate) fn signature_list(value: Value) -> Result<Vec<RawSignature>, ProtocolError> {
+    match value {
+        Value::Null => Ok(vec![]),
+        Value::Array(_) => Ok(serde_json::from_value(value)?),
+        value => Ok(vec![serde_json::from_value(value)?]),
+    }
+}
+
+impl<T> TryFrom<Signature<T>> for RawSignature
+where
+    T: Task,
+{
+    type Error = ProtocolError;
+
+    fn try_from(signature: Signature<T>) -> Result<Self, Self::Error> {
+        let kwargs = match signature.params {
+            SignatureParams::Params(params) => match serde_json::to_value(params)? {
+                Value::Object(kwargs) => kwargs,
+                _ => return Err(ProtocolError::InvalidProperty("params".into())),
+            },
+            SignatureParams::Partial(kwargs) => kwargs,
+        };
+        let mut raw = RawSignature::new(T::NAME, vec![], kwargs);
+        raw.immutable = signature.immutable;
+
+        // Rust time limits are soft time limits, Python time limits are hard ones.
+        let options = &mut raw.options;
+        let mut set = |name: &str, value: Value| {
+            options.insert(name.into(), value);
+        };
+        if let Some(queue) = signature.queue {
+            set("queue", json!(queue));
+        }
+        if let Some(countdown) = signature.countdown {
+            set("countdown", json!(countdown));
+        } else if let Some(eta) = signature.eta {
+            set(
+                "eta",
+                json!(eta.to_rfc3339_opts(SecondsFormat::Micros, false)),
+            );
+        }
+        if let Some(expires_in) = signature.expires_in {
+            set("expires", json!(expires_in));
+        } else if let Some(expires) = signature.expires {
+            set(
+                "expires",
+                json!(expires.to_rfc3339_opts(SecondsFormat::Micros, false)),
+            );
+        }
+        if let Some(time_limit) = signature.options.time_limit {
+            set("soft_time_limit", json!(time_limit));
+        }
+        if let Some(hard_time_limit) = signature.options.hard_time_limit {
+            set("time_limit", json!(hard_time_limit));
+        }
+        if let Some(content_type) = signature.options.content_type {
+            set("serializer", json!(content_type.serializer()));
+        }
+        if !signature.chain.is_empty() {
+            // Like in task messages, the chain is stored in reverse order.
+            let chain: Vec<&RawSignature> = signature.chain.iter().rev().collect();
+            set("chain", json!(chain));
+        }
+        if !signature.callbacks.is_empty() {
+            set("link", json!(signature.callbacks));
+        }
+        if !signature.errbacks.is_empty() {
+            set("link_error", json!(signature.errbacks));
+        }
+        Ok(raw)
+    }
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The state of a task as recorded by a [`ResultBackend`](crate::backend::ResultBackend).
+///
+/// These mirror the [built-in states](https://docs.celeryproject.org/en/stable/reference/celery.states.html)
+/// of Python Celery and are serialized the same way (e.g. `"SUCCESS"`), so results stored by
+/// Rust workers can be read by Python producers and vice versa.
+#[derive(Eq, PartialEq, Debug, Clone, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TaskState {
+    /// The task is waiting for execution or unknown.
+    #[default]
+    Pending,
+
+    /// The task was received by a worker.
+    Received,
+
+    /// The task was started by a worker.
+    Started,
+
+    /// The task executed successfully.
+    Success,
+
+    /// The task raised an error and won't be retried.
+    Failure,
+
+    /// The task was revoked.
+    Revoked,
+
+    /// The task was rejected by a worker.
+    Rejected,
+
+    /// The task is waiting to be retried.
+    Retry,
+
+    /// The task was ignored.
+    Ignored,
+
+    /// A custom state, such as one set through progress updates.
+    Custom(String),
+}
+
+impl TaskState {
+    /// Whether the task has finished executing, successfully or not.
+    pub fn is_ready(&self) -> bool {
+        matches!(
+            self,
+            TaskState::Success | TaskState::Failure | TaskState::Revoked
+        )
+    }
+
+    /// Whether the task has finished executing without success.
+    pub fn is_exception(&self) -> bool {
+        matches!(self, TaskState::Failure | TaskState::Revoked)
+    }
+
+    /// The name of the state as used on the wire.
+    pub fn as_str(&self) -> &str {
+        match self {
+            TaskState::Pending => "PENDING",
+            TaskState::Received => "RECEIVED",
+            TaskState::Started => "STARTED",
+            TaskState::Success => "SUCCESS",
+            TaskState::Failure => "FAILURE",
+            TaskState::Revoked => "REVOKED",
+            TaskState::Rejected => "REJECTED",
+            TaskState::Retry => "RETRY",
+            TaskState::Ignored => "IGNORED",
+            TaskState::Custom(state) => state,
+        }
+    }
+}
+
+impl fmt::Display for TaskState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl From<&str> for TaskState {
+    fn from(state: &str) -> Self {
+        match state {
+            "PENDING" => TaskState::Pending,
+            "RECEIVED" => TaskState::Received,
+            "STARTED" => TaskState::Started,
+            "SUCCESS" => TaskState::Success,
+            "FAILURE" => TaskState::Failure,
+            "REVOKED" => TaskState::Revoked,
+            "REJECTED" => TaskState::Rejected,
+            "RETRY" => TaskState::Retry,
+            "IGNORED" => TaskState::Ignored,
+            _ => TaskState::Custom(state.into()),
+        }
+    }
+}
+
+impl From<String> for TaskState {
+    fn from(state: String) -> Self {
+        TaskState::from(state.as_str())
+    }
+}
+
+impl From<TaskState> for String {
+    fn from(state: TaskState) -> Self {
+        match state {
+            TaskState::Custom(state) => state,
+            _ => state.as_str().into(),
+        }
+    }
+}
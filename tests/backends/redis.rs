@@ -0,0 +1,183 @@
+use anyhow::Result;
+use celery::backend::{
+    GroupMeta, RedisBackendBuilder, ResultBackend, ResultBackendBuilder, TaskMeta,
+};
+use celery::error::TaskError;
+use celery::task::TaskState;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn redis_addr() -> String {
+    std::env::var("REDIS_ADDR").unwrap_or_else(|_| "redis://127.0.0.1:6379/".into())
+}
+
+#[tokio::test]
+async fn test_redis_backend() -> Result<()> {
+    let backend = RedisBackendBuilder::new(&redis_addr()).build(2).await?;
+
+    // Nothing stored yet.
+    let meta = backend.get_task_meta("redis-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Pending);
+
+    backend
+        .store_result(&TaskMeta::success("redis-backend-test", json!(3)))
+        .await?;
+    let meta = backend.get_task_meta("redis-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!(3));
+
+    backend
+        .store_result(&TaskMeta::failure(
+            "redis-backend-test",
+            &TaskError::ExpectedError("oops".into()),
+        ))
+        .await?;
+    let meta = backend.get_task_meta("redis-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Failure);
+    assert!(matches!(meta.error(), Some(TaskError::ExpectedError(_))));
+
+    backend.forget("redis-backend-test").await?;
+    let meta = backend.get_task_meta("redis-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Pending);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redis_backend_wait_for() -> Result<()> {
+    let backend = Arc::new(RedisBackendBuilder::new(&redis_addr()).build(2).await?);
+    let task_id = uuid::Uuid::new_v4().to_string();
+
+    let worker = backend.clone();
+    let worker_task_id = task_id.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        worker
+            .store_result(&TaskMeta::success(&worker_task_id, json!(3)))
+            .await
+            .unwrap();
+    });
+
+    // The interval is way longer than the timeout, so this only succeeds if the result is
+    // published.
+    let meta = backend
+        .wait_for(
+            &task_id,
+            Some(Duration::from_secs(5)),
+            Duration::from_secs(3600),
+        )
+        .await?;
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!(3));
+
+    backend.forget(&task_id).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redis_backend_concurrent_waiters() -> Result<()> {
+    let backend = Arc::new(RedisBackendBuilder::new(&redis_addr()).build(2).await?);
+    let task_ids = (0..20)
+        .map(|_| uuid::Uuid::new_v4().to_string())
+        .collect::<Vec<_>>();
+
+    // Five waiters for each task.
+    let waiters = task_ids
+        .iter()
+        .cycle()
+        .take(100)
+        .cloned()
+        .map(|task_id| {
+            let backend = backend.clone();
+            tokio::spawn(async move {
+                backend
+                    .wait_for(
+                        &task_id,
+                        Some(Duration::from_secs(5)),
+                        Duration::from_secs(3600),
+                    )
+                    .await
+                    .map(|meta| (task_id, meta))
+            })
+        })
+        .collect::<Vec<_>>();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The waiters share a single subscriber connection.
+    let mut connection = redis::Client::open(redis_addr())?
+        .get_async_connection()
+        .await?;
+    let patterns: usize = redis::cmd("PUBSUB")
+        .arg("NUMPAT")
+        .query_async(&mut connection)
+        .await?;
+    assert_eq!(patterns, 1);
+
+    for (i, task_id) in task_ids.iter().enumerate() {
+        backend
+            .store_result(&TaskMeta::success(task_id, json!(i)))
+            .await?;
+    }
+    for waiter in waiters {
+        let (task_id, meta) = waiter.await??;
+        let i = task_ids.iter().position(|id| *id == task_id).unwrap();
+        assert_eq!(meta.status, TaskState::Success);
+        assert_eq!(meta.result, json!(i));
+    }
+
+    for task_id in &task_ids {
+        backend.forget(task_id).await?;
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redis_backend_groups() -> Result<()> {
+    let backend = RedisBackendBuilder::new(&redis_addr()).build(2).await?;
+    let group_id = uuid::Uuid::new_v4().to_string();
+    assert!(backend.restore_group(&group_id).await?.is_none());
+
+    let meta = GroupMeta::new(
+        &group_id,
+        json!([[group_id, null], [[["aaa", null], null]]]),
+    );
+    backend.save_group(&meta).await?;
+    let restored = backend.restore_group(&group_id).await?.unwrap();
+    assert_eq!(restored.group_id, group_id);
+    assert_eq!(restored.result, meta.result);
+
+    backend.delete_group(&group_id).await?;
+    assert!(backend.restore_group(&group_id).await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redis_backend_chord() -> Result<()> {
+    let backend = RedisBackendBuilder::new(&redis_addr()).build(2).await?;
+    let group_id = uuid::Uuid::new_v4().to_string();
+    assert!(backend.supports_native_join());
+    backend.apply_chord(&group_id, 2).await?;
+
+    let meta = TaskMeta::success("redis-chord-bbb", json!(2));
+    assert!(backend
+        .on_chord_part_return(&group_id, Some(1), &meta)
+        .await?
+        .is_none());
+    let meta = TaskMeta::success("redis-chord-aaa", json!(1));
+    let results = backend
+        .on_chord_part_return(&group_id, Some(0), &meta)
+        .await?
+        .unwrap();
+    assert_eq!(
+        results.iter().map(|meta| &meta.result).collect::<Vec<_>>(),
+        vec![&json!(1), &json!(2)]
+    );
+    assert_eq!(results[0].task_id, "redis-chord-aaa");
+    assert_eq!(results[0].status, TaskState::Success);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use anyhow::Result;
+use celery::backend::{ResultBackend, ResultBackendBuilder, RpcBackendBuilder, TaskMeta};
+use celery::task::TaskState;
+use serde_json::json;
+use tokio::time::Duration;
+
+fn amqp_addr() -> String {
+    std::env::var("AMQP_ADDR").unwrap_or_else(|_| "amqp://127.0.0.1:5672//".into())
+}
+
+#[tokio::test]
+async fn test_rpc_backend() -> Result<()> {
+    let backend = RpcBackendBuilder::new("rpc://")
+        .broker_url(&amqp_addr())
+        .build(2)
+        .await?;
+    let reply_to = backend.reply_to().await?.unwrap();
+    // The reply queue is only declared once.
+    assert_eq!(backend.reply_to().await?, Some(reply_to.clone()));
+
+    let meta = backend.get_task_meta("rpc-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Pending);
+
+    // This is what a worker would do.
+    let mut meta = TaskMeta::success("rpc-backend-test", json!(3));
+    meta.reply_to = Some(reply_to);
+    backend.store_result(&meta).await?;
+
+    let meta = backend
+        .wait_for(
+            "rpc-backend-test",
+            Some(Duration::from_secs(5)),
+            Duration::from_millis(100),
+        )
+        .await?;
+    assert_eq!(meta.status, TaskState::Success);
+    assert_eq!(meta.result, json!(3));
+
+    backend.forget("rpc-backend-test").await?;
+    let meta = backend.get_task_meta("rpc-backend-test").await?;
+    assert_eq!(meta.status, TaskState::Pending);
+
+    Ok(())
+}
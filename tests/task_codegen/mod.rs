@@ -1,5 +1,9 @@
+use celery::canvas::{MapTask, StarmapTask};
 use celery::error::TaskError;
-use celery::task::{Task, TaskResult};
+use celery::protocol::Message;
+use celery::task::{RateLimit, RawSignature, Task, TaskResult};
+use serde_json::json;
+use std::convert::TryFrom;
 
 #[celery::task(name = "add")]
 fn add(x: i32, y: i32) -> TaskResult<i32> {
@@ -16,6 +20,51 @@ fn test_add_arg_names() {
     assert_eq!(add::ARGS, &["x", "y"]);
 }
 
+#[test]
+fn test_add_partial() {
+    let signature = RawSignature::try_from(add::partial(2)).unwrap();
+    assert_eq!(signature.task, "add");
+    assert!(signature.args.is_empty());
+    assert_eq!(signature.kwargs, *json!({"y": 2}).as_object().unwrap());
+    assert_eq!(add::partial(2).upstream_param(), Some("x"));
+}
+
+#[test]
+fn test_add_immutable() {
+    let signature = add::new(1, 2).immutable();
+    assert_eq!(signature.upstream_param(), None);
+    let signature = RawSignature::try_from(signature).unwrap();
+    assert!(signature.immutable);
+    assert!(signature.args.is_empty());
+}
+
+#[test]
+fn test_add_chain() {
+    let message = Message::try_from(celery::chain![add::new(1, 2), add::partial(3)]).unwrap();
+    let (_, embed) = message.body::<add>().unwrap().parts();
+    let chain = embed.chain.unwrap();
+    assert_eq!(chain.len(), 1);
+    assert_eq!(chain[0], RawSignature::try_from(add::partial(3)).unwrap());
+}
+
+#[test]
+fn test_add_starmap() {
+    let message = Message::try_from(add::starmap(vec![(1, 2), (3, 4)])).unwrap();
+    assert_eq!(message.headers.task, "celery.starmap");
+    let (params, _) = message.body::<StarmapTask>().unwrap().parts();
+    assert_eq!(params.task.task, "add");
+    assert_eq!(
+        params.it,
+        vec![vec![json!(1), json!(2)], vec![json!(3), json!(4)]]
+    );
+}
+
+#[test]
+fn test_add_chunks() {
+    let group = add::chunks((0..5).map(|i| (i, i)), 2);
+    assert_eq!(group.len(), 3);
+}
+
 #[celery::task]
 fn add_auto_name(x: i32, y: i32) -> TaskResult<i32> {
     Ok(x + y)
@@ -33,7 +82,10 @@ fn test_auto_name() {
     min_retry_delay = 0,
     max_retry_delay = 60,
     retry_for_unexpected = false,
-    acks_late = true
+    acks_late = true,
+    ignore_result = true,
+    track_started = true,
+    rate_limit = "100/m"
 )]
 fn task_with_options() -> TaskResult<String> {
     Ok("it worked!".into())
@@ -51,6 +103,15 @@ fn test_task_options() {
         Some(false)
     );
     assert_eq!(task_with_options::DEFAULTS.acks_late, Some(true));
+    assert_eq!(task_with_options::DEFAULTS.ignore_result, Some(true));
+    assert_eq!(task_with_options::DEFAULTS.track_started, Some(true));
+    assert_eq!(
+        task_with_options::DEFAULTS.rate_limit,
+        Some(RateLimit {
+            tasks: 100.0,
+            period: 60
+        })
+    );
 }
 
 #[celery::task(bind = true)]
@@ -64,6 +125,36 @@ fn bound_task_with_other_params(t: &Self, default_time_limit: u32) -> TaskResult
     Ok(t.time_limit().unwrap_or(default_time_limit))
 }
 
+#[test]
+fn test_bound_task_map() {
+    let message = Message::try_from(bound_task_with_other_params::map(vec![1, 2])).unwrap();
+    assert_eq!(message.headers.task, "celery.map");
+    let (params, _) = message.body::<MapTask>().unwrap().parts();
+    assert_eq!(params.task.task, "bound_task_with_other_params");
+    assert_eq!(params.it, vec![json!(1), json!(2)]);
+}
+
+#[celery::task(blocking = true, time_limit = 10)]
+fn blocking_task(x: u64, y: u64) -> TaskResult<u64> {
+    std::thread::sleep(std::time::Duration::from_millis(1));
+    Ok(x + y)
+}
+
+#[celery::task(bind = true, blocking = true)]
+fn bound_blocking_task(t: &Self, default_time_limit: u32) -> TaskResult<u32> {
+    Ok(t.time_limit().unwrap_or(default_time_limit))
+}
+
+#[test]
+fn test_blocking_task() {
+    let message = Message::try_from(blocking_task::new(1, 2)).unwrap();
+    assert_eq!(message.headers.task, "blocking_task");
+    assert_eq!(blocking_task::DEFAULTS.time_limit, Some(10));
+
+    let message = Message::try_from(bound_blocking_task::new(1)).unwrap();
+    assert_eq!(message.headers.task, "bound_blocking_task");
+}
+
 // This didn't work before since Task::run took a reference to self
 // instead of consuming self, so it was like
 //